@@ -6,8 +6,11 @@ fn main() -> Result<()> {
   config.type_attribute(".sync_server.v1", "#[serde(rename_all = \"camelCase\")]");
   config.type_attribute(".pool.v1.PoolFileInfo", "#[derive(serde::Serialize, serde::Deserialize)]");
   config.type_attribute(".pool.v1.PoolFileInfo", "#[serde(rename_all = \"camelCase\")]");
+  config.field_attribute(".pool.v1.PoolFileInfo.file_hash", "#[serde(default)]");
   config.type_attribute(".pool.v1.PoolFileSeeders", "#[derive(serde::Serialize, serde::Deserialize)]");
   config.type_attribute(".pool.v1.PoolFileSeeders", "#[serde(rename_all = \"camelCase\")]");
+  config.type_attribute(".pool.v1.PoolFolderFileInfo", "#[derive(serde::Serialize, serde::Deserialize)]");
+  config.type_attribute(".pool.v1.PoolFolderFileInfo", "#[serde(rename_all = \"camelCase\")]");
   config.type_attribute(".pool.v1.PoolFolderInfo", "#[derive(serde::Serialize, serde::Deserialize)]");
   config.type_attribute(".pool.v1.PoolFolderInfo", "#[serde(rename_all = \"camelCase\")]");
   config.type_attribute(".pool.v1.PoolImageData", "#[derive(serde::Serialize, serde::Deserialize)]");
@@ -16,6 +19,8 @@ fn main() -> Result<()> {
   config.type_attribute(".pool.v1.PoolChunkRange", "#[serde(rename_all = \"camelCase\")]");
   config.type_attribute(".pool.v1.PoolMessage", "#[derive(serde::Serialize, serde::Deserialize)]");
   config.type_attribute(".pool.v1.PoolMessage", "#[serde(rename_all = \"camelCase\")]");
+  config.type_attribute(".pool.v1.PoolEphemeralMessage", "#[derive(serde::Serialize, serde::Deserialize)]");
+  config.type_attribute(".pool.v1.PoolEphemeralMessage", "#[serde(rename_all = \"camelCase\")]");
   config.bytes(&["."]);
   config.compile_protos(&["src/sync_server.v1.proto", "src/pool.v1.proto"], &["src/"])?;
   tauri_build::build();
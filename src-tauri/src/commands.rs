@@ -1,5 +1,5 @@
 use crate::{
-    events::{latest_pool_messages_event, init_app_event, refresh_auth_token_event}, poolpb::PoolFileInfo, POOL_MANAGER, STORE_MANAGER, ipc::IPCPoolMessageHistory, MESSAGES_DB, sspb::{PoolDeviceInfo, PoolUserInfo, PoolInfo},
+    events::{latest_pool_messages_event, init_app_event, refresh_auth_token_event}, poolpb::{PoolFileInfo, PoolFolderInfo}, POOL_MANAGER, STORE_MANAGER, ipc::{IPCPoolMessageHistory, IPCPoolMessageSearch, IPCPoolMessageThread, IPCPoolNodeConnectionStats, IPCSearchFilters}, MESSAGES_DB, sspb::{PoolDeviceInfo, PoolUserInfo, PoolInfo}, store::setting_store::{BandwidthLimits, IceServer, SyncServerSettings},
 };
 
 #[tauri::command]
@@ -41,8 +41,44 @@ pub async fn disconnect_from_pool(pool_id: String) {
 }
 
 #[tauri::command]
-pub async fn send_text_message(pool_id: String, text: String) {
-    POOL_MANAGER.send_text_message(&pool_id, text).await;
+pub async fn send_text_message(pool_id: String, text: String, reply_msg_id: Option<String>) {
+    POOL_MANAGER.send_text_message(&pool_id, text, reply_msg_id).await;
+}
+
+#[tauri::command]
+pub async fn edit_message(pool_id: String, msg_id: String, text: String) {
+    POOL_MANAGER.edit_message(&pool_id, msg_id, text).await;
+}
+
+#[tauri::command]
+pub async fn delete_message(pool_id: String, msg_id: String) {
+    POOL_MANAGER.delete_message(&pool_id, msg_id).await;
+}
+
+#[tauri::command]
+pub async fn send_reaction(pool_id: String, msg_id: String, emoji: String, remove: bool) {
+    POOL_MANAGER.send_reaction(&pool_id, msg_id, emoji, remove).await;
+}
+
+#[tauri::command]
+pub async fn send_read_receipt(pool_id: String, msg_id: String) {
+    POOL_MANAGER.send_read_receipt(&pool_id, msg_id).await;
+}
+
+// Opens the pool's messages db, so only requested for pools that are shown
+#[tauri::command]
+pub fn request_unread_count(pool_id: String) -> usize {
+    MESSAGES_DB.unread_count(&pool_id, &STORE_MANAGER.user_id())
+}
+
+#[tauri::command]
+pub async fn send_typing_indicator(pool_id: String, is_typing: bool) {
+    POOL_MANAGER.send_typing_indicator(&pool_id, is_typing).await;
+}
+
+#[tauri::command]
+pub async fn send_presence(pool_id: String, is_idle: bool) {
+    POOL_MANAGER.send_presence(&pool_id, is_idle).await;
 }
 
 #[tauri::command]
@@ -55,6 +91,28 @@ pub async fn add_image_offer(pool_id: String, file_path: String) {
     POOL_MANAGER.add_image_offer(&pool_id, file_path).await;
 }
 
+#[tauri::command]
+pub async fn add_folder_offer(pool_id: String, folder_path: String) {
+    POOL_MANAGER.add_folder_offer(&pool_id, folder_path).await;
+}
+
+#[tauri::command]
+pub async fn retract_folder_offer(pool_id: String, folder_id: String) {
+    POOL_MANAGER.retract_folder_offer(&pool_id, folder_id).await;
+}
+
+#[tauri::command]
+pub async fn download_folder(
+    pool_id: String,
+    folder_info: PoolFolderInfo,
+    dir_path: String,
+    file_ids: Vec<String>,
+) {
+    POOL_MANAGER
+        .download_folder(&pool_id, folder_info, dir_path, file_ids)
+        .await;
+}
+
 #[tauri::command]
 pub async fn download_file(pool_id: String, file_info: PoolFileInfo, dir_path: String) {
     POOL_MANAGER
@@ -72,6 +130,94 @@ pub async fn remove_file_download(pool_id: String, file_id: String) {
     POOL_MANAGER.remove_file_download(&pool_id, file_id).await;
 }
 
+#[tauri::command]
+pub async fn pause_file_download(pool_id: String, file_id: String) -> bool {
+    POOL_MANAGER.pause_file_download(&pool_id, file_id).await
+}
+
+#[tauri::command]
+pub async fn resume_file_download(pool_id: String, file_id: String) -> bool {
+    POOL_MANAGER.resume_file_download(&pool_id, file_id).await
+}
+
+#[tauri::command]
+pub async fn set_file_download_priority(pool_id: String, file_id: String, priority: i32) -> bool {
+    POOL_MANAGER
+        .set_file_download_priority(&pool_id, file_id, priority)
+        .await
+}
+
+#[tauri::command]
+pub async fn stop_file_upload(
+    pool_id: String,
+    file_id: String,
+    requesting_node_id: String,
+) -> bool {
+    POOL_MANAGER
+        .stop_file_upload(&pool_id, file_id, requesting_node_id)
+        .await
+}
+
+#[tauri::command]
+pub fn request_max_concurrent_downloads() -> usize {
+    STORE_MANAGER.max_concurrent_downloads()
+}
+
+#[tauri::command]
+pub async fn set_max_concurrent_downloads(max_concurrent_downloads: usize) {
+    STORE_MANAGER.set_max_concurrent_downloads(max_concurrent_downloads);
+    POOL_MANAGER.start_queued_file_downloads().await;
+}
+
+#[tauri::command]
+pub fn request_bandwidth_limits() -> BandwidthLimits {
+    STORE_MANAGER.bandwidth_limits()
+}
+
+#[tauri::command]
+pub async fn set_bandwidth_limits(bandwidth_limits: BandwidthLimits) {
+    STORE_MANAGER.set_bandwidth_limits(bandwidth_limits);
+    POOL_MANAGER.set_bandwidth_limits(bandwidth_limits).await;
+}
+
+#[tauri::command]
+pub fn request_ice_servers() -> Vec<IceServer> {
+    STORE_MANAGER.ice_servers()
+}
+
+// Used for connections made from then on
+#[tauri::command]
+pub fn set_ice_servers(ice_servers: Vec<IceServer>) {
+    STORE_MANAGER.set_ice_servers(ice_servers);
+}
+
+#[tauri::command]
+pub fn request_sync_server_settings() -> SyncServerSettings {
+    STORE_MANAGER.sync_server_settings()
+}
+
+// Only saved if the sync server answers with a matching version, pools use it from their next connection
+#[tauri::command]
+pub async fn set_sync_server_settings(sync_server_settings: SyncServerSettings) -> bool {
+    match POOL_MANAGER
+        .sync_server_version(&sync_server_settings)
+        .await
+    {
+        Some(version) => {
+            log::info!("set_sync_server_settings : version {}", version);
+            STORE_MANAGER.set_sync_server_settings(sync_server_settings);
+            true
+        }
+        None => false,
+    }
+}
+
+// Also sent with the state updates every CONNECTION_STATS_INTERVAL
+#[tauri::command]
+pub async fn pool_connection_stats(pool_id: String) -> Vec<IPCPoolNodeConnectionStats> {
+    POOL_MANAGER.pool_connection_stats(&pool_id).await
+}
+
 #[tauri::command]
 pub async fn request_message_history(pool_id: String, msg_id: String, chunk_number: u64) -> IPCPoolMessageHistory {
     if msg_id.is_empty() {
@@ -79,4 +225,19 @@ pub async fn request_message_history(pool_id: String, msg_id: String, chunk_numb
     } else {
         MESSAGES_DB.messages_history_chunk_by_id(&pool_id, &msg_id)
     }
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub async fn request_message_history_by_date(pool_id: String, created: u64) -> IPCPoolMessageHistory {
+    MESSAGES_DB.messages_history_chunk_by_date(&pool_id, created)
+}
+
+#[tauri::command]
+pub async fn request_thread(pool_id: String, msg_id: String) -> IPCPoolMessageThread {
+    MESSAGES_DB.thread(&pool_id, &msg_id)
+}
+
+#[tauri::command]
+pub async fn search_messages(pool_id: String, query: String, filters: Option<IPCSearchFilters>) -> IPCPoolMessageSearch {
+    MESSAGES_DB.search_messages(&pool_id, &query, filters.unwrap_or_default())
+}
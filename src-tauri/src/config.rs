@@ -13,6 +13,9 @@ pub const BUFFERED_AMOUNT_LOW_THRESHOLD: usize = MAX_DC_BUFFER_SIZE - DC_REFILL_
 pub const DC_INIT_BUFFER_MAX_FILL_RATE_TIMEOUT: u64 = Duration::from_secs(1).as_millis() as u64;
 pub const DC_INIT_BUFFER_MIN_FILL_RATE_TIMEOUT: u64 = Duration::from_millis(1).as_millis() as u64;
 
+pub const DEFAULT_STUN_SERVER_URL: &'static str = "stun:stun.l.google.com:19302";
+pub const CONNECTION_STATS_INTERVAL: Duration = Duration::from_secs(2);
+
 pub const CACHE_CHUNK_SIZE: usize = 1 * 1024 * 1024;
 pub const CACHE_CHUNK_TO_CHUNK_SIZE_FACTOR: usize = CACHE_CHUNK_SIZE / CHUNK_SIZE;
 
@@ -22,6 +25,7 @@ pub const CACHE_FILE_SIZE: usize = 256 * 1024 * 1024;
 pub const MAX_CACHE_CHUNKS_AMOUNT: usize = CACHE_FILE_SIZE / CACHE_CHUNK_SIZE; 
 
 pub const MAX_TEMP_FILE_SIZE: u64 = 16 * 1024 * 1024;
+pub const MAX_FOLDER_OFFER_FILES: usize = 1000;
 // pub const MAX_TEMP_FILES_PER_POOL: usize = 10;
 pub const MAX_TEMP_FILES_SIZE_PER_POOL: u64 = 128 * 1024 * 1024;
 // pub const TOTAL_TEMP_FILE_THRESHOLD: u64 = 512 * 1024 * 1024;
@@ -35,10 +39,33 @@ pub const CHUNKS_MISSING_SEND_INTERVAL_IN_SEC: usize = 5;
 pub const CHUNKS_MISSING_SEND_INTERVAL: Duration = Duration::from_secs(CHUNKS_MISSING_SEND_INTERVAL_IN_SEC as u64);
 pub const MAX_CHUNKS_MISSING_RETRY: usize = 3;
 pub const MAX_POLL_COUNT_BEFORE_SEND: usize = CHUNKS_MISSING_SEND_INTERVAL_IN_SEC / CHUNKS_MISSING_POLLING_INTERVAL_IN_SEC; 
+pub const MAX_SWARM_SEEDERS: usize = 4;
+pub const SWARM_REBALANCE_INTERVAL: Duration = Duration::from_secs(2);
+pub const SWARM_STALL_TIMEOUT: Duration = CHUNKS_MISSING_SEND_INTERVAL;
+pub const SWARM_MIN_TAKE_OVER_CHUNKS: u64 = CACHE_CHUNK_TO_CHUNK_SIZE_FACTOR as u64;
+pub const FILE_DOWNLOAD_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+pub const TRANSFER_RATE_WINDOW: Duration = Duration::from_secs(5);
+pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 3;
+pub const BANDWIDTH_LIMIT_BURST: Duration = Duration::from_secs(2);
+pub const MAX_MEDIA_RANGE_SIZE: u64 = 4 * 1024 * 1024;
 
 pub const MESSAGES_DB_CHUNK_SIZE: u64 = 16 * 1024;
 pub const RECEIVED_MESSAGES_SIZE: usize = 100;
 pub const LATEST_MESSAGES_SIZE: usize = 50;
+pub const SEARCH_RESULTS_LIMIT: usize = 50;
+pub const HISTORY_PAGE_SIZE: usize = 100;
+pub const MAX_HISTORY_BACKFILL_MESSAGES: usize = 50 * HISTORY_PAGE_SIZE;
+pub const LOGICAL_CLOCK_COUNTER_BITS: u32 = 16;
+pub const MAX_CLOCK_DRIFT: Duration = Duration::from_secs(60);
+pub const REPLY_SNIPPET_LENGTH: usize = 100;
+pub const MAX_HELD_AMENDMENTS: usize = 1000;
+
+pub const EPHEMERAL_ID_LENGTH: usize = 10;
+pub const EPHEMERAL_SEND_INTERVAL: Duration = Duration::from_secs(3);
+pub const EPHEMERAL_RECEIVE_INTERVAL: Duration = Duration::from_millis(500);
+
+pub const READ_RECEIPT_INTERVAL: Duration = Duration::from_secs(2);
+pub const UNREAD_COUNT_LIMIT: usize = 100;
 
 pub const MIN_MESSAGE_HIEGHT: u32 = 28;
 pub const MESSAGE_VIEWPORT_SIZE: u32 = 3;
@@ -50,6 +77,9 @@ pub const PREVIEW_IMAGE_DIMENSION: u32 = 10;
 
 pub const HEARTBEAT_INTERVAL_SECONDS: u64 = 30;
 pub const HEARTBEAT_TIMEOUT_SECONDS: u64 = 10;
+pub const SYNC_SERVER_RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+pub const SYNC_SERVER_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+pub const SYNC_SERVER_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub const SYNC_SERVER_TEST_DOMAIN: &'static str = "192.168.0.18:80";
 pub const SYNC_SERVER_PROD_DOMAIN: &'static str = "ec2-99-79-191-205.ca-central-1.compute.amazonaws.com";
@@ -59,24 +89,24 @@ pub const SYNC_SERVER_DOMAIN: &'static str = if PRODUCTION_MODE { SYNC_SERVER_PR
 
 pub const MAIN_TEST_POOL_ID: &'static str = "MAIN_TEST_POOL_ID";
 
-fn sync_server_ws_host(path: String) -> String {
+fn sync_server_ws_host(domain: &str, is_secure: bool, path: String) -> String {
     format!("ws{}://{}{}",
-        if SYNC_SERVER_IS_SECURE { "s" } else { "" },
-        SYNC_SERVER_DOMAIN,
+        if is_secure { "s" } else { "" },
+        domain,
         path
     )
 }
 
-fn sync_server_api_host(path: String) -> String {
+fn sync_server_api_host(domain: &str, is_secure: bool, path: String) -> String {
     format!("http{}://{}{}",
-        if SYNC_SERVER_IS_SECURE { "s" } else { "" },
-        SYNC_SERVER_DOMAIN,
+        if is_secure { "s" } else { "" },
+        domain,
         path
     )
 }
 
-pub fn sync_server_connect_endpoint(pool_id: &str, device_id: String) -> String {
-    sync_server_ws_host(format!("/ss/{}/connect?poolid={}&deviceid={}&test={}",
+pub fn sync_server_connect_endpoint(domain: &str, is_secure: bool, pool_id: &str, device_id: String) -> String {
+    sync_server_ws_host(domain, is_secure, format!("/ss/{}/connect?poolid={}&deviceid={}&test={}",
         SYNC_SERVER_VERSION,
         pool_id,
         device_id,
@@ -84,6 +114,6 @@ pub fn sync_server_connect_endpoint(pool_id: &str, device_id: String) -> String
     ))
 }
 
-pub fn sync_server_api_get_version_endpoint() -> String {
-    sync_server_api_host(format!("/ss/version"))
+pub fn sync_server_api_get_version_endpoint(domain: &str, is_secure: bool) -> String {
+    sync_server_api_host(domain, is_secure, format!("/ss/version"))
 }
\ No newline at end of file
@@ -0,0 +1,221 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use bytes::Buf;
+use prost::Message;
+
+use crate::{config::MAX_HELD_AMENDMENTS, poolpb::PoolMessage};
+
+use super::messages_db::{amendment_target_msg_id, message_order};
+
+const AMENDMENTS_FILE_EXTENSION: &'static str = ".amendments.db";
+
+// Append only log of edits and deletes, kept so they can be sent on to nodes that missed them.
+// Only amendments made by their target's author are logged, ones whose target hasn't arrived
+// yet are held in memory until it does
+pub(super) struct AmendmentsLog {
+    amendments_file: File,
+    amendments: HashMap<String, Vec<PoolMessage>>, // target msg_id -> amendments
+    amendment_msg_ids: HashSet<String>,
+    ordered: Vec<(u64, String, String)>, // (order, msg_id, target msg_id), sorted
+    held: HashMap<String, Vec<PoolMessage>>, // target msg_id -> amendments
+    held_order: VecDeque<(String, String)>, // (target msg_id, msg_id), oldest first
+}
+
+impl AmendmentsLog {
+    pub(super) fn open(db_path: PathBuf, pool_id: &String) -> Option<Self> {
+        let mut path = db_path;
+        path.push(format!("{}{}", pool_id, AMENDMENTS_FILE_EXTENSION));
+
+        let mut amendments_file = File::options()
+            .write(true)
+            .read(true)
+            .create(true)
+            .open(path)
+            .ok()?;
+
+        let mut buf = Vec::new();
+        amendments_file.read_to_end(&mut buf).ok()?;
+
+        let mut amendments_log = AmendmentsLog {
+            amendments_file,
+            amendments: HashMap::new(),
+            amendment_msg_ids: HashSet::new(),
+            ordered: Vec::new(),
+            held: HashMap::new(),
+            held_order: VecDeque::new(),
+        };
+
+        // Drop a partially written amendment at the end
+        let file_len = buf.len();
+        let mut buf = buf.as_slice();
+        let mut valid_len = 0;
+        while buf.has_remaining() {
+            match PoolMessage::decode_length_delimited(&mut buf) {
+                Ok(msg) if amendment_target_msg_id(&msg).is_some() => {
+                    valid_len = file_len - buf.remaining();
+                    amendments_log.insert_amendment(msg);
+                }
+                _ => break,
+            }
+        }
+
+        if valid_len != file_len {
+            log::warn!(
+                "AmendmentsLog::open : truncating from {} to {}",
+                file_len,
+                valid_len
+            );
+            amendments_log
+                .amendments_file
+                .set_len(valid_len as u64)
+                .ok()?;
+        }
+
+        Some(amendments_log)
+    }
+
+    // Returns false if the amendment already exists, is invalid or couldn't be written
+    pub(super) fn add_amendment(&mut self, amendment: PoolMessage) -> bool {
+        if amendment_target_msg_id(&amendment).is_none()
+            || self.amendment_msg_ids.contains(&amendment.msg_id)
+        {
+            return false;
+        }
+
+        let written = self.amendments_file.seek(SeekFrom::End(0)).and_then(|_| {
+            self.amendments_file
+                .write_all(&amendment.encode_length_delimited_to_vec())
+        });
+        if let Err(err) = written {
+            log::error!("AmendmentsLog::add_amendment : {}", err);
+            return false;
+        }
+        self.insert_amendment(amendment);
+
+        true
+    }
+
+    pub(super) fn contains(&self, msg_id: &String) -> bool {
+        self.amendment_msg_ids.contains(msg_id)
+    }
+
+    // Kept until the target arrives, the oldest are dropped past MAX_HELD_AMENDMENTS
+    pub(super) fn hold_amendment(&mut self, amendment: PoolMessage) {
+        let target_msg_id = match amendment_target_msg_id(&amendment) {
+            Some(target_msg_id) => target_msg_id.clone(),
+            None => return,
+        };
+        if self.amendment_msg_ids.contains(&amendment.msg_id) {
+            return;
+        }
+
+        let held = self.held.entry(target_msg_id.clone()).or_default();
+        if held.iter().any(|held| held.msg_id == amendment.msg_id) {
+            return;
+        }
+        self.held_order
+            .push_back((target_msg_id, amendment.msg_id.clone()));
+        held.push(amendment);
+
+        if self.held_order.len() > MAX_HELD_AMENDMENTS {
+            if let Some((target_msg_id, msg_id)) = self.held_order.pop_front() {
+                if let Some(held) = self.held.get_mut(&target_msg_id) {
+                    held.retain(|held| held.msg_id != msg_id);
+                    if held.is_empty() {
+                        self.held.remove(&target_msg_id);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(super) fn take_held_amendments(&mut self, target_msg_id: &String) -> Vec<PoolMessage> {
+        match self.held.remove(target_msg_id) {
+            Some(held) => {
+                self.held_order
+                    .retain(|(held_target_msg_id, _)| held_target_msg_id != target_msg_id);
+                held
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub(super) fn amendments(&self, target_msg_id: &String) -> &[PoolMessage] {
+        match self.amendments.get(target_msg_id) {
+            Some(amendments) => amendments,
+            None => &[],
+        }
+    }
+
+    pub(super) fn amendment_messages(&self, msgs: &[PoolMessage]) -> Vec<PoolMessage> {
+        let mut amendment_messages = Vec::new();
+        for msg in msgs {
+            amendment_messages.extend(self.amendments(&msg.msg_id).iter().cloned());
+        }
+        amendment_messages
+    }
+
+    // The newest amendments up to limit, in order
+    pub(super) fn latest_amendments(&self, limit: usize) -> Vec<PoolMessage> {
+        let skip = self.ordered.len().saturating_sub(limit);
+        self.ordered
+            .iter()
+            .skip(skip)
+            .filter_map(|(_, msg_id, target_msg_id)| {
+                self.amendments
+                    .get(target_msg_id)?
+                    .iter()
+                    .find(|amendment| &amendment.msg_id == msg_id)
+                    .cloned()
+            })
+            .collect()
+    }
+
+    // Amendments made in [after_created, before_created), whatever their target's age
+    pub(super) fn amendments_between(
+        &self,
+        after_created: u64,
+        before_created: u64,
+        limit: usize,
+    ) -> Vec<PoolMessage> {
+        let mut amendment_messages = Vec::new();
+        for amendments in self.amendments.values() {
+            for amendment in amendments {
+                if amendment.created >= after_created
+                    && (before_created == 0 || amendment.created < before_created)
+                {
+                    amendment_messages.push(amendment.clone());
+                }
+            }
+        }
+        amendment_messages.sort_by_key(|amendment| message_order(amendment));
+        amendment_messages.truncate(limit);
+        amendment_messages
+    }
+
+    fn insert_amendment(&mut self, amendment: PoolMessage) {
+        let target_msg_id = match amendment_target_msg_id(&amendment) {
+            Some(target_msg_id) => target_msg_id.clone(),
+            None => return,
+        };
+
+        let entry = (
+            message_order(&amendment),
+            amendment.msg_id.clone(),
+            target_msg_id.clone(),
+        );
+        let position = self.ordered.partition_point(|ordered| ordered < &entry);
+        self.ordered.insert(position, entry);
+
+        self.amendment_msg_ids.insert(amendment.msg_id.clone());
+        self.amendments
+            .entry(target_msg_id)
+            .or_default()
+            .push(amendment);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, io, path::PathBuf};
+
+use prost::Message;
+
+use crate::poolpb::PoolMessage;
+
+use super::{
+    index_file::{IndexFile, IndexRecord},
+    messages_db::{message_order, reply_msg_id},
+};
+
+const ID_INDEX_FILE_EXTENSION: &'static str = ".ids.db";
+
+#[derive(Clone, PartialEq, Message)]
+struct IdIndexRecord {
+    #[prost(string, tag = "1")]
+    msg_id: String,
+    #[prost(uint64, tag = "2")]
+    chunk_number: u64,
+    #[prost(uint64, tag = "3")]
+    order: u64,
+    #[prost(string, tag = "4")]
+    reply_msg_id: String,
+}
+
+impl IndexRecord for IdIndexRecord {
+    fn chunk_number(&self) -> u64 {
+        self.chunk_number
+    }
+}
+
+// Maps msg_id -> chunk_number and keeps the running max message order of each chunk
+// so jumping to a message or a date doesn't need to decode the chunks.
+// Also maps parent msg_id -> replies for threads.
+pub(super) struct IdIndex {
+    index_file: IndexFile,
+    msg_chunks: HashMap<String, u64>, // msg_id -> chunk_number
+    chunks_max_order: Vec<u64>,       // chunk_number -> max order up to and including the chunk
+    replies: HashMap<String, Vec<String>>, // parent msg_id -> reply msg_ids
+}
+
+impl IdIndex {
+    pub(super) fn open(db_path: PathBuf, pool_id: &String) -> Option<Self> {
+        Some(IdIndex {
+            index_file: IndexFile::open(db_path, pool_id, ID_INDEX_FILE_EXTENSION)?,
+            msg_chunks: HashMap::new(),
+            chunks_max_order: Vec::new(),
+            replies: HashMap::new(),
+        })
+    }
+
+    // Loads the index, returns false if it is out of sync with the messages file
+    pub(super) fn load(&mut self, messages_file_size: u64) -> bool {
+        let records = match self
+            .index_file
+            .load_records::<IdIndexRecord>(messages_file_size)
+        {
+            Some(records) => records,
+            None => return false,
+        };
+
+        for record in records {
+            self.insert_record(record);
+        }
+
+        true
+    }
+
+    pub(super) fn clear(&mut self) -> io::Result<()> {
+        self.msg_chunks.clear();
+        self.chunks_max_order.clear();
+        self.replies.clear();
+        self.index_file.clear()
+    }
+
+    pub(super) fn truncate_from_chunk(&mut self, chunk_number: u64) -> io::Result<()> {
+        self.msg_chunks
+            .retain(|_, msg_chunk_number| *msg_chunk_number < chunk_number);
+        self.chunks_max_order.truncate(chunk_number as usize);
+
+        let msg_chunks = &self.msg_chunks;
+        self.replies.retain(|_, reply_msg_ids| {
+            reply_msg_ids.retain(|reply_msg_id| msg_chunks.contains_key(reply_msg_id));
+            !reply_msg_ids.is_empty()
+        });
+
+        self.index_file.truncate_from_chunk(chunk_number)
+    }
+
+    // The record is kept in memory even if it couldn't be written
+    pub(super) fn index_message(&mut self, chunk_number: u64, msg: &PoolMessage) -> io::Result<()> {
+        let record = IdIndexRecord {
+            msg_id: msg.msg_id.clone(),
+            chunk_number,
+            order: message_order(msg),
+            reply_msg_id: reply_msg_id(msg).cloned().unwrap_or_default(),
+        };
+
+        let written = self.index_file.append_record(&record);
+        self.insert_record(record);
+        written
+    }
+
+    pub(super) fn set_covered_size(&mut self, messages_file_size: u64) -> io::Result<()> {
+        self.index_file.set_covered_size(messages_file_size)
+    }
+
+    pub(super) fn chunk_number(&self, msg_id: &String) -> Option<u64> {
+        self.msg_chunks.get(msg_id).copied()
+    }
+
+    pub(super) fn replies(&self, msg_id: &String) -> Vec<String> {
+        self.replies.get(msg_id).cloned().unwrap_or_default()
+    }
+
+    pub(super) fn max_order(&self) -> Option<u64> {
+        self.chunks_max_order.last().copied()
+    }
+
+    // First chunk that may contain messages ordered at or after the given order
+    pub(super) fn chunk_number_by_order(&self, order: u64) -> Option<u64> {
+        if self.chunks_max_order.is_empty() {
+            return None;
+        }
+
+        let chunk_number = self
+            .chunks_max_order
+            .partition_point(|max_order| *max_order < order);
+
+        Some(chunk_number.min(self.chunks_max_order.len() - 1) as u64)
+    }
+
+    fn insert_record(&mut self, record: IdIndexRecord) {
+        let chunk_number = record.chunk_number as usize;
+        while self.chunks_max_order.len() <= chunk_number {
+            let prev_max_order = self.chunks_max_order.last().copied().unwrap_or(0);
+            self.chunks_max_order.push(prev_max_order);
+        }
+
+        let max_order = &mut self.chunks_max_order[chunk_number];
+        *max_order = (*max_order).max(record.order);
+
+        if !record.reply_msg_id.is_empty() {
+            self.replies
+                .entry(record.reply_msg_id)
+                .or_default()
+                .push(record.msg_id.clone());
+        }
+
+        self.msg_chunks.insert(record.msg_id, record.chunk_number);
+    }
+}
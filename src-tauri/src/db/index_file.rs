@@ -0,0 +1,136 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use bytes::Buf;
+use prost::Message;
+
+const INDEX_FILE_HEADER_SIZE: u64 = 8;
+
+pub(super) trait IndexRecord: Message + Default {
+    fn chunk_number(&self) -> u64;
+}
+
+// Append only file of length delimited records, with a header holding how much
+// of the messages file the records cover so stale indexes can be rebuilt.
+// Records are appended in chunk order so the index can be truncated from a chunk.
+pub(super) struct IndexFile {
+    file: File,
+    chunk_offsets: Vec<u64>, // chunk_number -> offset of the chunk's first record
+    end_offset: u64,
+    synced: bool, // false once a write failed
+}
+
+impl IndexFile {
+    pub(super) fn open(db_path: PathBuf, pool_id: &String, extension: &str) -> Option<Self> {
+        let mut path = db_path;
+        path.push(format!("{}{}", pool_id, extension));
+
+        let file = File::options()
+            .write(true)
+            .read(true)
+            .create(true)
+            .open(path)
+            .ok()?;
+
+        Some(IndexFile {
+            file,
+            chunk_offsets: Vec::new(),
+            end_offset: INDEX_FILE_HEADER_SIZE,
+            synced: true,
+        })
+    }
+
+    // Returns None if the index is out of sync with the messages file
+    pub(super) fn load_records<T: IndexRecord>(
+        &mut self,
+        messages_file_size: u64,
+    ) -> Option<Vec<T>> {
+        let mut buf = Vec::new();
+        self.file.seek(SeekFrom::Start(0)).ok()?;
+        self.file.read_to_end(&mut buf).ok()?;
+        if buf.len() < INDEX_FILE_HEADER_SIZE as usize {
+            return None;
+        }
+
+        let mut buf = buf.as_slice();
+        if buf.get_u64() != messages_file_size {
+            return None;
+        }
+
+        let file_len = buf.len() as u64 + INDEX_FILE_HEADER_SIZE;
+        let mut records = Vec::new();
+        while buf.has_remaining() {
+            let offset = file_len - buf.remaining() as u64;
+            let record = T::decode_length_delimited(&mut buf).ok()?;
+            self.add_chunk_offsets(record.chunk_number(), offset);
+            records.push(record);
+        }
+        self.end_offset = file_len;
+
+        Some(records)
+    }
+
+    pub(super) fn append_record<T: IndexRecord>(&mut self, record: &T) -> io::Result<()> {
+        let buf = record.encode_length_delimited_to_vec();
+        let written = self
+            .file
+            .seek(SeekFrom::Start(self.end_offset))
+            .and_then(|_| self.file.write_all(&buf));
+        if written.is_err() {
+            self.synced = false;
+            return written;
+        }
+
+        self.add_chunk_offsets(record.chunk_number(), self.end_offset);
+        self.end_offset += buf.len() as u64;
+        Ok(())
+    }
+
+    // Removes the records of the given chunk and every chunk after it
+    pub(super) fn truncate_from_chunk(&mut self, chunk_number: u64) -> io::Result<()> {
+        let offset = match self.chunk_offsets.get(chunk_number as usize) {
+            Some(offset) => *offset,
+            None => return Ok(()),
+        };
+
+        self.chunk_offsets.truncate(chunk_number as usize);
+        self.end_offset = offset;
+        if let Err(err) = self.file.set_len(offset) {
+            self.synced = false;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // Once a write has failed the records on disk can't be trusted, so the header is set
+    // to a size no messages file has and the index is rebuilt on the next open
+    pub(super) fn set_covered_size(&mut self, messages_file_size: u64) -> io::Result<()> {
+        let covered_size = if self.synced {
+            messages_file_size
+        } else {
+            u64::MAX
+        };
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&covered_size.to_be_bytes())
+    }
+
+    pub(super) fn clear(&mut self) -> io::Result<()> {
+        self.chunk_offsets.clear();
+        self.end_offset = INDEX_FILE_HEADER_SIZE;
+        self.synced = false;
+
+        self.file.set_len(0)?;
+        self.synced = true;
+        self.set_covered_size(0)
+    }
+
+    fn add_chunk_offsets(&mut self, chunk_number: u64, offset: u64) {
+        while self.chunk_offsets.len() <= chunk_number as usize {
+            self.chunk_offsets.push(offset);
+        }
+    }
+}
@@ -1,9 +1,8 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs::{create_dir, File},
-    io::{Read, Seek, SeekFrom, Write},
+    fs::{create_dir, read, read_dir, remove_file, rename, File},
+    io::{self, Read, Seek, SeekFrom, Write},
     path::PathBuf,
-    time::{SystemTime, UNIX_EPOCH},
 };
 
 use bytes::{Buf, Bytes};
@@ -11,14 +10,102 @@ use parking_lot::{Mutex, MutexGuard};
 use prost::Message;
 
 use crate::{
-    config::{LATEST_MESSAGES_SIZE, MESSAGES_DB_CHUNK_SIZE, PRODUCTION_MODE},
-    ipc::IPCPoolMessageHistory,
-    poolpb::PoolMessage,
+    config::{
+        HISTORY_PAGE_SIZE, LOGICAL_CLOCK_COUNTER_BITS, MESSAGES_DB_CHUNK_SIZE,
+        REPLY_SNIPPET_LENGTH, SEARCH_RESULTS_LIMIT, UNREAD_COUNT_LIMIT,
+    },
+    ipc::{
+        IPCMessageReaction, IPCMessageReactions, IPCPoolMessageHistory, IPCPoolMessageThread, IPCPoolMessageSearch, IPCPoolMessageSearchResult, IPCSearchFilters,
+    },
+    poolpb::{
+        pool_direct_message::HistoryRequestData, pool_message::Data as PoolMessageData,
+        PoolMessage,
+    },
     store::store_manager::StoreManager, STORE_MANAGER,
 };
 
-// const TEMP_MESSAGES: bool = !PRODUCTION_MODE;
-const TEMP_MESSAGES: bool = true;
+use super::{
+    amendments_log::AmendmentsLog,
+    id_index::IdIndex,
+    reactions_log::{reaction_target_msg_id, ReactionsLog},
+    search_index::{search_matches, searchable_text, SearchIndex},
+};
+
+const MESSAGES_FILE_EXTENSION: &'static str = ".msgs.db";
+const REWRITE_JOURNAL_FILE_EXTENSION: &'static str = ".msgs.rewrite";
+
+// Messages are stored ordered by their logical clock, messages without one fall back to created
+pub fn message_order(msg: &PoolMessage) -> u64 {
+    if msg.clock != 0 {
+        msg.clock
+    } else {
+        created_order(msg.created)
+    }
+}
+
+fn created_order(created: u64) -> u64 {
+    created << LOGICAL_CLOCK_COUNTER_BITS
+}
+
+fn cmp_message_order(a: &PoolMessage, b: &PoolMessage) -> std::cmp::Ordering {
+    (message_order(a), &a.msg_id).cmp(&(message_order(b), &b.msg_id))
+}
+
+pub fn reply_msg_id(msg: &PoolMessage) -> Option<&String> {
+    match &msg.data {
+        Some(PoolMessageData::TextData(text_data)) if !text_data.reply_msg_id.is_empty() => {
+            Some(&text_data.reply_msg_id)
+        }
+        _ => None,
+    }
+}
+
+pub enum AmendMessageResult {
+    Amended(PoolMessage),
+    NotFound,
+    Rejected,
+}
+
+pub fn amendment_target_msg_id(amendment: &PoolMessage) -> Option<&String> {
+    match &amendment.data {
+        Some(PoolMessageData::EditData(edit_data)) => Some(&edit_data.msg_id),
+        Some(PoolMessageData::DeleteData(delete_data)) => Some(&delete_data.msg_id),
+        _ => None,
+    }
+}
+
+// Only the original author can edit or delete, deletes always win and
+// edits only apply if they are newer than the last applied edit.
+// Returns true if the target was modified
+pub fn apply_amendment(target: &mut PoolMessage, amendment: &PoolMessage) -> bool {
+    if target.user_id != amendment.user_id || target.deleted {
+        return false;
+    }
+
+    let order = message_order(amendment);
+    match &amendment.data {
+        Some(PoolMessageData::EditData(edit_data)) => {
+            if order <= target.edited {
+                return false;
+            }
+
+            match &mut target.data {
+                Some(PoolMessageData::TextData(text_data)) => {
+                    text_data.text = edit_data.text.clone();
+                }
+                _ => return false,
+            }
+        }
+        Some(PoolMessageData::DeleteData(_)) => {
+            target.data = None;
+            target.deleted = true;
+        }
+        _ => return false,
+    }
+
+    target.edited = target.edited.max(order);
+    true
+}
 
 pub struct MessagesDB {
     pool_messages: Mutex<HashMap<String, MessagesDBInternal>>, // pool_id -> internal
@@ -29,7 +116,8 @@ pub struct MessagesDB {
 impl MessagesDB {
     pub fn init() -> Self {
         let db_path = Self::db_path().unwrap();
-        let _ = create_dir(db_path);
+        let _ = create_dir(db_path.clone());
+        Self::remove_orphaned_messages_files(&db_path);
 
         MessagesDB {
             pool_messages: Mutex::new(HashMap::new()),
@@ -39,7 +127,10 @@ impl MessagesDB {
 
     pub fn last_messages(&self, pool_id: &String, size: usize) -> Vec<PoolMessage> {
         let mut pool_messages = self.pool_messages.lock();
-        let internal = self.get_messages_internal(pool_id, &mut pool_messages);
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return Vec::new(),
+        };
         internal.last_messages(size)
     }
 
@@ -55,6 +146,24 @@ impl MessagesDB {
         unreachable!()
     }
 
+    pub fn messages_history_chunk_by_date(
+        &self,
+        pool_id: &String,
+        created: u64,
+    ) -> IPCPoolMessageHistory {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => {
+                return IPCPoolMessageHistory {
+                    is_latest: true,
+                    ..Default::default()
+                }
+            }
+        };
+        internal.messages_history_chunk_by_date(created, self.max_messages_render)
+    }
+
     pub fn messages_history_chunk(
         &self,
         pool_id: &String,
@@ -67,41 +176,278 @@ impl MessagesDB {
         unreachable!()
     }
 
+    pub fn thread(&self, pool_id: &String, msg_id: &String) -> IPCPoolMessageThread {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return IPCPoolMessageThread::default(),
+        };
+        internal.thread(msg_id)
+    }
+
+    // Start of the parent's text or file name to quote in a reply
+    pub fn reply_snippet(&self, pool_id: &String, msg_id: &String) -> Option<String> {
+        let msg = self.message(pool_id, msg_id)?;
+        let text = searchable_text(&msg)?;
+        Some(text.chars().take(REPLY_SNIPPET_LENGTH).collect())
+    }
+
+    pub fn search_messages(
+        &self,
+        pool_id: &String,
+        query: &String,
+        filters: IPCSearchFilters,
+    ) -> IPCPoolMessageSearch {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return IPCPoolMessageSearch::default(),
+        };
+        internal.search_messages(query, filters)
+    }
+
     // Precondition: message is already filtired
-    pub fn append_message(&self, pool_id: &String, msg: PoolMessage) {
+    // Returns None if the message already exists, otherwise whether it was added as the newest message
+    // along with the message as stored, amendments that came before it are applied
+    pub fn add_message(&self, pool_id: &String, mut msg: PoolMessage) -> Option<(bool, PoolMessage)> {
         let mut pool_messages = self.pool_messages.lock();
-        let internal = self.get_messages_internal(pool_id, &mut pool_messages);
-        internal.append_message(msg.clone());
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return None,
+        };
+        if internal.id_index.chunk_number(&msg.msg_id).is_some() {
+            return None;
+        }
+        internal.apply_logged_amendments(&mut msg);
+
+        let is_newest = match internal.id_index.max_order() {
+            Some(max_order) => message_order(&msg) >= max_order,
+            None => true,
+        };
+        internal.merge_messages(vec![msg.clone()]);
+
+        Some((is_newest, msg))
     }
 
     // Filters and adds latest messages
     pub fn add_latest_messages(&self, pool_id: &String, latest_msgs: Vec<PoolMessage>) {
         let mut pool_messages = self.pool_messages.lock();
-        let internal = self.get_messages_internal(pool_id, &mut pool_messages);
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return,
+        };
 
-        let last_messages = internal.last_messages(LATEST_MESSAGES_SIZE + 50);
-        let mut existing_messages = HashSet::with_capacity(last_messages.len());
-        for msg in last_messages {
-            existing_messages.insert(msg.msg_id);
-        }
+        internal.merge_messages(latest_msgs);
+    }
+
+    // Inserts messages in order, skipping ones that already exist. Returns the amount inserted
+    pub fn merge_messages(&self, pool_id: &String, msgs: Vec<PoolMessage>) -> usize {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return 0,
+        };
+        internal.merge_messages(msgs)
+    }
+
+    pub fn amend_message(&self, pool_id: &String, amendment: &PoolMessage) -> AmendMessageResult {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return AmendMessageResult::Rejected,
+        };
+        internal.amend_message(amendment)
+    }
+
+    pub fn latest_amendments(&self, pool_id: &String, limit: usize) -> Vec<PoolMessage> {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return Vec::new(),
+        };
+        internal.amendments_log.latest_amendments(limit)
+    }
+
+    // Amendments to the page's messages, the first page also has the ones made in the
+    // requested range so older messages that were amended during a gap converge
+    pub fn history_amendments(
+        &self,
+        pool_id: &String,
+        history_request_data: &HistoryRequestData,
+        msgs: &Vec<PoolMessage>,
+    ) -> Vec<PoolMessage> {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return Vec::new(),
+        };
 
-        for msg in latest_msgs {
-            if !existing_messages.contains(&msg.msg_id) {
-                internal.append_message(msg);
+        let mut amendments = internal.amendments_log.amendment_messages(msgs);
+        if history_request_data.after_msg_id.is_empty() {
+            for amendment in internal.amendments_log.amendments_between(
+                history_request_data.after_created,
+                history_request_data.before_created,
+                HISTORY_PAGE_SIZE,
+            ) {
+                if !amendments.iter().any(|a| a.msg_id == amendment.msg_id) {
+                    amendments.push(amendment);
+                }
             }
         }
+        amendments
+    }
+
+    pub fn message(&self, pool_id: &String, msg_id: &String) -> Option<PoolMessage> {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = self.get_messages_internal(pool_id, &mut pool_messages)?;
+        let chunk_number = internal.id_index.chunk_number(msg_id)?;
+        internal
+            .process_chunk(chunk_number)
+            .into_iter()
+            .find(|msg| &msg.msg_id == msg_id)
+    }
+
+    // Returns the target message's reactions if the reaction was added
+    pub fn add_reaction(&self, pool_id: &String, reaction: PoolMessage) -> Option<(String, Vec<IPCMessageReaction>)> {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = self.get_messages_internal(pool_id, &mut pool_messages)?;
+        let target_msg_id = reaction_target_msg_id(&reaction)?.clone();
+        if !internal.reactions_log.add_reaction(reaction) {
+            return None;
+        }
+        let reactions = internal.reactions_log.reactions(&target_msg_id);
+        Some((target_msg_id, reactions))
+    }
+
+    pub fn reaction_messages(&self, pool_id: &String, msgs: &Vec<PoolMessage>) -> Vec<PoolMessage> {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return Vec::new(),
+        };
+        internal.reactions_log.reaction_messages(msgs)
+    }
+
+    pub fn message_reactions(&self, pool_id: &String, msgs: &Vec<PoolMessage>) -> Vec<IPCMessageReactions> {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return Vec::new(),
+        };
+        internal.reactions_log.message_reactions(msgs)
+    }
+
+    // Other users' messages after the user's last read receipt, capped at UNREAD_COUNT_LIMIT
+    pub fn unread_count(&self, pool_id: &String, user_id: &String) -> usize {
+        let after_order = STORE_MANAGER.last_read_order(pool_id, user_id).unwrap_or(0);
+
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return 0,
+        };
+        internal.unread_count(user_id, after_order)
+    }
+
+    pub fn has_message(&self, pool_id: &String, msg_id: &String) -> bool {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return false,
+        };
+        internal.id_index.chunk_number(msg_id).is_some()
+    }
+
+    pub fn newest_created(&self, pool_id: &String) -> Option<u64> {
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = self.get_messages_internal(pool_id, &mut pool_messages)?;
+        internal
+            .id_index
+            .max_order()
+            .map(|max_order| max_order >> LOGICAL_CLOCK_COUNTER_BITS)
+    }
+
+    // Returns a page of messages in the requested range and whether there are more
+    pub fn history_page(
+        &self,
+        pool_id: &String,
+        history_request_data: &HistoryRequestData,
+    ) -> (Vec<PoolMessage>, bool) {
+        let limit = match history_request_data.limit as usize {
+            0 => HISTORY_PAGE_SIZE,
+            limit => limit.min(HISTORY_PAGE_SIZE),
+        };
+
+        let mut pool_messages = self.pool_messages.lock();
+        let internal = match self.get_messages_internal(pool_id, &mut pool_messages) {
+            Some(internal) => internal,
+            None => return (Vec::new(), false),
+        };
+        internal.history_page(history_request_data, limit)
     }
 
     fn get_messages_internal<'a>(
         &self,
         pool_id: &String,
         pool_messages: &'a mut MutexGuard<HashMap<String, MessagesDBInternal>>,
-    ) -> &'a mut MessagesDBInternal {
+    ) -> Option<&'a mut MessagesDBInternal> {
         if !pool_messages.contains_key(pool_id) {
-            pool_messages.insert(pool_id.clone(), MessagesDBInternal::init(pool_id.clone()));
+            match MessagesDBInternal::init(pool_id.clone()) {
+                Some(internal) => {
+                    pool_messages.insert(pool_id.clone(), internal);
+                }
+                None => {
+                    log::error!(
+                        "get_messages_internal : couldn't open the db of {}",
+                        pool_id
+                    );
+                    return None;
+                }
+            }
+        }
+
+        pool_messages.get_mut(pool_id)
+    }
+
+    // Earlier versions opened a new "{pool_id}-{micros}.msgs.db" on every launch
+    fn remove_orphaned_messages_files(db_path: &PathBuf) {
+        let entries = match read_dir(db_path) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let is_orphaned = match path.file_name().and_then(|file_name| file_name.to_str()) {
+                Some(file_name) => Self::is_orphaned_messages_file_name(file_name),
+                None => false,
+            };
+
+            if is_orphaned {
+                log::info!("remove_orphaned_messages_files : removing {:?}", path);
+                let _ = remove_file(path);
+            }
         }
+    }
+
+    fn is_orphaned_messages_file_name(file_name: &str) -> bool {
+        let file_stem = match file_name.strip_suffix(MESSAGES_FILE_EXTENSION) {
+            Some(file_stem) => file_stem,
+            None => return false,
+        };
 
-        pool_messages.get_mut(pool_id).unwrap()
+        match file_stem.rsplit_once('-') {
+            Some((pool_id, micros)) => {
+                !pool_id.is_empty()
+                    && micros.len() >= 16
+                    && micros.chars().all(|c| c.is_ascii_digit())
+            }
+            None => false,
+        }
     }
 
     fn db_path() -> Option<PathBuf> {
@@ -117,36 +463,482 @@ impl MessagesDB {
 
 struct MessagesDBInternal {
     messages_file: File,
+    id_index: IdIndex,
+    search_index: SearchIndex,
+    reactions_log: ReactionsLog,
+    amendments_log: AmendmentsLog,
+    rewrite_journal_path: PathBuf,
 
     current_chunk_number: u64,
     current_chunk_size: u64,
 }
 
 impl MessagesDBInternal {
-    fn init(pool_id: String) -> Self {
-        let messages_file = Self::open_messages_file(pool_id).unwrap();
-        let messages_file_metadata = messages_file.metadata().unwrap();
+    fn init(pool_id: String) -> Option<Self> {
+        let messages_file = Self::open_messages_file(&pool_id)?;
+        let messages_file_metadata = messages_file.metadata().ok()?;
         let messages_file_size = messages_file_metadata.len();
 
         let current_chunk_number = messages_file_size / MESSAGES_DB_CHUNK_SIZE;
         let current_chunk_size = messages_file_size % MESSAGES_DB_CHUNK_SIZE;
 
+        let db_path = MessagesDB::db_path()?;
+        let id_index = IdIndex::open(db_path.clone(), &pool_id)?;
+        let search_index = SearchIndex::open(db_path.clone(), &pool_id)?;
+        let reactions_log = ReactionsLog::open(db_path.clone(), &pool_id)?;
+        let amendments_log = AmendmentsLog::open(db_path.clone(), &pool_id)?;
+
+        let mut rewrite_journal_path = db_path;
+        rewrite_journal_path.push(format!("{}{}", pool_id, REWRITE_JOURNAL_FILE_EXTENSION));
+
         let mut internal = MessagesDBInternal {
             messages_file,
+            id_index,
+            search_index,
+            reactions_log,
+            amendments_log,
+            rewrite_journal_path,
             current_chunk_number,
             current_chunk_size,
         };
 
-        internal.check_corrupt();
+        let rewrite_replayed = internal.replay_rewrite_journal();
+        internal.recover_chunks();
 
-        internal
+        let messages_file_size = internal.messages_file_size();
+        let id_index_synced = internal.id_index.load(messages_file_size);
+        let search_index_synced = internal.search_index.load(messages_file_size);
+        if rewrite_replayed || !id_index_synced || !search_index_synced {
+            internal.rebuild_indexes();
+        }
+
+        Some(internal)
+    }
+
+    fn write_message(&mut self, msg: &PoolMessage) -> io::Result<()> {
+        let buf = msg.encode_length_delimited_to_vec();
+        self.append_message_buf(&buf)?;
+
+        self.index_message(self.current_chunk_number, msg);
+        Ok(())
+    }
+
+    fn append_message_buf(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.pre_message_chunk_append(buf.len())?;
+        self.messages_file.seek(SeekFrom::End(0))?;
+        self.messages_file.write_all(buf)
+    }
+
+    fn merge_messages(&mut self, msgs: Vec<PoolMessage>) -> usize {
+        let mut seen_msg_ids = HashSet::with_capacity(msgs.len());
+        let mut new_msgs = Vec::with_capacity(msgs.len());
+        for msg in msgs {
+            if msg.msg_id.is_empty()
+                || self.id_index.chunk_number(&msg.msg_id).is_some()
+                || !seen_msg_ids.insert(msg.msg_id.clone())
+            {
+                continue;
+            }
+            let mut msg = msg;
+            self.apply_logged_amendments(&mut msg);
+            new_msgs.push(msg);
+        }
+
+        if new_msgs.is_empty() {
+            return 0;
+        }
+
+        let inserted = new_msgs.len();
+        new_msgs.sort_by(cmp_message_order);
+
+        // Only rewrite when the messages don't all come after the existing ones
+        let min_order = message_order(&new_msgs[0]);
+        let chunk_number = match self.id_index.max_order() {
+            Some(max_order) if min_order < max_order => {
+                self.id_index.chunk_number_by_order(min_order).unwrap()
+            }
+            _ => {
+                let mut written = 0;
+                for msg in new_msgs.iter() {
+                    if let Err(err) = self.write_message(msg) {
+                        log::error!("merge_messages : {}", err);
+                        break;
+                    }
+                    written += 1;
+                }
+                self.set_indexes_covered_size();
+                return written;
+            }
+        };
+
+        let mut msgs = self.messages_from_chunk(chunk_number);
+        msgs.append(&mut new_msgs);
+        msgs.sort_by(cmp_message_order);
+        if let Err(err) = self.rewrite_from_chunk(chunk_number, msgs) {
+            log::error!("merge_messages : {}", err);
+            return 0;
+        }
+
+        inserted
+    }
+
+    // Amendments are only logged once their author is checked against the target,
+    // ones whose target isn't stored yet are held and applied once it is
+    fn amend_message(&mut self, amendment: &PoolMessage) -> AmendMessageResult {
+        let target_msg_id = match amendment_target_msg_id(amendment) {
+            Some(target_msg_id) => target_msg_id,
+            None => return AmendMessageResult::Rejected,
+        };
+
+        if self.amendments_log.contains(&amendment.msg_id) {
+            return AmendMessageResult::Rejected;
+        }
+
+        let chunk_number = match self.id_index.chunk_number(target_msg_id) {
+            Some(chunk_number) => chunk_number,
+            None => {
+                self.amendments_log.hold_amendment(amendment.clone());
+                return AmendMessageResult::NotFound;
+            }
+        };
+
+        let mut msgs = self.messages_from_chunk(chunk_number);
+        let target = match msgs.iter_mut().find(|msg| &msg.msg_id == target_msg_id) {
+            Some(target) => target,
+            None => {
+                self.amendments_log.hold_amendment(amendment.clone());
+                return AmendMessageResult::NotFound;
+            }
+        };
+
+        if target.user_id != amendment.user_id
+            || !self.amendments_log.add_amendment(amendment.clone())
+            || !apply_amendment(target, amendment)
+        {
+            return AmendMessageResult::Rejected;
+        }
+
+        let target = target.clone();
+        if let Err(err) = self.rewrite_from_chunk(chunk_number, msgs) {
+            log::error!("amend_message : {}", err);
+            return AmendMessageResult::Rejected;
+        }
+
+        AmendMessageResult::Amended(target)
     }
 
-    fn append_message(&mut self, msg: PoolMessage) {
-        let mut buf = msg.encode_length_delimited_to_vec();
-        self.pre_message_chunk_append(buf.len());
-        self.messages_file.seek(SeekFrom::End(0)).unwrap();
-        self.messages_file.write_all(&mut buf).unwrap();
+    // Held amendments by the message's author are logged now, the rest are dropped
+    fn apply_logged_amendments(&mut self, msg: &mut PoolMessage) {
+        for amendment in self.amendments_log.take_held_amendments(&msg.msg_id) {
+            if amendment.user_id == msg.user_id {
+                self.amendments_log.add_amendment(amendment);
+            }
+        }
+
+        for amendment in self.amendments_log.amendments(&msg.msg_id) {
+            apply_amendment(msg, amendment);
+        }
+    }
+
+    fn messages_from_chunk(&mut self, chunk_number: u64) -> Vec<PoolMessage> {
+        let mut msgs = Vec::new();
+        for c in chunk_number..=self.current_chunk_number {
+            msgs.append(&mut self.process_chunk(c));
+        }
+        msgs
+    }
+
+    // Replaces every message from the start of the chunk onwards. The messages are journaled
+    // first, so a rewrite that fails or crashes partway through is finished on the next init
+    fn rewrite_from_chunk(&mut self, chunk_number: u64, msgs: Vec<PoolMessage>) -> io::Result<()> {
+        log::debug!(
+            "rewrite_from_chunk : rewriting {} messages from chunk {}",
+            msgs.len(),
+            chunk_number
+        );
+
+        self.write_rewrite_journal(chunk_number, &msgs)?;
+
+        self.truncate_to_chunk(chunk_number)?;
+        self.truncate_indexes_from_chunk(chunk_number);
+
+        for msg in msgs.iter() {
+            self.write_message(msg)?;
+        }
+        self.set_indexes_covered_size();
+
+        remove_file(&self.rewrite_journal_path)
+    }
+
+    fn truncate_to_chunk(&mut self, chunk_number: u64) -> io::Result<()> {
+        self.messages_file
+            .set_len(chunk_number * MESSAGES_DB_CHUNK_SIZE)?;
+        self.current_chunk_number = chunk_number;
+        self.current_chunk_size = 0;
+        Ok(())
+    }
+
+    // The chunk number followed by the messages, written aside and renamed so a journal
+    // that exists is always complete
+    fn write_rewrite_journal(&self, chunk_number: u64, msgs: &Vec<PoolMessage>) -> io::Result<()> {
+        let mut buf = chunk_number.to_le_bytes().to_vec();
+        for msg in msgs {
+            buf.append(&mut msg.encode_length_delimited_to_vec());
+        }
+
+        let tmp_path = self.rewrite_journal_path.with_extension("tmp");
+        let mut journal_file = File::create(&tmp_path)?;
+        journal_file.write_all(&buf)?;
+        journal_file.sync_all()?;
+        rename(tmp_path, &self.rewrite_journal_path)
+    }
+
+    // Returns true if an interrupted rewrite was finished, the indexes need a rebuild then
+    fn replay_rewrite_journal(&mut self) -> bool {
+        let buf = match read(&self.rewrite_journal_path) {
+            Ok(buf) => buf,
+            Err(_) => return false,
+        };
+
+        let chunk_number = match buf.get(..8).and_then(|buf| buf.try_into().ok()) {
+            Some(chunk_number) => u64::from_le_bytes(chunk_number),
+            None => {
+                let _ = remove_file(&self.rewrite_journal_path);
+                return false;
+            }
+        };
+
+        log::warn!(
+            "replay_rewrite_journal : finishing rewrite from chunk {}",
+            chunk_number
+        );
+
+        let mut msgs_buf = &buf[8..];
+        let mut msgs = Vec::new();
+        while msgs_buf.has_remaining() {
+            match PoolMessage::decode_length_delimited(&mut msgs_buf) {
+                Ok(msg) => msgs.push(msg),
+                Err(_) => {
+                    log::error!("replay_rewrite_journal : corrupt journal");
+                    return false;
+                }
+            }
+        }
+
+        let replayed = self.truncate_to_chunk(chunk_number).and_then(|_| {
+            for msg in msgs.iter() {
+                self.append_message_buf(&msg.encode_length_delimited_to_vec())?;
+            }
+            Ok(())
+        });
+
+        match replayed {
+            Ok(()) => {
+                let _ = remove_file(&self.rewrite_journal_path);
+                true
+            }
+            Err(err) => {
+                log::error!("replay_rewrite_journal : {}", err);
+                false
+            }
+        }
+    }
+
+    fn history_page(
+        &mut self,
+        history_request_data: &HistoryRequestData,
+        limit: usize,
+    ) -> (Vec<PoolMessage>, bool) {
+        let after_msg_id = &history_request_data.after_msg_id;
+        let before_msg_id = &history_request_data.before_msg_id;
+        let after_created = history_request_data.after_created;
+        let before_created = history_request_data.before_created;
+
+        let mut past_cursor = after_msg_id.is_empty();
+        let start_chunk_number = match self.id_index.chunk_number(after_msg_id) {
+            Some(chunk_number) if !past_cursor => chunk_number,
+            _ => {
+                past_cursor = true;
+                match self.id_index.chunk_number_by_order(created_order(after_created)) {
+                    Some(chunk_number) => chunk_number,
+                    None => return (Vec::new(), false),
+                }
+            }
+        };
+
+        let mut msgs = Vec::new();
+        for chunk_number in start_chunk_number..=self.current_chunk_number {
+            for msg in self.process_chunk(chunk_number) {
+                if !past_cursor {
+                    past_cursor = &msg.msg_id == after_msg_id;
+                    continue;
+                }
+
+                if &msg.msg_id == before_msg_id {
+                    return (msgs, false);
+                }
+
+                if msg.created < after_created
+                    || (before_created != 0 && msg.created >= before_created)
+                {
+                    continue;
+                }
+
+                if msgs.len() == limit {
+                    return (msgs, true);
+                }
+                msgs.push(msg);
+            }
+        }
+
+        (msgs, false)
+    }
+
+    // Index write errors are only logged, the indexes stay usable in memory and
+    // are rebuilt on the next init
+    fn index_message(&mut self, chunk_number: u64, msg: &PoolMessage) {
+        if let Err(err) = self.id_index.index_message(chunk_number, msg) {
+            log::error!("index_message : id index : {}", err);
+        }
+        if let Err(err) = self.search_index.index_message(chunk_number, msg) {
+            log::error!("index_message : search index : {}", err);
+        }
+    }
+
+    fn truncate_indexes_from_chunk(&mut self, chunk_number: u64) {
+        if let Err(err) = self.id_index.truncate_from_chunk(chunk_number) {
+            log::error!("truncate_indexes_from_chunk : id index : {}", err);
+        }
+        if let Err(err) = self.search_index.truncate_from_chunk(chunk_number) {
+            log::error!("truncate_indexes_from_chunk : search index : {}", err);
+        }
+    }
+
+    fn set_indexes_covered_size(&mut self) {
+        let messages_file_size = self.messages_file_size();
+        if let Err(err) = self.id_index.set_covered_size(messages_file_size) {
+            log::error!("set_indexes_covered_size : id index : {}", err);
+        }
+        if let Err(err) = self.search_index.set_covered_size(messages_file_size) {
+            log::error!("set_indexes_covered_size : search index : {}", err);
+        }
+    }
+
+    fn rebuild_indexes(&mut self) {
+        log::info!("rebuild_indexes : rebuilding");
+        if let Err(err) = self.id_index.clear() {
+            log::error!("rebuild_indexes : id index : {}", err);
+        }
+        if let Err(err) = self.search_index.clear() {
+            log::error!("rebuild_indexes : search index : {}", err);
+        }
+        for chunk_number in 0..=self.current_chunk_number {
+            for msg in self.process_chunk(chunk_number) {
+                self.index_message(chunk_number, &msg);
+            }
+        }
+        self.set_indexes_covered_size();
+    }
+
+    fn thread(&mut self, msg_id: &String) -> IPCPoolMessageThread {
+        let mut message = None;
+        let mut replies = Vec::new();
+
+        let reply_msg_ids: HashSet<String> = self.id_index.replies(msg_id).into_iter().collect();
+        let mut chunk_numbers: Vec<u64> = reply_msg_ids
+            .iter()
+            .chain(std::iter::once(msg_id))
+            .filter_map(|id| self.id_index.chunk_number(id))
+            .collect();
+        chunk_numbers.sort_unstable();
+        chunk_numbers.dedup();
+
+        for chunk_number in chunk_numbers {
+            for msg in self.process_chunk(chunk_number) {
+                if &msg.msg_id == msg_id {
+                    message = Some(msg);
+                } else if reply_msg_ids.contains(&msg.msg_id) {
+                    replies.push(msg);
+                }
+            }
+        }
+        replies.sort_by(cmp_message_order);
+
+        let mut reactions = self.reactions_log.message_reactions(&replies);
+        if let Some(message) = &message {
+            reactions.append(
+                &mut self
+                    .reactions_log
+                    .message_reactions(std::slice::from_ref(message)),
+            );
+        }
+
+        IPCPoolMessageThread {
+            message,
+            replies,
+            reactions,
+        }
+    }
+
+    fn search_messages(&mut self, query: &String, filters: IPCSearchFilters) -> IPCPoolMessageSearch {
+        let limit = filters.limit.unwrap_or(SEARCH_RESULTS_LIMIT);
+        let mut results = Vec::new();
+        let mut has_more = false;
+
+        let mut loaded_chunk_number = None;
+        let mut loaded_chunk = Vec::new();
+        for posting in self.search_index.search(query) {
+            if loaded_chunk_number != Some(posting.chunk_number) {
+                loaded_chunk = self.process_chunk(posting.chunk_number);
+                loaded_chunk_number = Some(posting.chunk_number);
+            }
+
+            let msg = match loaded_chunk.iter().find(|msg| msg.msg_id == posting.msg_id) {
+                Some(msg) => msg,
+                None => continue,
+            };
+
+            if !Self::search_filters_match(msg, &filters) {
+                continue;
+            }
+
+            if results.len() == limit {
+                has_more = true;
+                break;
+            }
+
+            results.push(IPCPoolMessageSearchResult {
+                matches: search_matches(searchable_text(msg).unwrap_or_default(), query),
+                message: msg.clone(),
+                chunk_number: posting.chunk_number,
+            });
+        }
+
+        IPCPoolMessageSearch { results, has_more }
+    }
+
+    fn search_filters_match(msg: &PoolMessage, filters: &IPCSearchFilters) -> bool {
+        if let Some(user_id) = &filters.user_id {
+            if &msg.user_id != user_id {
+                return false;
+            }
+        }
+        if let Some(msg_type) = filters.msg_type {
+            if msg.r#type != msg_type {
+                return false;
+            }
+        }
+        if let Some(after) = filters.after {
+            if msg.created < after {
+                return false;
+            }
+        }
+        if let Some(before) = filters.before {
+            if msg.created > before {
+                return false;
+            }
+        }
+        true
     }
 
     fn last_messages(&mut self, size: usize) -> Vec<PoolMessage> {
@@ -174,62 +966,103 @@ impl MessagesDBInternal {
         msgs
     }
 
-    fn messages_history_chunk_by_id(&mut self, msg_id: &String, min_messages: usize) -> IPCPoolMessageHistory {
-        let mut messages = Vec::new();
-        let mut chunk_number = self.current_chunk_number;
-        let mut chunk_lens = Vec::with_capacity(2);
-        'outer_loop: loop {
-            let mut chunk = self.process_chunk(chunk_number);
-            let chunk_len = chunk.len();
-
-            for i in 0..chunk_len {
-                if &chunk[i].msg_id == msg_id {
-                    if i > chunk_len / 2 {
-                        if chunk_number < self.current_chunk_number {
-                            messages = self.process_chunk(chunk_number + 1);
-                            chunk_lens.push(chunk.len());
-                            chunk_lens.push(messages.len());
-                            chunk.append(&mut messages);
-                            messages = chunk;
-                        } else {
-                            chunk_lens.push(chunk.len());
-                            messages = chunk;
-                        }
-                    } else {
-                        if chunk_number > 0 {
-                            chunk_number -= 1;
-                            messages = self.process_chunk(chunk_number);
-                            chunk_lens.push(messages.len());
-                            chunk_lens.push(chunk.len());
-                            messages.append(&mut chunk);
-                        } else {
-                            chunk_lens.push(chunk.len());
-                            messages = chunk;
-                        }
+    fn unread_count(&mut self, user_id: &String, after_order: u64) -> usize {
+        let mut unread_count = 0;
+        for chunk_number in (0..=self.current_chunk_number).rev() {
+            for msg in self.process_chunk(chunk_number).iter().rev() {
+                if message_order(msg) <= after_order {
+                    return unread_count;
+                }
+
+                if &msg.user_id != user_id && !msg.deleted {
+                    unread_count += 1;
+                    if unread_count >= UNREAD_COUNT_LIMIT {
+                        return unread_count;
                     }
+                }
+            }
+        }
+        unread_count
+    }
 
-                    while messages.len() < min_messages {
-                        if chunk_number == 0 {
-                            break;
-                        }
-                        chunk_number -= 1;
+    fn messages_history_chunk_by_id(&mut self, msg_id: &String, min_messages: usize) -> IPCPoolMessageHistory {
+        let chunk_number = match self.id_index.chunk_number(msg_id) {
+            Some(chunk_number) => chunk_number,
+            None => return self.empty_messages_history(),
+        };
 
-                        let mut chunk = self.process_chunk(chunk_number);
-                        chunk_lens.insert(0, chunk.len());
-                        chunk.append(&mut messages);
-                        messages = chunk;
-                    }
+        let chunk = self.process_chunk(chunk_number);
+        let position = chunk
+            .iter()
+            .position(|msg| &msg.msg_id == msg_id)
+            .unwrap_or(0);
 
-                    break 'outer_loop;
-                }
+        self.messages_history_around(chunk_number, chunk, position, min_messages)
+    }
+
+    fn messages_history_chunk_by_date(&mut self, created: u64, min_messages: usize) -> IPCPoolMessageHistory {
+        let chunk_number = match self.id_index.chunk_number_by_order(created_order(created)) {
+            Some(chunk_number) => chunk_number,
+            None => return self.empty_messages_history(),
+        };
+
+        let chunk = self.process_chunk(chunk_number);
+        let position = chunk
+            .iter()
+            .position(|msg| msg.created >= created)
+            .unwrap_or(chunk.len().saturating_sub(1));
+
+        self.messages_history_around(chunk_number, chunk, position, min_messages)
+    }
+
+    // Loads the neighbouring chunk closest to the position, then older chunks until min_messages
+    fn messages_history_around(
+        &mut self,
+        mut chunk_number: u64,
+        mut chunk: Vec<PoolMessage>,
+        position: usize,
+        min_messages: usize,
+    ) -> IPCPoolMessageHistory {
+        let mut messages;
+        let mut chunk_lens = Vec::with_capacity(2);
+        if position > chunk.len() / 2 {
+            if chunk_number < self.current_chunk_number {
+                messages = self.process_chunk(chunk_number + 1);
+                chunk_lens.push(chunk.len());
+                chunk_lens.push(messages.len());
+                chunk.append(&mut messages);
+                messages = chunk;
+            } else {
+                chunk_lens.push(chunk.len());
+                messages = chunk;
+            }
+        } else {
+            if chunk_number > 0 {
+                chunk_number -= 1;
+                messages = self.process_chunk(chunk_number);
+                chunk_lens.push(messages.len());
+                chunk_lens.push(chunk.len());
+                messages.append(&mut chunk);
+            } else {
+                chunk_lens.push(chunk.len());
+                messages = chunk;
             }
+        }
+
+        while messages.len() < min_messages {
             if chunk_number == 0 {
                 break;
             }
             chunk_number -= 1;
+
+            let mut chunk = self.process_chunk(chunk_number);
+            chunk_lens.insert(0, chunk.len());
+            chunk.append(&mut messages);
+            messages = chunk;
         }
 
         IPCPoolMessageHistory {
+            reactions: self.reactions_log.message_reactions(&messages),
             messages,
             chunk_lens,
             chunk_number,
@@ -237,10 +1070,21 @@ impl MessagesDBInternal {
         }
     }
 
+    fn empty_messages_history(&self) -> IPCPoolMessageHistory {
+        IPCPoolMessageHistory {
+            messages: Vec::new(),
+            reactions: Vec::new(),
+            chunk_lens: Vec::new(),
+            chunk_number: 0,
+            is_latest: self.current_chunk_number == 0,
+        }
+    }
+
     fn messages_history_chunk(&mut self, chunk_number: u64) -> IPCPoolMessageHistory {
         if chunk_number > self.current_chunk_number {
             return IPCPoolMessageHistory {
                 messages: Vec::new(),
+                reactions: Vec::new(),
                 chunk_lens: Vec::new(),
                 chunk_number,
                 is_latest: false,
@@ -250,6 +1094,7 @@ impl MessagesDBInternal {
         let chunk = self.process_chunk(chunk_number);
         let chunk_len = chunk.len();
         IPCPoolMessageHistory {
+            reactions: self.reactions_log.message_reactions(&chunk),
             messages: chunk,
             chunk_lens: vec![chunk_len],
             chunk_number,
@@ -257,44 +1102,85 @@ impl MessagesDBInternal {
         }
     }
 
-    fn check_corrupt(&mut self) {
-        let mut buf = vec![0u8; self.current_chunk_size as usize];
-        self.messages_file
-            .seek(SeekFrom::Start(
-                self.current_chunk_number * MESSAGES_DB_CHUNK_SIZE,
-            ))
-            .unwrap();
-        self.messages_file.read_exact(&mut buf).unwrap();
+    // Validates every chunk so that a partially written message never hides the ones after it.
+    // Sealed chunks have anything past their last valid message zeroed out as padding,
+    // while the current chunk is truncated to its last valid message.
+    fn recover_chunks(&mut self) {
+        for chunk_number in 0..=self.current_chunk_number {
+            let is_current_chunk = chunk_number == self.current_chunk_number;
+            let chunk = self.read_chunk(chunk_number);
+            let valid_len = Self::valid_chunk_len(&chunk);
 
-        let mut buf = Bytes::from(buf);
-        while buf.has_remaining() {
-            let remaining = buf.remaining();
-            if PoolMessage::decode_length_delimited(&mut buf).is_err() {
-                self.current_chunk_size -= remaining as u64;
+            if valid_len == chunk.len() {
+                continue;
+            }
+
+            if is_current_chunk {
+                log::warn!(
+                    "recover_chunks : truncating chunk {} from {} to {}",
+                    chunk_number,
+                    chunk.len(),
+                    valid_len
+                );
+                self.current_chunk_size = valid_len as u64;
                 self.messages_file
                     .set_len(self.messages_file_size())
                     .unwrap();
-                break;
+            } else if chunk[valid_len..].iter().any(|b| *b != 0) {
+                log::warn!(
+                    "recover_chunks : zeroing corrupt data in chunk {} after {}",
+                    chunk_number,
+                    valid_len
+                );
+                let padding = vec![0u8; chunk.len() - valid_len];
+                self.messages_file
+                    .seek(SeekFrom::Start(
+                        chunk_number * MESSAGES_DB_CHUNK_SIZE + valid_len as u64,
+                    ))
+                    .unwrap();
+                self.messages_file.write_all(&padding).unwrap();
             }
         }
     }
 
-    fn process_chunk(&mut self, chunk_number: u64) -> Vec<PoolMessage> {
+    // Returns the length of the leading run of valid messages in a chunk
+    fn valid_chunk_len(chunk: &[u8]) -> usize {
+        let mut buf = chunk;
+        let mut valid_len = 0;
+        while buf.has_remaining() {
+            match PoolMessage::decode_length_delimited(&mut buf) {
+                Ok(msg) if !msg.msg_id.is_empty() => valid_len = chunk.len() - buf.remaining(),
+                _ => break,
+            }
+        }
+        valid_len
+    }
+
+    fn read_chunk(&mut self, chunk_number: u64) -> Vec<u8> {
         let chunk_size = if chunk_number == self.current_chunk_number {
             self.current_chunk_size
         } else {
             MESSAGES_DB_CHUNK_SIZE
         };
 
+        let mut buf = vec![0u8; chunk_size as usize];
         if chunk_size == 0 {
-            return Vec::new();
+            return buf;
         }
 
-        let mut buf = vec![0u8; chunk_size as usize];
         self.messages_file
             .seek(SeekFrom::Start(chunk_number * MESSAGES_DB_CHUNK_SIZE))
             .unwrap();
-        let _ = self.messages_file.read_exact(&mut buf).unwrap();
+        self.messages_file.read_exact(&mut buf).unwrap();
+
+        buf
+    }
+
+    fn process_chunk(&mut self, chunk_number: u64) -> Vec<PoolMessage> {
+        let buf = self.read_chunk(chunk_number);
+        if buf.is_empty() {
+            return Vec::new();
+        }
 
         let mut buf = Bytes::from(buf);
         let mut msgs = Vec::new();
@@ -308,42 +1194,32 @@ impl MessagesDBInternal {
         msgs
     }
 
-    fn pre_message_chunk_append(&mut self, msg_buf_len: usize) {
+    fn pre_message_chunk_append(&mut self, msg_buf_len: usize) -> io::Result<()> {
         if self.current_chunk_size + msg_buf_len as u64 <= MESSAGES_DB_CHUNK_SIZE {
             self.current_chunk_size += msg_buf_len as u64;
-            return;
+            return Ok(());
         }
 
         let padding_len = MESSAGES_DB_CHUNK_SIZE - self.current_chunk_size;
         let mut padding = vec![0u8; padding_len as usize];
-        self.messages_file.seek(SeekFrom::End(0)).unwrap();
-        self.messages_file.write_all(&mut padding).unwrap();
+        self.messages_file.seek(SeekFrom::End(0))?;
+        self.messages_file.write_all(&mut padding)?;
         self.current_chunk_number += 1;
         self.current_chunk_size = msg_buf_len as u64;
+        Ok(())
     }
 
     fn messages_file_size(&self) -> u64 {
         (self.current_chunk_number * MESSAGES_DB_CHUNK_SIZE) + self.current_chunk_size
     }
 
-    fn open_messages_file(pool_id: String) -> Option<File> {
+    fn open_messages_file(pool_id: &String) -> Option<File> {
         let mut path = match MessagesDB::db_path() {
             Some(db_path) => db_path,
             None => return None,
         };
 
-        if TEMP_MESSAGES {
-            path.push(format!(
-                "{}-{}.msgs.db",
-                pool_id,
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_micros()
-            ));
-        } else {
-            path.push(format!("{}.msgs.db", pool_id));
-        }
+        path.push(format!("{}{}", pool_id, MESSAGES_FILE_EXTENSION));
 
         File::options()
             .write(true)
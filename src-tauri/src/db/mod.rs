@@ -1 +1,6 @@
-pub mod messages_db;
\ No newline at end of file
+pub mod messages_db;
+mod index_file;
+mod id_index;
+mod amendments_log;
+mod reactions_log;
+mod search_index;
\ No newline at end of file
@@ -0,0 +1,183 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use bytes::Buf;
+use prost::Message;
+
+use crate::{
+    ipc::{IPCMessageReaction, IPCMessageReactions},
+    poolpb::{pool_message::Data as PoolMessageData, PoolMessage},
+};
+
+use super::messages_db::message_order;
+
+const REACTIONS_FILE_EXTENSION: &'static str = ".reactions.db";
+
+// Append only log of reaction messages, aggregated per target message in memory
+pub(super) struct ReactionsLog {
+    reactions_file: File,
+    reactions: HashMap<String, Vec<PoolMessage>>, // target msg_id -> reaction messages
+    reaction_msg_ids: HashSet<String>,
+}
+
+impl ReactionsLog {
+    pub(super) fn open(db_path: PathBuf, pool_id: &String) -> Option<Self> {
+        let mut path = db_path;
+        path.push(format!("{}{}", pool_id, REACTIONS_FILE_EXTENSION));
+
+        let mut reactions_file = File::options()
+            .write(true)
+            .read(true)
+            .create(true)
+            .open(path)
+            .ok()?;
+
+        let mut buf = Vec::new();
+        reactions_file.read_to_end(&mut buf).ok()?;
+
+        let mut reactions_log = ReactionsLog {
+            reactions_file,
+            reactions: HashMap::new(),
+            reaction_msg_ids: HashSet::new(),
+        };
+
+        // Drop a partially written reaction at the end
+        let file_len = buf.len();
+        let mut buf = buf.as_slice();
+        let mut valid_len = 0;
+        while buf.has_remaining() {
+            match PoolMessage::decode_length_delimited(&mut buf) {
+                Ok(msg) if reaction_target_msg_id(&msg).is_some() => {
+                    valid_len = file_len - buf.remaining();
+                    reactions_log.insert_reaction(msg);
+                }
+                _ => break,
+            }
+        }
+
+        if valid_len != file_len {
+            log::warn!("ReactionsLog::open : truncating from {} to {}", file_len, valid_len);
+            reactions_log.reactions_file.set_len(valid_len as u64).ok()?;
+        }
+
+        Some(reactions_log)
+    }
+
+    // Returns false if the reaction already exists, is invalid or couldn't be written
+    pub(super) fn add_reaction(&mut self, reaction: PoolMessage) -> bool {
+        if reaction_target_msg_id(&reaction).is_none()
+            || self.reaction_msg_ids.contains(&reaction.msg_id)
+        {
+            return false;
+        }
+
+        let written = self.reactions_file.seek(SeekFrom::End(0)).and_then(|_| {
+            self.reactions_file
+                .write_all(&reaction.encode_length_delimited_to_vec())
+        });
+        if let Err(err) = written {
+            log::error!("ReactionsLog::add_reaction : {}", err);
+            return false;
+        }
+        self.insert_reaction(reaction);
+
+        true
+    }
+
+    pub(super) fn reaction_messages(&self, msgs: &[PoolMessage]) -> Vec<PoolMessage> {
+        let mut reaction_messages = Vec::new();
+        for msg in msgs {
+            if let Some(reactions) = self.reactions.get(&msg.msg_id) {
+                reaction_messages.extend(reactions.iter().cloned());
+            }
+        }
+        reaction_messages
+    }
+
+    pub(super) fn message_reactions(&self, msgs: &[PoolMessage]) -> Vec<IPCMessageReactions> {
+        let mut message_reactions = Vec::new();
+        for msg in msgs {
+            let reactions = self.reactions(&msg.msg_id);
+            if !reactions.is_empty() {
+                message_reactions.push(IPCMessageReactions {
+                    msg_id: msg.msg_id.clone(),
+                    reactions,
+                });
+            }
+        }
+        message_reactions
+    }
+
+    // The latest add or remove of each user and emoji wins
+    pub(super) fn reactions(&self, msg_id: &String) -> Vec<IPCMessageReaction> {
+        let reaction_msgs = match self.reactions.get(msg_id) {
+            Some(reaction_msgs) => reaction_msgs,
+            None => return Vec::new(),
+        };
+
+        let mut latest: HashMap<(&String, &String), &PoolMessage> = HashMap::new();
+        for reaction_msg in reaction_msgs {
+            let reaction_data = match &reaction_msg.data {
+                Some(PoolMessageData::ReactionData(reaction_data)) => reaction_data,
+                _ => continue,
+            };
+
+            let key = (&reaction_msg.user_id, &reaction_data.emoji);
+            match latest.get(&key) {
+                Some(latest_msg) if message_order(latest_msg) >= message_order(reaction_msg) => {}
+                _ => {
+                    latest.insert(key, reaction_msg);
+                }
+            }
+        }
+
+        let mut reactions: Vec<IPCMessageReaction> = Vec::new();
+        for ((user_id, emoji), reaction_msg) in latest {
+            if let Some(PoolMessageData::ReactionData(reaction_data)) = &reaction_msg.data {
+                if reaction_data.remove {
+                    continue;
+                }
+            }
+
+            match reactions.iter_mut().find(|reaction| &reaction.emoji == emoji) {
+                Some(reaction) => reaction.user_ids.push(user_id.clone()),
+                None => reactions.push(IPCMessageReaction {
+                    emoji: emoji.clone(),
+                    count: 0,
+                    user_ids: vec![user_id.clone()],
+                }),
+            }
+        }
+
+        for reaction in reactions.iter_mut() {
+            reaction.count = reaction.user_ids.len();
+            reaction.user_ids.sort();
+        }
+        reactions.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emoji.cmp(&b.emoji)));
+
+        reactions
+    }
+
+    fn insert_reaction(&mut self, reaction: PoolMessage) {
+        let target_msg_id = match reaction_target_msg_id(&reaction) {
+            Some(target_msg_id) => target_msg_id.clone(),
+            None => return,
+        };
+
+        self.reaction_msg_ids.insert(reaction.msg_id.clone());
+        self.reactions.entry(target_msg_id).or_default().push(reaction);
+    }
+}
+
+pub(super) fn reaction_target_msg_id(reaction: &PoolMessage) -> Option<&String> {
+    match &reaction.data {
+        Some(PoolMessageData::ReactionData(reaction_data)) if !reaction_data.emoji.is_empty() => {
+            Some(&reaction_data.msg_id)
+        }
+        _ => None,
+    }
+}
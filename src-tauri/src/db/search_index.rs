@@ -0,0 +1,222 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    path::PathBuf,
+};
+
+use prost::Message;
+
+use crate::{
+    ipc::IPCSearchMatch,
+    poolpb::{pool_message, PoolMessage},
+};
+
+use super::index_file::{IndexFile, IndexRecord};
+
+const SEARCH_INDEX_FILE_EXTENSION: &'static str = ".search.db";
+
+#[derive(Clone, PartialEq, Message)]
+struct SearchIndexRecord {
+    #[prost(string, tag = "1")]
+    msg_id: String,
+    #[prost(uint64, tag = "2")]
+    chunk_number: u64,
+    #[prost(string, repeated, tag = "3")]
+    terms: Vec<String>,
+}
+
+impl IndexRecord for SearchIndexRecord {
+    fn chunk_number(&self) -> u64 {
+        self.chunk_number
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct SearchPosting {
+    pub(super) chunk_number: u64,
+    pub(super) msg_id: String,
+}
+
+// Inverted index of message text and file names
+pub(super) struct SearchIndex {
+    index_file: IndexFile,
+    terms: BTreeMap<String, Vec<usize>>, // term -> posting indexes
+    postings: Vec<SearchPosting>,
+}
+
+impl SearchIndex {
+    pub(super) fn open(db_path: PathBuf, pool_id: &String) -> Option<Self> {
+        Some(SearchIndex {
+            index_file: IndexFile::open(db_path, pool_id, SEARCH_INDEX_FILE_EXTENSION)?,
+            terms: BTreeMap::new(),
+            postings: Vec::new(),
+        })
+    }
+
+    // Loads the index, returns false if it is out of sync with the messages file
+    pub(super) fn load(&mut self, messages_file_size: u64) -> bool {
+        let records = match self
+            .index_file
+            .load_records::<SearchIndexRecord>(messages_file_size)
+        {
+            Some(records) => records,
+            None => return false,
+        };
+
+        for record in records {
+            self.insert_record(record);
+        }
+
+        true
+    }
+
+    pub(super) fn clear(&mut self) -> io::Result<()> {
+        self.terms.clear();
+        self.postings.clear();
+        self.index_file.clear()
+    }
+
+    pub(super) fn truncate_from_chunk(&mut self, chunk_number: u64) -> io::Result<()> {
+        let postings_len = self
+            .postings
+            .partition_point(|posting| posting.chunk_number < chunk_number);
+        self.postings.truncate(postings_len);
+
+        self.terms.retain(|_, posting_indexes| {
+            posting_indexes.retain(|i| *i < postings_len);
+            !posting_indexes.is_empty()
+        });
+
+        self.index_file.truncate_from_chunk(chunk_number)
+    }
+
+    pub(super) fn index_message(&mut self, chunk_number: u64, msg: &PoolMessage) -> io::Result<()> {
+        let text = match searchable_text(msg) {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+
+        let terms = unique_terms(text);
+        if terms.is_empty() {
+            return Ok(());
+        }
+
+        let record = SearchIndexRecord {
+            msg_id: msg.msg_id.clone(),
+            chunk_number,
+            terms,
+        };
+
+        let written = self.index_file.append_record(&record);
+        self.insert_record(record);
+        written
+    }
+
+    pub(super) fn set_covered_size(&mut self, messages_file_size: u64) -> io::Result<()> {
+        self.index_file.set_covered_size(messages_file_size)
+    }
+
+    // Postings of messages that match every query term (prefix match), newest first
+    pub(super) fn search(&self, query: &str) -> Vec<SearchPosting> {
+        let query_terms = unique_terms(query);
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matched: Option<HashSet<usize>> = None;
+        for query_term in query_terms.iter() {
+            let mut term_matches = HashSet::new();
+            for (term, posting_indexes) in self.terms.range(query_term.clone()..) {
+                if !term.starts_with(query_term.as_str()) {
+                    break;
+                }
+                term_matches.extend(posting_indexes.iter().copied());
+            }
+
+            matched = Some(match matched {
+                Some(matched) => matched.intersection(&term_matches).copied().collect(),
+                None => term_matches,
+            });
+        }
+
+        let mut posting_indexes: Vec<usize> = matched.unwrap_or_default().into_iter().collect();
+        posting_indexes.sort_unstable_by(|a, b| b.cmp(a));
+        posting_indexes
+            .into_iter()
+            .map(|i| self.postings[i].clone())
+            .collect()
+    }
+
+    fn insert_record(&mut self, record: SearchIndexRecord) {
+        let posting_index = self.postings.len();
+        self.postings.push(SearchPosting {
+            chunk_number: record.chunk_number,
+            msg_id: record.msg_id,
+        });
+
+        for term in record.terms {
+            self.terms.entry(term).or_default().push(posting_index);
+        }
+    }
+}
+
+pub(super) fn searchable_text(msg: &PoolMessage) -> Option<&str> {
+    match msg.data.as_ref()? {
+        pool_message::Data::TextData(text_data) => Some(&text_data.text),
+        pool_message::Data::FileOfferData(file_info) => Some(&file_info.file_name),
+        pool_message::Data::MediaOfferData(media_offer_data) => {
+            Some(&media_offer_data.file_info.as_ref()?.file_name)
+        }
+        _ => None,
+    }
+}
+
+// Offsets are in UTF-16 code units so they line up with JS string indexes
+pub(super) fn search_matches(text: &str, query: &str) -> Vec<IPCSearchMatch> {
+    let query_terms = unique_terms(query);
+    let mut matches = Vec::new();
+    for (term, search_match) in tokenize(text) {
+        if query_terms
+            .iter()
+            .any(|query_term| term.starts_with(query_term.as_str()))
+        {
+            matches.push(search_match);
+        }
+    }
+    matches
+}
+
+fn unique_terms(text: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for (term, _) in tokenize(text) {
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+fn tokenize(text: &str) -> Vec<(String, IPCSearchMatch)> {
+    let mut tokens = Vec::new();
+    let mut term = String::new();
+    let mut start = 0;
+    let mut offset = 0;
+
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if term.is_empty() {
+                start = offset;
+            }
+            term.extend(c.to_lowercase());
+        } else if !term.is_empty() {
+            tokens.push((std::mem::take(&mut term), IPCSearchMatch { start, end: offset }));
+        }
+        offset += c.len_utf16();
+    }
+
+    if !term.is_empty() {
+        tokens.push((term, IPCSearchMatch { start, end: offset }));
+    }
+
+    tokens
+}
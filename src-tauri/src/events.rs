@@ -3,11 +3,11 @@ use tauri::Manager;
 use crate::{
     ipc::{
         IPCAddPoolFileOffers, IPCAddPoolNode, IPCAddPoolUser, IPCAppendPoolMessage,
-        IPCCompletePoolFileDownload, IPCInitPool, IPCInitPoolFileSeeders,
-        IPCLatestPoolMessages, IPCPoolNode, IPCReconnectPool, IPCRemovePoolFileOffer,
-        IPCRemovePoolNode, IPCRemovePoolUser, IPCStateUpdate, IPCRefreshAuthToken,
+        IPCCompletePoolFileDownload, IPCInitPool, IPCInitPoolFileSeeders, IPCInsertPoolMessage,
+        IPCLatestPoolMessages, IPCMessageReaction, IPCPoolEphemeralMessage, IPCPoolMessageReactions, IPCPoolNode, IPCPoolReadReceipt, IPCQueuePoolFileDownload, IPCReadReceipt, IPCReconnectPool, IPCRemovePoolFileOffer, IPCRemovePoolFolderOffer,
+        IPCRemovePoolNode, IPCRemovePoolUser, IPCStartPoolFileDownload, IPCStateUpdate, IPCRefreshAuthToken, IPCUpdatePoolMessage,
     },
-    poolpb::{PoolFileInfo, PoolFileSeeders, PoolMessage},
+    poolpb::{PoolEphemeralMessage, PoolFileInfo, PoolFileSeeders, PoolMessage},
     sspb::PoolUserInfo,
     GLOBAL_APP_HANDLE, MESSAGES_DB, STORE_MANAGER,
 };
@@ -26,12 +26,20 @@ const REMOVE_POOL_USER_EVENT: &'static str = "remove-pool-user";
 
 const ADD_POOL_FILE_OFFERS_EVENT: &'static str = "add-pool-file-offers";
 const REMOVE_POOL_FILE_OFFER_EVENT: &'static str = "remove-pool-file-offer";
+const REMOVE_POOL_FOLDER_OFFER_EVENT: &'static str = "remove-pool-folder-offer";
 const INIT_POOL_FILE_SEEDERS_EVENT: &'static str = "init-pool-file-seeders";
 
+const QUEUE_POOL_FILE_DOWNLOAD_EVENT: &'static str = "queue-pool-file-download";
+const START_POOL_FILE_DOWNLOAD_EVENT: &'static str = "start-pool-file-download";
 const COMPLETE_POOL_FILE_DOWNLOAD_EVENT: &'static str = "complete-pool-file-download";
 
 const LATEST_POOL_MESSAGES_EVENT: &'static str = "latest-pool-messages";
 const APPEND_POOL_MESSAGE_EVENT: &'static str = "append-pool-message";
+const INSERT_POOL_MESSAGE_EVENT: &'static str = "insert-pool-message";
+const UPDATE_POOL_MESSAGE_EVENT: &'static str = "update-pool-message";
+const UPDATE_POOL_MESSAGE_REACTIONS_EVENT: &'static str = "update-pool-message-reactions";
+const POOL_EPHEMERAL_MESSAGE_EVENT: &'static str = "pool-ephemeral-message";
+const UPDATE_POOL_READ_RECEIPT_EVENT: &'static str = "update-pool-read-receipt";
 
 pub fn state_update_event(state: IPCStateUpdate) {
     if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
@@ -149,6 +157,19 @@ pub fn remove_pool_file_offer_event(pool_id: &String, node_id: String, file_id:
     }
 }
 
+pub fn remove_pool_folder_offer_event(pool_id: &String, node_id: String, folder_id: String) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            REMOVE_POOL_FOLDER_OFFER_EVENT,
+            IPCRemovePoolFolderOffer {
+                pool_id: pool_id.clone(),
+                node_id,
+                folder_id,
+            },
+        );
+    }
+}
+
 pub fn init_pool_file_seeders_event(pool_id: &String, file_seeders: Vec<PoolFileSeeders>) {
     if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
         let _ = app_handle.emit_all(
@@ -161,6 +182,33 @@ pub fn init_pool_file_seeders_event(pool_id: &String, file_seeders: Vec<PoolFile
     }
 }
 
+// Sent again when the priority of a queued download changes
+pub fn queue_pool_file_download_event(pool_id: &String, file_id: String, priority: i32) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            QUEUE_POOL_FILE_DOWNLOAD_EVENT,
+            IPCQueuePoolFileDownload {
+                pool_id: pool_id.clone(),
+                file_id,
+                priority,
+            },
+        );
+    }
+}
+
+pub fn start_pool_file_download_event(pool_id: &String, file_id: String) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            START_POOL_FILE_DOWNLOAD_EVENT,
+            IPCStartPoolFileDownload {
+                pool_id: pool_id.clone(),
+                file_id,
+            },
+        );
+    }
+}
+
+// Also sent for queued downloads that are removed before they start
 pub fn complete_pool_file_download_event(pool_id: &String, file_id: String, success: bool) {
     if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
         let _ = app_handle.emit_all(
@@ -177,11 +225,26 @@ pub fn complete_pool_file_download_event(pool_id: &String, file_id: String, succ
 pub fn latest_pool_messages_event(pool_id: &String) {
     if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
         let max_messages_render = STORE_MANAGER.max_messages_render();
+        let messages = MESSAGES_DB.last_messages(&pool_id, max_messages_render);
         let _ = app_handle.emit_all(
             LATEST_POOL_MESSAGES_EVENT,
             IPCLatestPoolMessages {
                 pool_id: pool_id.clone(),
-                messages: MESSAGES_DB.last_messages(&pool_id, max_messages_render),
+                reactions: MESSAGES_DB.message_reactions(&pool_id, &messages),
+                read_receipts: STORE_MANAGER
+                    .read_receipts(&pool_id)
+                    .into_iter()
+                    .flat_map(|(user_id, read_receipts)| {
+                        read_receipts
+                            .into_iter()
+                            .map(move |(device_id, read_receipt)| IPCReadReceipt {
+                                user_id: user_id.clone(),
+                                device_id,
+                                msg_id: read_receipt.msg_id,
+                            })
+                    })
+                    .collect(),
+                messages,
                 max_messages_render,
             },
         );
@@ -199,3 +262,70 @@ pub fn append_pool_message_event(pool_id: &String, message: PoolMessage) {
         );
     }
 }
+
+pub fn insert_pool_message_event(pool_id: &String, message: PoolMessage) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            INSERT_POOL_MESSAGE_EVENT,
+            IPCInsertPoolMessage {
+                pool_id: pool_id.clone(),
+                message,
+            },
+        );
+    }
+}
+
+pub fn update_pool_message_event(pool_id: &String, message: PoolMessage) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            UPDATE_POOL_MESSAGE_EVENT,
+            IPCUpdatePoolMessage {
+                pool_id: pool_id.clone(),
+                message,
+            },
+        );
+    }
+}
+
+pub fn update_pool_message_reactions_event(
+    pool_id: &String,
+    msg_id: String,
+    reactions: Vec<IPCMessageReaction>,
+) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            UPDATE_POOL_MESSAGE_REACTIONS_EVENT,
+            IPCPoolMessageReactions {
+                pool_id: pool_id.clone(),
+                msg_id,
+                reactions,
+            },
+        );
+    }
+}
+
+pub fn pool_ephemeral_message_event(pool_id: &String, node_id: String, message: PoolEphemeralMessage) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            POOL_EPHEMERAL_MESSAGE_EVENT,
+            IPCPoolEphemeralMessage {
+                pool_id: pool_id.clone(),
+                node_id,
+                message,
+            },
+        );
+    }
+}
+
+pub fn update_pool_read_receipt_event(pool_id: &String, read_receipt: IPCReadReceipt, unread_count: usize) {
+    if let Some(app_handle) = &*GLOBAL_APP_HANDLE.load() {
+        let _ = app_handle.emit_all(
+            UPDATE_POOL_READ_RECEIPT_EVENT,
+            IPCPoolReadReceipt {
+                pool_id: pool_id.clone(),
+                read_receipt,
+                unread_count,
+            },
+        );
+    }
+}
@@ -1,7 +1,7 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    poolpb::{PoolFileInfo, PoolFileSeeders, PoolMessage},
+    poolpb::{PoolEphemeralMessage, PoolFileInfo, PoolFileSeeders, PoolMessage},
     sspb::{PoolDeviceInfo, PoolInfo, PoolUserInfo},
 };
 
@@ -9,11 +9,28 @@ use crate::{
 pub struct IPCFileDownloadProgress {
     pub file_id: String,
     pub progress: usize,
+    pub bytes_downloaded: u64,
+    pub total_size: u64,
+    pub bytes_per_sec: u64, // over the last TRANSFER_RATE_WINDOW
+    pub eta_secs: Option<u64>,
+    pub seeder_node_ids: Vec<String>, // seeders with chunks left to send
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IPCFileUploadProgress {
+    pub pool_id: String,
+    pub file_id: String,
+    pub requesting_node_id: String,
+    pub chunks_sent: u64,
+    pub chunks_requested: u64, // chunks promised by other nodes are skipped, so it can finish short of this
+    pub bytes_per_sec: u64,
 }
 
 #[derive(Default, Clone, Serialize)]
 pub struct IPCStateUpdate {
     pub file_downloads_progress: Vec<IPCFileDownloadProgress>,
+    pub file_uploads_progress: Vec<IPCFileUploadProgress>,
+    pub connection_stats: Vec<IPCPoolNodeConnectionStats>,
 }
 
 #[derive(Default, Clone, Serialize)]
@@ -87,12 +104,32 @@ pub struct IPCRemovePoolFileOffer {
     pub file_id: String,
 }
 
+#[derive(Clone, Serialize)]
+pub struct IPCRemovePoolFolderOffer {
+    pub pool_id: String,
+    pub node_id: String,
+    pub folder_id: String,
+}
+
 #[derive(Clone, Serialize)]
 pub struct IPCInitPoolFileSeeders {
     pub pool_id: String,
     pub file_seeders: Vec<PoolFileSeeders>,
 }
 
+#[derive(Clone, Serialize)]
+pub struct IPCQueuePoolFileDownload {
+    pub pool_id: String,
+    pub file_id: String,
+    pub priority: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCStartPoolFileDownload {
+    pub pool_id: String,
+    pub file_id: String,
+}
+
 #[derive(Clone, Serialize)]
 pub struct IPCCompletePoolFileDownload {
     pub pool_id: String,
@@ -100,10 +137,32 @@ pub struct IPCCompletePoolFileDownload {
     pub success: bool,
 }
 
+#[derive(Clone, Serialize)]
+pub struct IPCMessageReaction {
+    pub emoji: String,
+    pub count: usize,
+    pub user_ids: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCMessageReactions {
+    pub msg_id: String,
+    pub reactions: Vec<IPCMessageReaction>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCPoolMessageReactions {
+    pub pool_id: String,
+    pub msg_id: String,
+    pub reactions: Vec<IPCMessageReaction>,
+}
+
 #[derive(Clone, Serialize)]
 pub struct IPCLatestPoolMessages {
     pub pool_id: String,
     pub messages: Vec<PoolMessage>,
+    pub reactions: Vec<IPCMessageReactions>,
+    pub read_receipts: Vec<IPCReadReceipt>,
     pub max_messages_render: usize, // TEMP
 }
 
@@ -114,9 +173,109 @@ pub struct IPCAppendPoolMessage {
 }
 
 #[derive(Clone, Serialize)]
+pub struct IPCPoolEphemeralMessage {
+    pub pool_id: String,
+    pub node_id: String,
+    pub message: PoolEphemeralMessage,
+}
+
+// Message that belongs before the newest message, placed by its clock
+#[derive(Clone, Serialize)]
+pub struct IPCInsertPoolMessage {
+    pub pool_id: String,
+    pub message: PoolMessage,
+}
+
+// Message that was edited or deleted in place
+#[derive(Clone, Serialize)]
+pub struct IPCUpdatePoolMessage {
+    pub pool_id: String,
+    pub message: PoolMessage,
+}
+
+#[derive(Default, Clone, Serialize)]
 pub struct IPCPoolMessageHistory {
     pub messages: Vec<PoolMessage>,
+    pub reactions: Vec<IPCMessageReactions>,
     pub chunk_lens: Vec<usize>,
     pub chunk_number: u64,
     pub is_latest: bool,
-}
\ No newline at end of file
+}
+
+#[derive(Default, Clone, Serialize)]
+pub struct IPCPoolMessageThread {
+    pub message: Option<PoolMessage>,
+    pub replies: Vec<PoolMessage>,
+    pub reactions: Vec<IPCMessageReactions>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCSearchMatch {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct IPCSearchFilters {
+    pub user_id: Option<String>,
+    pub msg_type: Option<i32>,
+    pub after: Option<u64>,
+    pub before: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCPoolMessageSearchResult {
+    pub message: PoolMessage,
+    pub chunk_number: u64,
+    pub matches: Vec<IPCSearchMatch>,
+}
+
+#[derive(Default, Clone, Serialize)]
+pub struct IPCPoolMessageSearch {
+    pub results: Vec<IPCPoolMessageSearchResult>,
+    pub has_more: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCReadReceipt {
+    pub user_id: String,
+    pub device_id: String,
+    pub msg_id: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCPoolReadReceipt {
+    pub pool_id: String,
+    pub read_receipt: IPCReadReceipt,
+    pub unread_count: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCDataChannelStats {
+    pub bytes_sent: u64,
+    pub messages_sent: u64,
+    pub bytes_received: u64,
+    pub messages_received: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCConnectionStateChange {
+    pub state: String,
+    pub elapsed_ms: u64, // since the connection was created
+}
+
+#[derive(Clone, Serialize)]
+pub struct IPCPoolNodeConnectionStats {
+    pub pool_id: String,
+    pub node_id: String,
+    pub state: String,
+    pub rtt_ms: Option<u64>, // None until a ping has come back
+    pub main_channel: IPCDataChannelStats,
+    pub chunks_channel: IPCDataChannelStats,
+    pub chunks_buffered_amount: usize,
+    pub local_candidate_type: Option<String>, // host, srflx, prflx or relay, None until a pair is selected
+    pub remote_candidate_type: Option<String>,
+    pub state_changes: Vec<IPCConnectionStateChange>,
+}
@@ -6,12 +6,12 @@
 use std::{env, sync::Arc};
 
 use app::{
-    __cmd__add_file_offer, __cmd__add_image_offer, __cmd__connect_to_pool,
-    __cmd__disconnect_from_pool, __cmd__download_file, __cmd__remove_file_download,
-    __cmd__request_message_history, __cmd__retract_file_offer, __cmd__send_text_message,
+    __cmd__add_file_offer, __cmd__add_folder_offer, __cmd__add_image_offer, __cmd__connect_to_pool, __cmd__delete_message,
+    __cmd__disconnect_from_pool, __cmd__edit_message, __cmd__download_file, __cmd__download_folder, __cmd__pause_file_download, __cmd__remove_file_download, __cmd__resume_file_download, __cmd__set_file_download_priority, __cmd__stop_file_upload, __cmd__request_max_concurrent_downloads, __cmd__set_max_concurrent_downloads, __cmd__request_bandwidth_limits, __cmd__set_bandwidth_limits, __cmd__request_ice_servers, __cmd__set_ice_servers, __cmd__request_sync_server_settings, __cmd__set_sync_server_settings, __cmd__pool_connection_stats,
+    __cmd__request_message_history, __cmd__request_message_history_by_date, __cmd__request_thread, __cmd__retract_file_offer, __cmd__retract_folder_offer, __cmd__search_messages, __cmd__send_presence, __cmd__send_reaction, __cmd__send_read_receipt, __cmd__request_unread_count, __cmd__send_text_message, __cmd__send_typing_indicator,
     commands::{
-        add_file_offer, add_image_offer, connect_to_pool, disconnect_from_pool, download_file,
-        remove_file_download, request_message_history, retract_file_offer, send_text_message, register_device, set_auth_token, add_pool, remove_pool, request_init_app,
+        add_file_offer, add_folder_offer, add_image_offer, connect_to_pool, delete_message, disconnect_from_pool, download_file, download_folder, edit_message,
+        pause_file_download, remove_file_download, resume_file_download, request_message_history, request_message_history_by_date, request_thread, retract_file_offer, retract_folder_offer, search_messages, send_presence, send_reaction, send_read_receipt, request_unread_count, send_text_message, send_typing_indicator, register_device, set_auth_token, add_pool, remove_pool, request_init_app, set_file_download_priority, stop_file_upload, request_max_concurrent_downloads, set_max_concurrent_downloads, request_bandwidth_limits, set_bandwidth_limits, request_ice_servers, set_ice_servers, request_sync_server_settings, set_sync_server_settings, pool_connection_stats,
     },
     config::PRODUCTION_MODE,
     events::init_app_event,
@@ -67,12 +67,38 @@ async fn main() {
             connect_to_pool,
             disconnect_from_pool,
             send_text_message,
+            edit_message,
+            delete_message,
+            send_reaction,
+            send_read_receipt,
+            request_unread_count,
+            send_typing_indicator,
+            send_presence,
             add_file_offer,
             add_image_offer,
+            add_folder_offer,
             download_file,
+            download_folder,
             retract_file_offer,
+            retract_folder_offer,
             remove_file_download,
+            pause_file_download,
+            resume_file_download,
+            set_file_download_priority,
+            stop_file_upload,
+            request_max_concurrent_downloads,
+            set_max_concurrent_downloads,
+            request_bandwidth_limits,
+            set_bandwidth_limits,
+            request_ice_servers,
+            set_ice_servers,
+            request_sync_server_settings,
+            set_sync_server_settings,
+            pool_connection_stats,
             request_message_history,
+            request_message_history_by_date,
+            request_thread,
+            search_messages,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
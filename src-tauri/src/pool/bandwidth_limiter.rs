@@ -0,0 +1,163 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::config::{BANDWIDTH_LIMIT_BURST, CHUNK_SIZE};
+
+struct TokenBucket {
+    tokens: f64, // bytes, negative while paying off a reservation
+    last_refill_instant: Instant,
+}
+
+// Token bucket in bytes per second, a rate of 0 is unlimited. Tokens are allowed to go
+// negative so chunks are never split, whoever comes after just waits longer
+pub(super) struct BandwidthLimiter {
+    rate: AtomicU64,
+    bucket: Mutex<TokenBucket>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(rate: u64) -> Self {
+        BandwidthLimiter {
+            rate: AtomicU64::new(rate),
+            bucket: Mutex::new(TokenBucket {
+                tokens: capacity(rate),
+                last_refill_instant: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        let mut bucket = self.bucket.lock();
+        let prev_rate = self.rate.swap(rate, Ordering::Relaxed);
+        if prev_rate != 0 {
+            refill(&mut bucket, prev_rate);
+        }
+
+        // Going from unlimited starts off with a full bucket
+        bucket.tokens = if prev_rate == 0 {
+            capacity(rate)
+        } else {
+            bucket.tokens.min(capacity(rate))
+        };
+        bucket.last_refill_instant = Instant::now();
+    }
+
+    // Waits until the rate allows for the bytes to be sent
+    pub async fn acquire(&self, bytes: u64) {
+        if let Some(wait) = self.reserve(bytes) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // Takes as many whole units as the tokens cover, up to max_units
+    pub fn take_units(&self, max_units: u64, unit_size: u64) -> u64 {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return max_units;
+        }
+
+        let mut bucket = self.bucket.lock();
+        refill(&mut bucket, rate);
+
+        let units = ((bucket.tokens.max(0.0) / unit_size as f64) as u64).min(max_units);
+        bucket.tokens -= (units * unit_size) as f64;
+        units
+    }
+
+    fn reserve(&self, bytes: u64) -> Option<Duration> {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return None;
+        }
+
+        let mut bucket = self.bucket.lock();
+        refill(&mut bucket, rate);
+
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-bucket.tokens / rate as f64))
+        }
+    }
+}
+
+fn refill(bucket: &mut TokenBucket, rate: u64) {
+    let elapsed = bucket.last_refill_instant.elapsed().as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * rate as f64).min(capacity(rate));
+    bucket.last_refill_instant = Instant::now();
+}
+
+// Unused bandwidth is saved up for a short burst. At least a chunk or rates below it would
+// never get one through
+fn capacity(rate: u64) -> f64 {
+    (rate as f64 * BANDWIDTH_LIMIT_BURST.as_secs_f64()).max(CHUNK_SIZE as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{capacity, BandwidthLimiter};
+
+    const RATE: u64 = 1024 * 1024;
+
+    // Moves the last refill back instead of sleeping
+    fn elapse(limiter: &BandwidthLimiter, elapsed: Duration) {
+        let mut bucket = limiter.bucket.lock();
+        bucket.last_refill_instant = Instant::now() - elapsed;
+    }
+
+    fn tokens(limiter: &BandwidthLimiter) -> f64 {
+        limiter.bucket.lock().tokens
+    }
+
+    #[test]
+    fn unlimited() {
+        let limiter = BandwidthLimiter::new(0);
+        assert_eq!(limiter.reserve(u64::MAX), None);
+        assert_eq!(limiter.take_units(100, 1024), 100);
+    }
+
+    #[test]
+    fn refill_up_to_capacity() {
+        let limiter = BandwidthLimiter::new(RATE);
+        let full = capacity(RATE) as u64;
+        assert_eq!(limiter.take_units(u64::MAX, 1), full);
+
+        elapse(&limiter, Duration::from_millis(500));
+        let refilled = limiter.take_units(u64::MAX, 1);
+        assert!(refilled >= RATE / 2 && refilled < RATE / 2 + RATE / 10);
+
+        elapse(&limiter, Duration::from_secs(60));
+        assert_eq!(limiter.take_units(u64::MAX, 1), full);
+    }
+
+    #[test]
+    fn negative_debt() {
+        let limiter = BandwidthLimiter::new(RATE);
+        let full = capacity(RATE) as u64;
+
+        // A reservation past the tokens goes through and is paid off by waiting
+        assert_eq!(limiter.reserve(full), None);
+        let wait = limiter.reserve(RATE).unwrap();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        assert!(tokens(&limiter) < 0.0);
+
+        // Nothing is handed out while in debt
+        assert_eq!(limiter.take_units(u64::MAX, 1), 0);
+        assert!(tokens(&limiter) < 0.0);
+
+        // Whoever comes next waits for the debt on top of their own bytes
+        let wait = limiter.reserve(RATE).unwrap();
+        assert!(wait > Duration::from_millis(1900) && wait <= Duration::from_secs(2));
+
+        // Paid off after the debt has been refilled
+        elapse(&limiter, Duration::from_secs(3));
+        assert!(limiter.take_units(u64::MAX, 1) > 0);
+    }
+}
@@ -8,6 +8,7 @@ use std::sync::atomic::{AtomicIsize, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use bytes::Bytes;
 use flume::{Receiver, Sender};
 use parking_lot::Mutex;
 
@@ -39,6 +40,7 @@ struct CacheChunk {
     // Any writes/reads have to acquire this lock
     active_lock: Arc<Mutex<()>>,
     chunk_ranges: ChunkRanges,
+    chunk_proofs: HashMap<u64, Vec<Bytes>>, // chunk_number -> merkle proof
 }
 
 struct CacheChunks {
@@ -229,7 +231,7 @@ impl CacheManager {
         &self,
         file_handle: &mut File,
         mut writer_head: usize,
-        chunk_msg: PoolChunkMessage,
+        mut chunk_msg: PoolChunkMessage,
     ) -> usize {
         let cache_chunk_number = chunk_number_to_cache_chunk_number(chunk_msg.chunk_number);
 
@@ -299,6 +301,7 @@ impl CacheManager {
                     promised_requests: HashMap::new(),
                     active_lock: Arc::new(Mutex::new(())),
                     chunk_ranges: Vec::with_capacity(1),
+                    chunk_proofs: HashMap::new(),
                 };
 
                 let cache_chunk_map = cache_chunks
@@ -312,6 +315,9 @@ impl CacheManager {
             let active_lock = match &mut cache_chunks.cache[cache_chunk_pos] {
                 Some(cache_chunk) => {
                     cache_chunk.chunk_ranges.add_chunk(chunk_msg.chunk_number);
+                    cache_chunk
+                        .chunk_proofs
+                        .insert(chunk_msg.chunk_number, mem::take(&mut chunk_msg.proof));
                     cache_chunk.active_lock.clone()
                 }
                 None => return writer_head,
@@ -371,6 +377,7 @@ impl CacheManager {
                             start: chunk_msg.chunk_number,
                             end: chunk_msg.chunk_number,
                         }]);
+                    cache_chunk.chunk_proofs.remove(&chunk_msg.chunk_number);
                 }
             }
         }
@@ -391,6 +398,7 @@ impl CacheManager {
                     file_id,
                     cache_chunk_pos,
                     chunk_ranges,
+                    chunk_proofs,
                     mut promised_requests_map,
                     active_lock,
                 ) = {
@@ -412,6 +420,7 @@ impl CacheManager {
                         cache_chunk.file_id.clone(),
                         cache_chunk_pos,
                         cache_chunk.chunk_ranges.clone(),
+                        cache_chunk.chunk_proofs.clone(),
                         mem::take(&mut cache_chunk.promised_requests),
                         cache_chunk.active_lock.clone(),
                     )
@@ -478,6 +487,7 @@ impl CacheManager {
                             file_id.clone(),
                             chunk_number,
                             buf,
+                            chunk_proofs.get(&chunk_number).cloned().unwrap_or_default(),
                             Some(dest_node_ids),
                             send_to_self,
                         );
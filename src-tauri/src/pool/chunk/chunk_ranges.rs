@@ -45,6 +45,10 @@ pub trait ChunkRangesUtil {
     fn has_chunk(&self, chunk_number: u64) -> bool;
 
     fn find_chunk_range(&self, chunk_number: u64) -> Option<&PoolChunkRange>;
+
+    fn chunks_len(&self) -> u64;
+
+    fn take_chunks(&mut self, count: u64) -> ChunkRanges;
 }
 
 impl ChunkRangesUtil for ChunkRanges {
@@ -349,4 +353,32 @@ impl ChunkRangesUtil for ChunkRanges {
         }
         None
     }
+
+    // Number of chunks in all chunk ranges
+    fn chunks_len(&self) -> u64 {
+        self.iter()
+            .map(|chunk_range| chunk_range.end - chunk_range.start + 1)
+            .sum()
+    }
+
+    // Removes and returns the first count chunks
+    fn take_chunks(&mut self, count: u64) -> ChunkRanges {
+        let mut taken = ChunkRanges::new();
+        let mut count = count;
+        while count > 0 && !self.is_empty() {
+            let chunk_range_len = self[0].end - self[0].start + 1;
+            if chunk_range_len <= count {
+                count -= chunk_range_len;
+                taken.push(self.remove(0));
+            } else {
+                taken.push(PoolChunkRange {
+                    start: self[0].start,
+                    end: self[0].start + count - 1,
+                });
+                self[0].start += count;
+                count = 0;
+            }
+        }
+        taken
+    }
 }
@@ -0,0 +1,295 @@
+use std::{fs::File, io::Read, path::PathBuf};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use crate::config::CHUNK_SIZE;
+
+use super::chunk_util::total_size_to_total_chunks;
+
+type Hash = [u8; 32];
+
+const EMPTY_HASH: Hash = [0u8; 32];
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+// Hash tree over the chunks of a file, the root is the file hash in PoolFileInfo.
+// Nodes are stored heap style (root at 1, children of i at 2i and 2i + 1) so a
+// download can fill it in from the proofs that come with each chunk and serve
+// proofs for the chunks it already has.
+pub struct MerkleTree {
+    total_chunks: u64,
+    leaves_len: usize,
+    nodes: Vec<Option<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(total_size: u64) -> Self {
+        let total_chunks = total_size_to_total_chunks(total_size);
+        let leaves_len = (total_chunks.max(1) as usize).next_power_of_two();
+
+        let mut nodes = vec![None; leaves_len * 2];
+        for i in (leaves_len + total_chunks as usize)..nodes.len() {
+            nodes[i] = Some(EMPTY_HASH);
+        }
+
+        // Subtrees that only cover padding are known up front
+        for i in (1..leaves_len).rev() {
+            if let (Some(left), Some(right)) = (nodes[i * 2], nodes[i * 2 + 1]) {
+                nodes[i] = Some(node_hash(&left, &right));
+            }
+        }
+
+        MerkleTree {
+            total_chunks,
+            leaves_len,
+            nodes,
+        }
+    }
+
+    pub fn from_file(path: &PathBuf, total_size: u64) -> Option<Self> {
+        let mut file_handle = File::open(path).ok()?;
+        let mut merkle_tree = Self::new(total_size);
+
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        for chunk_number in 0..merkle_tree.total_chunks {
+            let chunk_size = (total_size - chunk_number * CHUNK_SIZE as u64).min(CHUNK_SIZE as u64);
+            let chunk = &mut buf[..chunk_size as usize];
+            file_handle.read_exact(chunk).ok()?;
+            merkle_tree.nodes[merkle_tree.leaves_len + chunk_number as usize] =
+                Some(leaf_hash(chunk));
+        }
+
+        for i in (1..merkle_tree.leaves_len).rev() {
+            let left = merkle_tree.nodes[i * 2]?;
+            let right = merkle_tree.nodes[i * 2 + 1]?;
+            merkle_tree.nodes[i] = Some(node_hash(&left, &right));
+        }
+
+        Some(merkle_tree)
+    }
+
+    pub fn root_hex(&self) -> Option<String> {
+        self.nodes[1].map(|root| to_hex(&root))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.nodes[1..].iter().all(|node| node.is_some())
+    }
+
+    // Sibling hashes from the chunk's leaf up to the root
+    pub fn proof(&self, chunk_number: u64) -> Option<Vec<Bytes>> {
+        let mut i = self.leaf_index(chunk_number)?;
+        let mut proof = Vec::with_capacity(self.depth());
+        while i > 1 {
+            proof.push(Bytes::copy_from_slice(&self.nodes[i ^ 1]?));
+            i /= 2;
+        }
+        Some(proof)
+    }
+
+    // Checks the chunk against the root, the proof's hashes are kept so proofs can be
+    // served for the chunk afterwards
+    pub fn verify_chunk(
+        &mut self,
+        root_hex: &str,
+        chunk_number: u64,
+        chunk: &[u8],
+        proof: &[Bytes],
+    ) -> bool {
+        let mut i = match self.leaf_index(chunk_number) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        if proof.len() != self.depth() {
+            return false;
+        }
+
+        let mut verified_nodes: Vec<(usize, Hash)> = Vec::with_capacity(proof.len() * 2 + 1);
+        let mut hash = leaf_hash(chunk);
+        for sibling in proof {
+            let sibling: Hash = match sibling.as_ref().try_into() {
+                Ok(sibling) => sibling,
+                Err(_) => return false,
+            };
+
+            verified_nodes.push((i, hash));
+            verified_nodes.push((i ^ 1, sibling));
+
+            hash = if i % 2 == 0 {
+                node_hash(&hash, &sibling)
+            } else {
+                node_hash(&sibling, &hash)
+            };
+            i /= 2;
+        }
+
+        if to_hex(&hash) != root_hex {
+            return false;
+        }
+
+        verified_nodes.push((1, hash));
+        for (i, hash) in verified_nodes {
+            self.nodes[i] = Some(hash);
+        }
+
+        true
+    }
+
+    // Chunks that don't match the verified nodes, file_tree being built from the downloaded file.
+    // A chunk is checked against the lowest verified node above it, so a bad chunk also fails
+    // the chunks under that node that were never verified on their own
+    pub fn mismatched_chunks(&self, file_tree: &MerkleTree) -> Vec<u64> {
+        let mut mismatched_chunks = Vec::new();
+        for chunk_number in 0..self.total_chunks {
+            let mut i = self.leaves_len + chunk_number as usize;
+            while i > 1 && self.nodes[i].is_none() {
+                i /= 2;
+            }
+
+            if self.nodes[i].is_none() || self.nodes[i] != file_tree.nodes[i] {
+                mismatched_chunks.push(chunk_number);
+            }
+        }
+        mismatched_chunks
+    }
+
+    fn depth(&self) -> usize {
+        self.leaves_len.trailing_zeros() as usize
+    }
+
+    fn leaf_index(&self, chunk_number: u64) -> Option<usize> {
+        if chunk_number >= self.total_chunks {
+            return None;
+        }
+        Some(self.leaves_len + chunk_number as usize)
+    }
+}
+
+// Leaves and nodes are prefixed differently so a node can't pass as a chunk
+fn leaf_hash(chunk: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(chunk);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn to_hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::config::CHUNK_SIZE;
+
+    use super::MerkleTree;
+
+    // Three chunks, the last one short, so the tree has a padding leaf
+    const TOTAL_SIZE: u64 = 2 * CHUNK_SIZE as u64 + 100;
+
+    fn test_file(name: &str) -> (PathBuf, Vec<u8>) {
+        let data: Vec<u8> = (0..TOTAL_SIZE).map(|i| (i % 251) as u8).collect();
+        let mut path = std::env::temp_dir();
+        path.push(format!("merkle_tree_{}_{}", name, std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        (path, data)
+    }
+
+    fn chunk(data: &[u8], chunk_number: u64) -> &[u8] {
+        let start = chunk_number as usize * CHUNK_SIZE;
+        &data[start..(start + CHUNK_SIZE).min(data.len())]
+    }
+
+    #[test]
+    fn verify_chunks() {
+        let (path, data) = test_file("verify");
+        let file_tree = MerkleTree::from_file(&path, TOTAL_SIZE).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let root_hex = file_tree.root_hex().unwrap();
+
+        let mut merkle_tree = MerkleTree::new(TOTAL_SIZE);
+        assert!(!merkle_tree.is_complete());
+
+        for chunk_number in 0..3 {
+            let proof = file_tree.proof(chunk_number).unwrap();
+            assert!(merkle_tree.verify_chunk(
+                &root_hex,
+                chunk_number,
+                chunk(&data, chunk_number),
+                &proof
+            ));
+        }
+
+        // Proofs are served for verified chunks the same as from the file
+        assert!(merkle_tree.is_complete());
+        assert_eq!(merkle_tree.root_hex(), Some(root_hex));
+        for chunk_number in 0..3 {
+            assert_eq!(
+                merkle_tree.proof(chunk_number),
+                file_tree.proof(chunk_number)
+            );
+        }
+        assert!(merkle_tree.mismatched_chunks(&file_tree).is_empty());
+    }
+
+    #[test]
+    fn reject_chunks() {
+        let (path, data) = test_file("reject");
+        let file_tree = MerkleTree::from_file(&path, TOTAL_SIZE).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let root_hex = file_tree.root_hex().unwrap();
+
+        let mut merkle_tree = MerkleTree::new(TOTAL_SIZE);
+        let proof = file_tree.proof(0).unwrap();
+
+        let mut tampered = chunk(&data, 0).to_vec();
+        tampered[0] ^= 1;
+        assert!(!merkle_tree.verify_chunk(&root_hex, 0, &tampered, &proof));
+
+        // Someone else's proof, a truncated proof, a chunk past the end and the wrong root
+        assert!(!merkle_tree.verify_chunk(&root_hex, 1, chunk(&data, 0), &proof));
+        assert!(!merkle_tree.verify_chunk(&root_hex, 0, chunk(&data, 0), &proof[1..]));
+        assert!(!merkle_tree.verify_chunk(&root_hex, 3, chunk(&data, 0), &proof));
+        assert!(!merkle_tree.verify_chunk(&"00".repeat(32), 0, chunk(&data, 0), &proof));
+
+        // Nothing from a rejected chunk is kept
+        assert_eq!(merkle_tree.root_hex(), None);
+        assert_eq!(merkle_tree.proof(1), None);
+    }
+
+    #[test]
+    fn mismatched_chunks() {
+        let (path, data) = test_file("mismatched");
+        let file_tree = MerkleTree::from_file(&path, TOTAL_SIZE).unwrap();
+        let root_hex = file_tree.root_hex().unwrap();
+
+        // Chunk 0 verified, chunks 1 and 2 resumed with chunk 2 corrupted on disk
+        let mut merkle_tree = MerkleTree::new(TOTAL_SIZE);
+        assert!(merkle_tree.verify_chunk(
+            &root_hex,
+            0,
+            chunk(&data, 0),
+            &file_tree.proof(0).unwrap()
+        ));
+
+        let mut corrupted = data.clone();
+        corrupted[2 * CHUNK_SIZE] ^= 1;
+        std::fs::write(&path, &corrupted).unwrap();
+        let corrupted_tree = MerkleTree::from_file(&path, TOTAL_SIZE).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // Chunk 1's hash came with chunk 0's proof, chunk 2 is only covered by its parent
+        assert_eq!(merkle_tree.mismatched_chunks(&corrupted_tree), vec![2]);
+    }
+}
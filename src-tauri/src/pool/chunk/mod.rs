@@ -1,3 +1,4 @@
 pub mod chunk_range;
 pub mod chunk_ranges;
-pub mod chunk_util;
\ No newline at end of file
+pub mod chunk_util;
+pub mod merkle_tree;
\ No newline at end of file
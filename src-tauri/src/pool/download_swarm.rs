@@ -0,0 +1,531 @@
+use std::{collections::HashSet, time::Instant};
+
+use crate::config::{
+    CHUNK_SIZE, MAX_CHUNKS_MISSING_RETRY, MAX_SWARM_SEEDERS, SWARM_MIN_TAKE_OVER_CHUNKS,
+    SWARM_REBALANCE_INTERVAL, SWARM_STALL_TIMEOUT,
+};
+
+use super::{
+    bandwidth_limiter::BandwidthLimiter,
+    chunk::chunk_ranges::{ChunkRanges, ChunkRangesUtil},
+};
+
+pub(super) struct SwarmRequest {
+    pub node_id: String,
+    pub chunk_ranges: ChunkRanges,
+    pub request_from_origin: bool,
+    pub replace: bool,
+}
+
+struct SeederAssignment {
+    node_id: String,
+    chunk_ranges: ChunkRanges, // assigned and not downloaded yet
+    chunks_received: u64,      // since the last rebalance
+    throughput: f64,           // chunks per second
+    last_chunk_instant: Instant,
+    retry_count: usize,
+    has_invalid_chunk: bool,
+    prioritized_chunks: ChunkRanges, // requested ahead of the rest of its ranges
+}
+
+// Splits a download across the closest seeders. Seeders that finish their ranges take
+// over the back half of the seeder furthest from finishing, stalled seeders are retried
+// and then dropped with their ranges going to the others
+pub(super) struct DownloadSwarm {
+    assignments: Vec<SeederAssignment>,
+    excluded_seeders: HashSet<String>, // stalled or sent invalid chunks
+    last_rebalance_instant: Instant,
+}
+
+impl DownloadSwarm {
+    pub fn new() -> Self {
+        DownloadSwarm {
+            assignments: Vec::new(),
+            excluded_seeders: HashSet::new(),
+            last_rebalance_instant: Instant::now(),
+        }
+    }
+
+    pub fn node_ids(&self) -> Vec<String> {
+        self.assignments
+            .iter()
+            .map(|assignment| assignment.node_id.clone())
+            .collect()
+    }
+
+    // Seeders that still have chunks left to send
+    pub fn serving_node_ids(&self) -> Vec<String> {
+        self.assignments
+            .iter()
+            .filter(|assignment| !assignment.chunk_ranges.is_empty())
+            .map(|assignment| assignment.node_id.clone())
+            .collect()
+    }
+
+    pub fn should_rebalance(&self) -> bool {
+        self.last_rebalance_instant.elapsed() >= SWARM_REBALANCE_INTERVAL
+    }
+
+    // Returns true if there are seeders left that haven't been excluded
+    pub fn has_valid_seeders(&self, seeders: &Vec<String>) -> bool {
+        seeders
+            .iter()
+            .any(|seeder| !self.excluded_seeders.contains(seeder))
+    }
+
+    pub fn add_chunk(&mut self, chunk_number: u64) {
+        if let Some(assignment) = self.find_assignment(chunk_number) {
+            assignment.chunks_received += 1;
+            assignment.last_chunk_instant = Instant::now();
+        }
+    }
+
+    pub fn add_invalid_chunk(&mut self, chunk_number: u64) {
+        if let Some(assignment) = self.find_assignment(chunk_number) {
+            assignment.has_invalid_chunk = true;
+        }
+    }
+
+    // Seeders holding any of the chunks are asked for just those first, the rest of their
+    // ranges are requested again on the rebalance after they're in
+    pub fn prioritize(&mut self, chunk_ranges: &ChunkRanges) -> Vec<SwarmRequest> {
+        let mut requests = Vec::new();
+
+        for assignment in self.assignments.iter_mut() {
+            let prioritized_chunks = assignment.chunk_ranges.intersection(chunk_ranges);
+            if prioritized_chunks.is_empty() || prioritized_chunks == assignment.prioritized_chunks
+            {
+                continue;
+            }
+
+            assignment.prioritized_chunks = prioritized_chunks.clone();
+            assignment.last_chunk_instant = Instant::now();
+
+            requests.push(SwarmRequest {
+                node_id: assignment.node_id.clone(),
+                chunk_ranges: prioritized_chunks,
+                request_from_origin: false,
+                replace: true,
+            });
+        }
+
+        requests
+    }
+
+    // Seeders are expected to be active and sorted by distance
+    pub fn rebalance(
+        &mut self,
+        seeders: &Vec<String>,
+        chunks_missing: &ChunkRanges,
+        download_limiter: &BandwidthLimiter,
+    ) -> Vec<SwarmRequest> {
+        let elapsed = self.last_rebalance_instant.elapsed().as_secs_f64();
+        self.last_rebalance_instant = Instant::now();
+
+        self.assignments
+            .retain(|assignment| seeders.contains(&assignment.node_id));
+
+        for assignment in self.assignments.iter_mut() {
+            assignment.chunk_ranges = assignment.chunk_ranges.intersection(chunks_missing);
+
+            if elapsed > 0.0 {
+                let throughput = assignment.chunks_received as f64 / elapsed;
+                assignment.throughput = if assignment.throughput == 0.0 {
+                    throughput
+                } else {
+                    (assignment.throughput + throughput) / 2.0
+                };
+            }
+            assignment.chunks_received = 0;
+        }
+
+        let mut requests = self.retry_stalled();
+
+        for assignment in self.assignments.iter_mut() {
+            if assignment.prioritized_chunks.is_empty() {
+                continue;
+            }
+
+            assignment.prioritized_chunks =
+                assignment.prioritized_chunks.intersection(chunks_missing);
+            if assignment.prioritized_chunks.is_empty() && !assignment.chunk_ranges.is_empty() {
+                requests.push(SwarmRequest {
+                    node_id: assignment.node_id.clone(),
+                    chunk_ranges: assignment.chunk_ranges.clone(),
+                    request_from_origin: false,
+                    replace: true,
+                });
+            }
+        }
+
+        let mut assigned = ChunkRanges::new();
+        for assignment in self.assignments.iter() {
+            for chunk_range in assignment.chunk_ranges.iter() {
+                assigned.add(chunk_range);
+            }
+        }
+        let mut unassigned = chunks_missing.diff(&assigned);
+
+        for seeder in seeders {
+            if self.assignments.len() >= MAX_SWARM_SEEDERS {
+                break;
+            }
+
+            if self.excluded_seeders.contains(seeder)
+                || self
+                    .assignments
+                    .iter()
+                    .any(|assignment| &assignment.node_id == seeder)
+            {
+                continue;
+            }
+
+            self.assignments.push(SeederAssignment {
+                node_id: seeder.clone(),
+                chunk_ranges: ChunkRanges::new(),
+                chunks_received: 0,
+                throughput: 0.0,
+                last_chunk_instant: Instant::now(),
+                retry_count: 0,
+                has_invalid_chunk: false,
+                prioritized_chunks: ChunkRanges::new(),
+            });
+        }
+
+        let idle: Vec<usize> = (0..self.assignments.len())
+            .filter(|&i| self.assignments[i].chunk_ranges.is_empty())
+            .collect();
+
+        if idle.is_empty() {
+            return requests;
+        }
+
+        if !unassigned.is_empty() {
+            // Only what the download limit allows for, the rest waits for a later rebalance
+            let budget = download_limiter.take_units(unassigned.chunks_len(), CHUNK_SIZE as u64);
+            let mut unassigned = unassigned.take_chunks(budget);
+
+            // Shared out by throughput, seeders without one yet get the average
+            let weights: Vec<f64> = idle.iter().map(|&i| self.weight(i)).collect();
+            let total_weight: f64 = weights.iter().sum();
+            let unassigned_len = unassigned.chunks_len();
+
+            for (n, &i) in idle.iter().enumerate() {
+                let count = if n == idle.len() - 1 {
+                    unassigned.chunks_len()
+                } else {
+                    ((unassigned_len as f64 * weights[n] / total_weight).ceil() as u64).max(1)
+                };
+
+                let chunk_ranges = unassigned.take_chunks(count);
+                if chunk_ranges.is_empty() {
+                    break;
+                }
+
+                requests.push(self.assign(i, chunk_ranges));
+            }
+        } else {
+            for i in idle {
+                let (chunk_ranges, kept_request) = match self.take_over_ranges() {
+                    Some(take_over) => take_over,
+                    None => break,
+                };
+
+                requests.extend(kept_request);
+                requests.push(self.assign(i, chunk_ranges));
+            }
+        }
+
+        requests
+    }
+
+    // A stalled seeder is requested again up to MAX_CHUNKS_MISSING_RETRY times with the
+    // last request going to the origin, invalid chunks skip straight to it
+    fn retry_stalled(&mut self) -> Vec<SwarmRequest> {
+        let mut requests = Vec::new();
+
+        let mut i = 0;
+        while i < self.assignments.len() {
+            let assignment = &mut self.assignments[i];
+            if assignment.chunk_ranges.is_empty() {
+                assignment.has_invalid_chunk = false;
+                i += 1;
+                continue;
+            }
+
+            if assignment.has_invalid_chunk {
+                assignment.has_invalid_chunk = false;
+                if assignment.retry_count < MAX_CHUNKS_MISSING_RETRY {
+                    assignment.retry_count = MAX_CHUNKS_MISSING_RETRY - 1;
+                } else {
+                    self.exclude(i);
+                    continue;
+                }
+            } else if assignment.last_chunk_instant.elapsed() < SWARM_STALL_TIMEOUT {
+                i += 1;
+                continue;
+            } else if assignment.retry_count >= MAX_CHUNKS_MISSING_RETRY {
+                self.exclude(i);
+                continue;
+            }
+
+            assignment.retry_count += 1;
+            assignment.last_chunk_instant = Instant::now();
+
+            requests.push(SwarmRequest {
+                node_id: assignment.node_id.clone(),
+                chunk_ranges: assignment.chunk_ranges.clone(),
+                request_from_origin: assignment.retry_count == MAX_CHUNKS_MISSING_RETRY,
+                replace: false,
+            });
+
+            i += 1;
+        }
+
+        requests
+    }
+
+    // The seeder furthest from finishing keeps the front half of its ranges and its request is
+    // replaced with just that half so it stops sending the back half
+    fn take_over_ranges(&mut self) -> Option<(ChunkRanges, Option<SwarmRequest>)> {
+        let mut slowest: Option<(usize, f64)> = None;
+        for i in 0..self.assignments.len() {
+            let chunks_len = self.assignments[i].chunk_ranges.chunks_len();
+            if chunks_len < SWARM_MIN_TAKE_OVER_CHUNKS {
+                continue;
+            }
+
+            let time_left = chunks_len as f64 / self.weight(i);
+            match slowest {
+                Some((_, slowest_time_left)) if slowest_time_left >= time_left => {}
+                _ => slowest = Some((i, time_left)),
+            }
+        }
+
+        let assignment = &mut self.assignments[slowest?.0];
+        let chunks_len = assignment.chunk_ranges.chunks_len();
+        let kept = assignment
+            .chunk_ranges
+            .take_chunks(chunks_len - chunks_len / 2);
+        let taken = std::mem::replace(&mut assignment.chunk_ranges, kept);
+
+        // Prioritized chunks it still has to send go first, the rebalance after they're in
+        // requests the kept half
+        assignment.prioritized_chunks = assignment
+            .prioritized_chunks
+            .intersection(&assignment.chunk_ranges);
+        let kept_request = if assignment.prioritized_chunks.is_empty() {
+            Some(SwarmRequest {
+                node_id: assignment.node_id.clone(),
+                chunk_ranges: assignment.chunk_ranges.clone(),
+                request_from_origin: false,
+                replace: true,
+            })
+        } else {
+            None
+        };
+
+        Some((taken, kept_request))
+    }
+
+    fn assign(&mut self, i: usize, chunk_ranges: ChunkRanges) -> SwarmRequest {
+        let assignment = &mut self.assignments[i];
+        assignment.chunk_ranges = chunk_ranges.clone();
+        assignment.last_chunk_instant = Instant::now();
+        assignment.retry_count = 0;
+        assignment.prioritized_chunks = ChunkRanges::new();
+
+        SwarmRequest {
+            node_id: assignment.node_id.clone(),
+            chunk_ranges,
+            request_from_origin: false,
+            replace: false,
+        }
+    }
+
+    fn exclude(&mut self, i: usize) {
+        let assignment = self.assignments.remove(i);
+        log::debug!("download_swarm : excluding seeder {}", assignment.node_id);
+        self.excluded_seeders.insert(assignment.node_id);
+    }
+
+    fn weight(&self, i: usize) -> f64 {
+        if self.assignments[i].throughput > 0.0 {
+            return self.assignments[i].throughput;
+        }
+
+        let throughputs: Vec<f64> = self
+            .assignments
+            .iter()
+            .map(|assignment| assignment.throughput)
+            .filter(|&throughput| throughput > 0.0)
+            .collect();
+
+        if throughputs.is_empty() {
+            1.0
+        } else {
+            throughputs.iter().sum::<f64>() / throughputs.len() as f64
+        }
+    }
+
+    fn find_assignment(&mut self, chunk_number: u64) -> Option<&mut SeederAssignment> {
+        self.assignments
+            .iter_mut()
+            .find(|assignment| assignment.chunk_ranges.has_chunk(chunk_number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::{
+        config::{MAX_CHUNKS_MISSING_RETRY, SWARM_STALL_TIMEOUT},
+        poolpb::PoolChunkRange,
+    };
+
+    use super::{BandwidthLimiter, ChunkRanges, DownloadSwarm, SwarmRequest};
+
+    fn range(start: u64, end: u64) -> PoolChunkRange {
+        PoolChunkRange { start, end }
+    }
+
+    fn seeders(node_ids: &[&str]) -> Vec<String> {
+        node_ids.iter().map(|node_id| node_id.to_string()).collect()
+    }
+
+    // Moves the last chunk back instead of sleeping
+    fn stall(swarm: &mut DownloadSwarm, node_id: &str) {
+        let assignment = swarm
+            .assignments
+            .iter_mut()
+            .find(|assignment| assignment.node_id == node_id)
+            .unwrap();
+        assignment.last_chunk_instant = Instant::now() - SWARM_STALL_TIMEOUT;
+    }
+
+    fn rebalance(
+        swarm: &mut DownloadSwarm,
+        seeders: &Vec<String>,
+        chunks_missing: &ChunkRanges,
+    ) -> Vec<SwarmRequest> {
+        swarm.rebalance(seeders, chunks_missing, &BandwidthLimiter::new(0))
+    }
+
+    fn assert_request(
+        request: &SwarmRequest,
+        node_id: &str,
+        chunk_ranges: ChunkRanges,
+        request_from_origin: bool,
+        replace: bool,
+    ) {
+        assert_eq!(request.node_id, node_id);
+        assert_eq!(request.chunk_ranges, chunk_ranges);
+        assert_eq!(request.request_from_origin, request_from_origin);
+        assert_eq!(request.replace, replace);
+    }
+
+    #[test]
+    fn split_across_seeders() {
+        let mut swarm = DownloadSwarm::new();
+        let seeders = seeders(&["a", "b", "c"]);
+
+        let requests = rebalance(&mut swarm, &seeders, &vec![range(0, 299)]);
+        assert_eq!(requests.len(), 3);
+        assert_request(&requests[0], "a", vec![range(0, 99)], false, false);
+        assert_request(&requests[1], "b", vec![range(100, 199)], false, false);
+        assert_request(&requests[2], "c", vec![range(200, 299)], false, false);
+
+        assert!(rebalance(&mut swarm, &seeders, &vec![range(0, 299)]).is_empty());
+    }
+
+    #[test]
+    fn take_over_from_slowest() {
+        let mut swarm = DownloadSwarm::new();
+        let seeders = seeders(&["a", "b"]);
+        rebalance(&mut swarm, &seeders, &vec![range(0, 199)]);
+
+        // a is done and takes the back half of b's ranges
+        let requests = rebalance(&mut swarm, &seeders, &vec![range(100, 199)]);
+        assert_eq!(requests.len(), 2);
+        assert_request(&requests[0], "b", vec![range(100, 149)], false, true);
+        assert_request(&requests[1], "a", vec![range(150, 199)], false, false);
+    }
+
+    #[test]
+    fn stalled_seeder_reassigned() {
+        let mut swarm = DownloadSwarm::new();
+        let seeders = seeders(&["a", "b", "c"]);
+        rebalance(&mut swarm, &seeders, &vec![range(0, 299)]);
+
+        for retry_count in 1..=MAX_CHUNKS_MISSING_RETRY {
+            stall(&mut swarm, "a");
+            let requests = rebalance(&mut swarm, &seeders, &vec![range(0, 299)]);
+            assert_eq!(requests.len(), 1);
+            assert_request(
+                &requests[0],
+                "a",
+                vec![range(0, 99)],
+                retry_count == MAX_CHUNKS_MISSING_RETRY,
+                false,
+            );
+        }
+
+        // b is done and gets a's ranges once a is out of retries
+        stall(&mut swarm, "a");
+        let chunks_missing = vec![range(0, 99), range(200, 299)];
+        let requests = rebalance(&mut swarm, &seeders, &chunks_missing);
+        assert_eq!(requests.len(), 1);
+        assert_request(&requests[0], "b", vec![range(0, 99)], false, false);
+        assert_eq!(swarm.node_ids(), seeders[1..].to_vec());
+    }
+
+    #[test]
+    fn excluded_seeder() {
+        let mut swarm = DownloadSwarm::new();
+        let seeders = seeders(&["a", "b"]);
+        rebalance(&mut swarm, &seeders, &vec![range(0, 99)]);
+
+        // An invalid chunk goes straight to the last retry
+        swarm.add_invalid_chunk(10);
+        let requests = rebalance(&mut swarm, &seeders, &vec![range(0, 99)]);
+        assert_eq!(requests.len(), 1);
+        assert_request(&requests[0], "a", vec![range(0, 49)], true, false);
+
+        swarm.add_invalid_chunk(10);
+        assert!(rebalance(&mut swarm, &seeders, &vec![range(0, 99)]).is_empty());
+        assert_eq!(swarm.node_ids(), vec!["b".to_string()]);
+        assert!(!swarm.has_valid_seeders(&vec!["a".to_string()]));
+        assert!(swarm.has_valid_seeders(&seeders));
+
+        // a isn't added back once b is done
+        let requests = rebalance(&mut swarm, &seeders, &vec![range(0, 49)]);
+        assert_eq!(requests.len(), 1);
+        assert_request(&requests[0], "b", vec![range(0, 49)], false, false);
+        assert_eq!(swarm.node_ids(), vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn prioritized_ranges_first() {
+        let mut swarm = DownloadSwarm::new();
+        let seeders = seeders(&["a", "b"]);
+        rebalance(&mut swarm, &seeders, &vec![range(0, 99)]);
+
+        let requests = swarm.prioritize(&vec![range(60, 69)]);
+        assert_eq!(requests.len(), 1);
+        assert_request(&requests[0], "b", vec![range(60, 69)], false, true);
+        assert!(swarm.prioritize(&vec![range(60, 69)]).is_empty());
+
+        // The rest of b's ranges are requested again once the prioritized ones are in
+        let chunks_missing = vec![range(0, 59), range(70, 99)];
+        let requests = rebalance(&mut swarm, &seeders, &chunks_missing);
+        assert_eq!(requests.len(), 1);
+        assert_request(
+            &requests[0],
+            "b",
+            vec![range(50, 59), range(70, 99)],
+            false,
+            true,
+        );
+    }
+}
@@ -4,7 +4,7 @@ use std::{
     io::{Read, Seek, SeekFrom, Write},
     path::PathBuf,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicBool, Ordering},
         Arc, Weak,
     },
     time::{Instant, SystemTime},
@@ -17,21 +17,28 @@ use parking_lot::{Mutex, RwLock};
 
 use crate::{
     config::{
-        CHUNKS_MISSING_POLLING_INTERVAL, CHUNK_SIZE, MAX_CHUNKS_MISSING_RETRY,
-        MAX_POLL_COUNT_BEFORE_SEND, MAX_TEMP_FILE_SIZE,
+        CHUNKS_MISSING_POLLING_INTERVAL, CHUNK_SIZE, FILE_DOWNLOAD_SAVE_INTERVAL,
+        MAX_TEMP_FILE_SIZE, TRANSFER_RATE_WINDOW,
     },
-    events::complete_pool_file_download_event,
+    events::{
+        complete_pool_file_download_event, queue_pool_file_download_event,
+        start_pool_file_download_event,
+    },
+    ipc::{IPCFileDownloadProgress, IPCFileUploadProgress},
     pool::chunk::{chunk_ranges::create_full_chunk_range, chunk_util::total_size_to_total_chunks},
     poolpb::{pool_message::FileRequestData, PoolChunkMessage, PoolFileInfo},
-    store::file_store::{FileStore, TempFile},
+    store::file_store::{FileStore, SavedFileDownload, TempFile},
     STATE_UPDATER, STORE_MANAGER,
 };
 
 use super::{
+    bandwidth_limiter::BandwidthLimiter,
     chunk::{
         chunk_ranges::{ChunkRanges, ChunkRangesUtil},
         chunk_util::chunk_number_to_cache_chunk_number,
+        merkle_tree::MerkleTree,
     },
+    download_swarm::{DownloadSwarm, SwarmRequest},
     pool_net::{PoolNet, SendChunkInfo},
     pool_state::PoolState,
 };
@@ -47,6 +54,9 @@ struct FileRequest {
     chunk_missing_range_number: usize,
     new: bool,
     wrapped: bool,
+
+    upload_rate: TransferRate,
+    progress: Arc<Mutex<IPCFileUploadProgress>>, // registered with the state updater while the request exists
 }
 
 struct ChunkSender {
@@ -56,6 +66,7 @@ struct ChunkSender {
 
     broadcasting: AtomicBool,
     path: PathBuf,
+    merkle_tree: Arc<RwLock<MerkleTree>>,
 
     file_requests: Mutex<VecDeque<FileRequest>>,
     file_manager_ref: Weak<FileManager>,
@@ -72,7 +83,27 @@ pub(super) struct FileDownload {
     chunks_downloaded: u64,
     chunks_downloaded_ranges: ChunkRanges,
 
-    requested_node_id: String,
+    requested_node_ids: Vec<String>,
+    start_instant: Instant,
+    last_saved_instant: Instant,
+
+    merkle_tree: Arc<RwLock<MerkleTree>>, // shared with the download's chunk sender
+    resumed_chunks_ranges: ChunkRanges,   // downloaded before a restart, no proofs for these
+    prioritized_chunks: Option<ChunkRanges>, // needed for streaming, not picked up by the chunk handler yet
+}
+
+struct QueuedFileDownload {
+    file_info: PoolFileInfo,
+    path: PathBuf,
+    chunks_downloaded_ranges: ChunkRanges,
+    is_resumed: bool, // stays saved in the store while queued
+    priority: i32,
+}
+
+#[derive(Debug)]
+struct TransferRate {
+    transferred: VecDeque<(Instant, u64)>, // bytes within TRANSFER_RATE_WINDOW
+    transferred_bytes: u64,
     start_instant: Instant,
 }
 
@@ -81,23 +112,28 @@ pub(super) struct FileManager {
     pub(super) pool_net_ref: ArcSwapOption<PoolNet>,
 
     file_downloads: Mutex<HashMap<String, FileDownload>>, // file_id -> file_download
+    download_queue: Mutex<Vec<QueuedFileDownload>>, // highest priority first, FIFO within a priority
     chunk_handlers: RwLock<HashMap<String, Sender<PoolChunkMessage>>>, // file_id -> chunk_handler
     chunk_senders: RwLock<HashMap<String, Arc<ChunkSender>>>, // file_id -> chunk_sender
 
     send_chunk_tx: Sender<SendChunkInfo>,
+    download_limiter: Arc<BandwidthLimiter>, // shared by all pools
 }
 
 impl FileManager {
     pub(super) fn init(
         pool_state: Arc<PoolState>,
         send_chunk_tx: Sender<SendChunkInfo>,
+        download_limiter: Arc<BandwidthLimiter>,
     ) -> Arc<Self> {
         let file_manager = Arc::new(FileManager {
             pool_state,
             file_downloads: Mutex::new(HashMap::new()),
+            download_queue: Mutex::new(Vec::new()),
             chunk_handlers: RwLock::new(HashMap::new()),
             chunk_senders: RwLock::new(HashMap::new()),
             send_chunk_tx,
+            download_limiter,
             pool_net_ref: ArcSwapOption::empty(),
         });
 
@@ -107,7 +143,7 @@ impl FileManager {
             for (path, file_info) in file_offers {
                 chunk_senders.insert(
                     file_info.file_id.clone(),
-                    ChunkSender::new(file_info, path, Arc::downgrade(&file_manager), false),
+                    ChunkSender::new(file_info, path, None, Arc::downgrade(&file_manager), false),
                 );
             }
         }
@@ -116,6 +152,20 @@ impl FileManager {
     }
 
     pub(super) fn clean(&self) {
+        // Emptied first so the chunk handlers exiting below don't start anything
+        {
+            let mut download_queue = self.download_queue.lock();
+            for queued_file_download in download_queue.drain(..) {
+                if !queued_file_download.is_resumed {
+                    complete_pool_file_download_event(
+                        &self.pool_state.pool_id,
+                        queued_file_download.file_info.file_id,
+                        false,
+                    );
+                }
+            }
+        }
+
         {
             let chunk_senders = self.chunk_senders.write();
             for chunk_sender in chunk_senders.values() {
@@ -130,7 +180,9 @@ impl FileManager {
 
         {
             let mut file_downloads = self.file_downloads.lock();
-            file_downloads.clear();
+            for (_, file_download) in file_downloads.drain() {
+                self.save_file_download(&file_download);
+            }
         }
 
         self.pool_net_ref.store(None);
@@ -140,12 +192,19 @@ impl FileManager {
         self: &Arc<Self>,
         file_info: PoolFileInfo,
         path: PathBuf,
+        merkle_tree: Option<Arc<RwLock<MerkleTree>>>,
         intend_to_broadcast: bool,
     ) {
         let mut chunk_senders = self.chunk_senders.write();
         chunk_senders.insert(
             file_info.file_id.clone(),
-            ChunkSender::new(file_info, path, Arc::downgrade(self), intend_to_broadcast),
+            ChunkSender::new(
+                file_info,
+                path,
+                merkle_tree,
+                Arc::downgrade(self),
+                intend_to_broadcast,
+            ),
         );
     }
 
@@ -171,7 +230,7 @@ impl FileManager {
             let file_downloads = self.file_downloads.lock();
             if let Some(file_download) = file_downloads.get(&file_request_data.file_id) {
                 let promised_chunks = file_request_data.requested_chunks.promise_valid_chunks(
-                    &file_download.chunks_downloaded_ranges,
+                    &file_download.provable_chunks_ranges(),
                     &mut file_request_data.promised_chunks,
                     partner_int_path,
                 );
@@ -213,6 +272,7 @@ impl FileManager {
             requested_chunks: promised_chunks,
             promised_chunks: Vec::new(),
             request_from_origin: false,
+            replace: file_request_data.replace,
         };
 
         chunk_sender.add_request(requesting_node_id, file_request_data);
@@ -231,10 +291,16 @@ impl FileManager {
         }
     }
 
-    pub(super) fn retract_file_request(&self, file_id: &String) {
+    // Returns true if the request was being served
+    pub(super) fn retract_file_request(
+        &self,
+        requesting_node_id: &String,
+        file_id: &String,
+    ) -> bool {
         let chunk_senders = self.chunk_senders.read();
-        if let Some(chunk_sender) = chunk_senders.get(file_id) {
-            chunk_sender.retract_request(file_id);
+        match chunk_senders.get(file_id) {
+            Some(chunk_sender) => chunk_sender.retract_request(requesting_node_id),
+            None => false,
         }
     }
 
@@ -245,10 +311,10 @@ impl FileManager {
         }
     }
 
-    pub(super) fn download_requested_node_id(&self, file_id: &String) -> Option<String> {
+    pub(super) fn download_requested_node_ids(&self, file_id: &String) -> Option<Vec<String>> {
         let file_downloads = self.file_downloads.lock();
         if let Some(file_download) = file_downloads.get(file_id) {
-            return Some(file_download.requested_node_id.clone());
+            return Some(file_download.requested_node_ids.clone());
         }
         None
     }
@@ -275,19 +341,22 @@ impl FileManager {
             if is_temp && success {
                 // Replace file offer
                 self_clone.retract_file_offer(file_info.file_id.clone());
-                self_clone.seed_file(file_info, to_path);
+                self_clone.seed_file(file_info, to_path, None);
             }
         });
     }
 
-    // Returns Ok(requested_node_id) if new file download has been initialized
+    // Returns true if new file download has been initialized, temp downloads skip the queue
+    // so previews aren't stuck behind large downloads
     pub(super) fn init_file_download(
         self: &Arc<Self>,
         file_info: PoolFileInfo,
         dir_path: Option<PathBuf>,
-    ) -> Option<String> {
-        if self.has_file_download(&file_info.file_id) {
-            return None;
+    ) -> bool {
+        if self.has_file_download(&file_info.file_id)
+            || self.is_queued_file_download(&file_info.file_id)
+        {
+            return false;
         }
 
         let (path, is_temp) = match dir_path {
@@ -297,7 +366,7 @@ impl FileManager {
             }
             None => {
                 if file_info.total_size > MAX_TEMP_FILE_SIZE {
-                    return None;
+                    return false;
                 }
 
                 let path = match FileStore::temp_file_path(
@@ -305,13 +374,312 @@ impl FileManager {
                     file_info.file_id.clone(),
                 ) {
                     Some(path) => path,
-                    _ => return None,
+                    _ => return false,
                 };
 
                 (path, true)
             }
         };
 
+        if file_info.total_size == 0 {
+            return self.complete_empty_file_download(file_info, path, is_temp);
+        }
+
+        if is_temp {
+            self.start_file_download(file_info, path, true, ChunkRanges::new())
+        } else {
+            self.queue_file_download(file_info, path, ChunkRanges::new(), false)
+        }
+    }
+
+    // There are no chunks to request, so the file is created and seeded right away
+    fn complete_empty_file_download(
+        &self,
+        file_info: PoolFileInfo,
+        path: PathBuf,
+        is_temp: bool,
+    ) -> bool {
+        if File::create(&path).is_err() {
+            return false;
+        }
+
+        let file_id = file_info.file_id.clone();
+        start_pool_file_download_event(&self.pool_state.pool_id, file_id.clone());
+
+        if is_temp {
+            self.add_temp_file(TempFile {
+                file_id: file_id.clone(),
+                file_size: 0,
+                created: SystemTime::now(),
+                path: path.clone(),
+            });
+        }
+
+        self.seed_file(
+            file_info,
+            path,
+            Some(Arc::new(RwLock::new(MerkleTree::new(0)))),
+        );
+
+        complete_pool_file_download_event(&self.pool_state.pool_id, file_id, true);
+        true
+    }
+
+    // Downloads saved in the store are resumed from the chunks already written to the partial file
+    pub(super) fn resume_file_downloads(self: &Arc<Self>) {
+        for saved_file_download in STORE_MANAGER.file_downloads(&self.pool_state.pool_id) {
+            if !saved_file_download.paused {
+                self.resume_saved_file_download(saved_file_download);
+            }
+        }
+    }
+
+    // Returns true if the paused download has started again or is queued to
+    pub(super) fn resume_file_download(self: &Arc<Self>, file_id: &String) -> bool {
+        match STORE_MANAGER.file_download(&self.pool_state.pool_id, file_id) {
+            Some(saved_file_download) => self.resume_saved_file_download(saved_file_download),
+            None => false,
+        }
+    }
+
+    fn resume_saved_file_download(
+        self: &Arc<Self>,
+        saved_file_download: SavedFileDownload,
+    ) -> bool {
+        let file_id = saved_file_download.file_info.file_id.clone();
+        if self.has_file_download(&file_id) || self.is_queued_file_download(&file_id) {
+            return false;
+        }
+
+        let full_chunk_range = create_full_chunk_range(saved_file_download.file_info.total_size);
+        let chunks_missing = full_chunk_range.diff(&saved_file_download.chunks_downloaded_ranges);
+
+        if chunks_missing.is_empty() || !saved_file_download.path.is_file() {
+            STORE_MANAGER.remove_file_download(&self.pool_state.pool_id, &file_id);
+            return false;
+        }
+
+        info!("Resuming file {}", file_id);
+
+        // Stays saved until one of the seeders is back
+        self.queue_file_download(
+            saved_file_download.file_info,
+            saved_file_download.path,
+            saved_file_download.chunks_downloaded_ranges,
+            true,
+        )
+    }
+
+    // Returns true if queued, the download starts right away if there's a free slot
+    fn queue_file_download(
+        self: &Arc<Self>,
+        file_info: PoolFileInfo,
+        path: PathBuf,
+        chunks_downloaded_ranges: ChunkRanges,
+        is_resumed: bool,
+    ) -> bool {
+        if self
+            .pool_state
+            .sorted_file_seeders(&file_info.file_id)
+            .is_none()
+        {
+            return false;
+        }
+
+        let file_id = file_info.file_id.clone();
+        let queued_file_download = QueuedFileDownload {
+            file_info,
+            path,
+            chunks_downloaded_ranges,
+            is_resumed,
+            priority: 0,
+        };
+
+        {
+            let mut download_queue = self.download_queue.lock();
+            let position = queue_position(&download_queue, queued_file_download.priority);
+            download_queue.insert(position, queued_file_download);
+        }
+
+        queue_pool_file_download_event(&self.pool_state.pool_id, file_id, 0);
+
+        self.start_queued_file_downloads();
+
+        true
+    }
+
+    // Starts queued downloads while there are free slots, the queue stays locked
+    // so two finishing downloads can't both take the last slot
+    pub(super) fn start_queued_file_downloads(self: &Arc<Self>) {
+        let max_concurrent_downloads = STORE_MANAGER.max_concurrent_downloads();
+
+        let mut download_queue = self.download_queue.lock();
+        while !download_queue.is_empty() {
+            let active_downloads = {
+                let file_downloads = self.file_downloads.lock();
+                file_downloads
+                    .values()
+                    .filter(|file_download| !file_download.is_temp)
+                    .count()
+            };
+
+            if active_downloads >= max_concurrent_downloads {
+                return;
+            }
+
+            let queued_file_download = download_queue.remove(0);
+            let file_id = queued_file_download.file_info.file_id.clone();
+            let started = self.start_file_download(
+                queued_file_download.file_info,
+                queued_file_download.path,
+                false,
+                queued_file_download.chunks_downloaded_ranges,
+            );
+
+            // Resumed downloads that can't start stay saved for the next reconnect
+            if !started && !queued_file_download.is_resumed {
+                complete_pool_file_download_event(&self.pool_state.pool_id, file_id, false);
+            }
+        }
+    }
+
+    // Returns true if the download was queued, running downloads keep going
+    pub(super) fn set_file_download_priority(&self, file_id: &String, priority: i32) -> bool {
+        {
+            let mut download_queue = self.download_queue.lock();
+            let mut queued_file_download = match download_queue
+                .iter()
+                .position(|queued_file_download| &queued_file_download.file_info.file_id == file_id)
+            {
+                Some(i) => download_queue.remove(i),
+                None => return false,
+            };
+
+            queued_file_download.priority = priority;
+            let position = queue_position(&download_queue, priority);
+            download_queue.insert(position, queued_file_download);
+        }
+
+        queue_pool_file_download_event(&self.pool_state.pool_id, file_id.clone(), priority);
+
+        true
+    }
+
+    fn is_queued_file_download(&self, file_id: &String) -> bool {
+        let download_queue = self.download_queue.lock();
+        download_queue
+            .iter()
+            .any(|queued_file_download| &queued_file_download.file_info.file_id == file_id)
+    }
+
+    fn dequeue_file_download(&self, file_id: &String) -> Option<QueuedFileDownload> {
+        let mut download_queue = self.download_queue.lock();
+        let i = download_queue
+            .iter()
+            .position(|queued_file_download| &queued_file_download.file_info.file_id == file_id)?;
+        Some(download_queue.remove(i))
+    }
+
+    // Keeps the partial file and downloaded ranges, returns the requested node ids to retract from
+    pub(super) fn pause_file_download(&self, file_id: &String) -> Option<Vec<String>> {
+        if let Some(queued_file_download) = self.dequeue_file_download(file_id) {
+            return self.pause_queued_file_download(queued_file_download);
+        }
+
+        let file_download = {
+            let mut file_downloads = self.file_downloads.lock();
+            match file_downloads.get(file_id) {
+                Some(file_download) if !file_download.is_temp => file_downloads.remove(file_id)?,
+                _ => return None,
+            }
+        };
+
+        let mut saved_file_download = file_download.saved();
+        saved_file_download.paused = true;
+        STORE_MANAGER.save_file_download(&self.pool_state.pool_id, saved_file_download);
+
+        let mut chunk_handlers = self.chunk_handlers.write();
+        chunk_handlers.remove(file_id);
+        drop(chunk_handlers);
+
+        // Otherwise the partial file would be offered as if it were complete
+        self.remove_chunk_sender(file_id);
+
+        STATE_UPDATER.unregister_download_progress(file_id);
+
+        info!("Paused file {}", file_id);
+
+        Some(file_download.requested_node_ids)
+    }
+
+    // Nothing has been requested yet, though a new download needs an empty partial file
+    // for the saved download to be resumed from
+    fn pause_queued_file_download(
+        &self,
+        queued_file_download: QueuedFileDownload,
+    ) -> Option<Vec<String>> {
+        if !queued_file_download.path.is_file() && File::create(&queued_file_download.path).is_err()
+        {
+            complete_pool_file_download_event(
+                &self.pool_state.pool_id,
+                queued_file_download.file_info.file_id,
+                false,
+            );
+            return None;
+        }
+
+        info!("Paused file {}", queued_file_download.file_info.file_id);
+
+        STORE_MANAGER.save_file_download(
+            &self.pool_state.pool_id,
+            SavedFileDownload {
+                file_info: queued_file_download.file_info,
+                path: queued_file_download.path,
+                chunks_downloaded_ranges: queued_file_download.chunks_downloaded_ranges,
+                paused: true,
+            },
+        );
+
+        Some(Vec::new())
+    }
+
+    pub(super) fn has_saved_file_download(&self, file_id: &String) -> bool {
+        STORE_MANAGER
+            .file_download(&self.pool_state.pool_id, file_id)
+            .is_some()
+    }
+
+    // Removes a queued or paused download that isn't running, along with its partial file
+    pub(super) fn remove_inactive_file_download(&self, file_id: &String) {
+        let is_queued = self.dequeue_file_download(file_id).is_some();
+        let saved_file_download = STORE_MANAGER.file_download(&self.pool_state.pool_id, file_id);
+
+        if let Some(saved_file_download) = &saved_file_download {
+            STORE_MANAGER.remove_file_download(&self.pool_state.pool_id, file_id);
+            let _ = remove_file(&saved_file_download.path);
+        }
+
+        if is_queued || saved_file_download.is_some() {
+            complete_pool_file_download_event(&self.pool_state.pool_id, file_id.clone(), false);
+        }
+    }
+
+    // Returns true if the file download has started, the chunk handler requests the chunks
+    fn start_file_download(
+        self: &Arc<Self>,
+        file_info: PoolFileInfo,
+        path: PathBuf,
+        is_temp: bool,
+        chunks_downloaded_ranges: ChunkRanges,
+    ) -> bool {
+        if self
+            .pool_state
+            .sorted_file_seeders(&file_info.file_id)
+            .is_none()
+        {
+            return false;
+        }
+
         let file_handle = File::options()
             .write(true)
             .create(true)
@@ -320,17 +688,22 @@ impl FileManager {
 
         let file_handle = match file_handle {
             Some(file_handle) => file_handle,
-            _ => return None,
+            _ => return false,
         };
 
         let total_chunks = total_size_to_total_chunks(file_info.total_size);
 
-        let cached_seeders: Vec<String> =
-            match self.pool_state.sorted_file_seeders(&file_info.file_id) {
-                Some(seeders) => seeders,
-                None => return None,
-            };
-        let requested_node_id = cached_seeders[0].clone();
+        let merkle_tree = Arc::new(RwLock::new(MerkleTree::new(file_info.total_size)));
+
+        let chunks_downloaded = chunks_downloaded_ranges.chunks_len();
+
+        // Only the last chunk can be short
+        let mut bytes_downloaded = chunks_downloaded * CHUNK_SIZE as u64;
+        if let Some(last_chunk) = total_chunks.checked_sub(1) {
+            if chunks_downloaded_ranges.has_chunk(last_chunk) {
+                bytes_downloaded -= total_chunks * CHUNK_SIZE as u64 - file_info.total_size;
+            }
+        }
 
         let file_download = FileDownload {
             file_info: file_info.clone(),
@@ -338,18 +711,36 @@ impl FileManager {
             path: path.clone(),
             is_temp,
             total_chunks,
-            chunks_downloaded: 0,
-            chunks_downloaded_ranges: ChunkRanges::new(),
-            requested_node_id: requested_node_id.clone(),
+            chunks_downloaded,
+            chunks_downloaded_ranges: chunks_downloaded_ranges.clone(),
+            requested_node_ids: Vec::new(),
             start_instant: Instant::now(),
+            last_saved_instant: Instant::now(),
+            merkle_tree: merkle_tree.clone(),
+            resumed_chunks_ranges: chunks_downloaded_ranges,
+            prioritized_chunks: None,
         };
 
+        self.save_file_download(&file_download);
+
         let mut file_downloads = self.file_downloads.lock();
         file_downloads.insert(file_info.file_id.clone(), file_download);
 
         drop(file_downloads);
 
-        let file_download_progress = Arc::new(AtomicUsize::new(0));
+        let file_download_progress = Arc::new(Mutex::new(IPCFileDownloadProgress {
+            file_id: file_info.file_id.clone(),
+            progress: if total_chunks > 0 {
+                ((chunks_downloaded * 100) / total_chunks) as usize
+            } else {
+                0
+            },
+            bytes_downloaded,
+            total_size: file_info.total_size,
+            bytes_per_sec: 0,
+            eta_secs: None,
+            seeder_node_ids: Vec::new(),
+        }));
 
         let (handle_chunk_tx, handle_chunk_rx) = flume::unbounded::<PoolChunkMessage>();
 
@@ -357,28 +748,29 @@ impl FileManager {
         chunk_handlers.insert(file_info.file_id.clone(), handle_chunk_tx);
         drop(chunk_handlers);
 
-        self.add_chunk_sender(file_info.clone(), path, false);
+        self.add_chunk_sender(file_info.clone(), path, Some(merkle_tree), false);
 
         STATE_UPDATER
             .register_download_progress(file_info.file_id.clone(), file_download_progress.clone());
 
-        info!(
-            "Downloading file {} requesting {}",
-            file_info.file_id, requested_node_id
-        );
+        info!("Downloading file {}", file_info.file_id);
+
+        start_pool_file_download_event(&self.pool_state.pool_id, file_info.file_id.clone());
 
         let file_manager_clone = self.clone();
         tokio::task::spawn_blocking(move || {
-            file_manager_clone.chunk_handler_loop(
+            file_manager_clone.clone().chunk_handler_loop(
                 file_info,
                 file_handle,
                 handle_chunk_rx,
                 file_download_progress,
-                cached_seeders,
             );
+
+            // The slot is free whether it completed, failed or got paused
+            file_manager_clone.start_queued_file_downloads();
         });
 
-        Some(requested_node_id)
+        true
     }
 
     fn chunk_sender_loop(self: Arc<Self>, file_id: String, mut broadcast: bool) {
@@ -401,8 +793,33 @@ impl FileManager {
             }
         };
 
+        // Offers loaded from the store are only hashed once they're requested,
+        // a file that changed since it was offered is retracted
+        let file_hash = &chunk_sender.file_info.file_hash;
+        if !file_hash.is_empty()
+            && !self.has_file_download(&file_id)
+            && !chunk_sender.merkle_tree.read().is_complete()
+        {
+            match MerkleTree::from_file(&chunk_sender.path, chunk_sender.file_info.total_size) {
+                Some(merkle_tree) if merkle_tree.root_hex().as_ref() == Some(file_hash) => {
+                    *chunk_sender.merkle_tree.write() = merkle_tree;
+                }
+                _ => {
+                    self.retract_file_offer(file_id);
+                    return;
+                }
+            }
+        }
+
+        // Empty files have nothing to send, requesters create them on their own
         let total_chunks = chunk_sender.total_chunks;
-        let last_chunk = total_chunks - 1;
+        let last_chunk = match total_chunks.checked_sub(1) {
+            Some(last_chunk) => last_chunk,
+            None => {
+                chunk_sender.file_requests.lock().clear();
+                return;
+            }
+        };
         let last_chunk_size = {
             let last_chunk_size =
                 (chunk_sender.file_info.total_size % (CHUNK_SIZE as u64)) as usize;
@@ -570,13 +987,40 @@ impl FileManager {
                 break;
             }
 
-            let send_chunk_info =
-                SendChunkInfo::create(file_id.clone(), chunk_number, buf, dest_node_ids, false);
+            let proof = chunk_sender
+                .merkle_tree
+                .read()
+                .proof(chunk_number)
+                .unwrap_or_default();
+
+            let chunk_size = buf.len() as u64;
+            let sent_node_ids = dest_node_ids.clone();
+
+            let send_chunk_info = SendChunkInfo::create(
+                file_id.clone(),
+                chunk_number,
+                buf,
+                proof,
+                dest_node_ids,
+                false,
+            );
 
             if self.send_chunk_tx.send(send_chunk_info).is_err() {
                 break;
             }
 
+            // Requests that weren't sent to are updated too so their rate drops off
+            {
+                let mut file_requests = chunk_sender.file_requests.lock();
+                for req in file_requests.iter_mut() {
+                    let is_sent = match &sent_node_ids {
+                        Some(sent_node_ids) => sent_node_ids.contains(&req.requesting_node_id),
+                        None => true,
+                    };
+                    req.update_progress(if is_sent { chunk_size } else { 0 });
+                }
+            }
+
             chunk_number += 1;
         }
     }
@@ -586,91 +1030,42 @@ impl FileManager {
         file_info: PoolFileInfo,
         mut file_handle: File,
         handle_chunk_rx: Receiver<PoolChunkMessage>,
-        file_download_status: Arc<AtomicUsize>,
-        mut cached_seeders: Vec<String>,
+        file_download_progress: Arc<Mutex<IPCFileDownloadProgress>>,
     ) {
         let mut is_done = false;
-        let mut is_missing = false;
-        let mut last_progress = 0;
+        let mut download_rate = TransferRate::new();
 
-        let mut retry_count = 0;
-        let mut last_request_sent_count = MAX_POLL_COUNT_BEFORE_SEND;
-        let mut seeder_index = 0;
+        let mut download_swarm = DownloadSwarm::new();
+        if !self.rebalance_download_swarm(&file_info.file_id, &mut download_swarm) {
+            return;
+        }
+        file_download_progress.lock().seeder_node_ids = download_swarm.serving_node_ids();
 
         loop {
             let chunk_msg = match handle_chunk_rx.recv_timeout(CHUNKS_MISSING_POLLING_INTERVAL) {
-                Ok(chunk_msg) => chunk_msg,
+                Ok(chunk_msg) => Some(chunk_msg),
                 Err(flume::RecvTimeoutError::Disconnected) => return,
-                Err(flume::RecvTimeoutError::Timeout) => {
-                    if last_request_sent_count == MAX_POLL_COUNT_BEFORE_SEND {
-                        last_request_sent_count = 0;
-                    } else {
-                        last_request_sent_count += 1;
-                        continue;
-                    }
-
-                    let request_node_id = loop {
-                        if retry_count < MAX_CHUNKS_MISSING_RETRY {
-                            if is_missing {
-                                retry_count += 1;
-                            } else {
-                                retry_count = 0;
-                            }
-                        } else {
-                            seeder_index += 1;
-
-                            if seeder_index == cached_seeders.len() {
-                                cached_seeders =
-                                    match self.pool_state.sorted_file_seeders(&file_info.file_id) {
-                                        Some(seeders) => seeders,
-                                        None => {
-                                            self.complete_file_download(&file_info.file_id, false);
-                                            return;
-                                        }
-                                    };
-
-                                seeder_index = 0;
-                            }
-
-                            retry_count = 0;
-                        }
-
-                        let request_node_id = cached_seeders[seeder_index].clone();
-
-                        if self.pool_state.is_node_active(&request_node_id) {
-                            break request_node_id;
-                        }
-
-                        retry_count = MAX_CHUNKS_MISSING_RETRY;
-                    };
-
-                    is_missing = true;
-
-                    let chunks_missing = {
-                        let mut file_downloads = self.file_downloads.lock();
-                        let file_download = match file_downloads.get_mut(&file_info.file_id) {
-                            Some(file_download) => file_download,
-                            None => return,
-                        };
-
-                        file_download.requested_node_id = request_node_id.clone();
+                Err(flume::RecvTimeoutError::Timeout) => None,
+            };
 
-                        // Note chunks_missing should never be empty or else it would've finished downloading
-                        file_download
-                            .full_chunk_range
-                            .diff(&file_download.chunks_downloaded_ranges)
-                    };
+            // Not only on timeouts, a seeder can stall while the others keep chunks coming
+            if download_swarm.should_rebalance() {
+                if !self.rebalance_download_swarm(&file_info.file_id, &mut download_swarm) {
+                    return;
+                }
+                file_download_progress.lock().seeder_node_ids = download_swarm.serving_node_ids();
+            }
 
-                    // Uncomment to disable chunksMissing
-                    // println!("Chunks Missing {:?}", chunks_missing);
-                    // continue;
+            if let Some(prioritized_chunks) = self.take_prioritized_chunks(&file_info.file_id) {
+                let requests = download_swarm.prioritize(&prioritized_chunks);
+                self.send_swarm_requests(&file_info.file_id, requests);
+            }
 
-                    self.request_chunks_missing(
-                        file_info.file_id.clone(),
-                        request_node_id,
-                        chunks_missing,
-                        retry_count == MAX_CHUNKS_MISSING_RETRY,
-                    );
+            let chunk_msg = match chunk_msg {
+                Some(chunk_msg) => chunk_msg,
+                None => {
+                    // Lets the rate drop off while nothing comes in
+                    update_download_progress(&file_download_progress, &mut download_rate, 0);
                     continue;
                 }
             };
@@ -688,26 +1083,6 @@ impl FileManager {
                 continue;
             }
 
-            if is_missing {
-                is_missing = false;
-            }
-
-            file_download
-                .chunks_downloaded_ranges
-                .add_chunk(chunk_msg.chunk_number);
-            file_download.chunks_downloaded += 1;
-
-            let progress =
-                ((file_download.chunks_downloaded * 100) / file_download.total_chunks) as usize;
-            if last_progress != progress {
-                last_progress = progress;
-                file_download_status.store(progress, Ordering::Relaxed);
-            }
-
-            if file_download.chunks_downloaded == file_download.total_chunks {
-                is_done = true;
-            }
-
             let chunk = if chunk_msg.chunk_number == file_download.total_chunks - 1 {
                 let end = (file_info.total_size % (CHUNK_SIZE as u64)) as usize;
                 if end != 0 {
@@ -719,8 +1094,30 @@ impl FileManager {
                 &chunk_msg.chunk
             };
 
+            // Offers made before hashing can't be verified
+            if !file_info.file_hash.is_empty()
+                && !file_download.merkle_tree.write().verify_chunk(
+                    &file_info.file_hash,
+                    chunk_msg.chunk_number,
+                    chunk,
+                    &chunk_msg.proof,
+                )
+            {
+                log::warn!(
+                    "chunk_handler_loop : invalid chunk {} of file {}",
+                    chunk_msg.chunk_number,
+                    file_info.file_id
+                );
+                download_swarm.add_invalid_chunk(chunk_msg.chunk_number);
+                continue;
+            }
+
             drop(file_downloads);
 
+            download_swarm.add_chunk(chunk_msg.chunk_number);
+
+            // Written before it's marked as downloaded, streaming and the chunk sender
+            // read the chunks back from the file
             let offset = chunk_msg.chunk_number * (CHUNK_SIZE as u64);
 
             let write_ok = if let Ok(_) = file_handle.seek(SeekFrom::Start(offset)) {
@@ -729,13 +1126,214 @@ impl FileManager {
                 false
             };
 
-            if !write_ok || is_done {
-                self.complete_file_download(&file_info.file_id, !write_ok);
+            if !write_ok {
+                self.complete_file_download(&file_info.file_id, true);
                 return;
             }
+
+            let mut file_downloads = self.file_downloads.lock();
+            let file_download = match file_downloads.get_mut(&file_info.file_id) {
+                Some(file_download) => file_download,
+                None => return,
+            };
+
+            file_download
+                .chunks_downloaded_ranges
+                .add_chunk(chunk_msg.chunk_number);
+            file_download.chunks_downloaded += 1;
+
+            update_download_progress(
+                &file_download_progress,
+                &mut download_rate,
+                chunk.len() as u64,
+            );
+
+            if file_download.chunks_downloaded == file_download.total_chunks {
+                is_done = true;
+            }
+
+            let save_file_download = !is_done
+                && !file_download.is_temp
+                && file_download.last_saved_instant.elapsed() >= FILE_DOWNLOAD_SAVE_INTERVAL;
+            let saved_file_download = if save_file_download {
+                file_download.last_saved_instant = Instant::now();
+                Some(file_download.saved())
+            } else {
+                None
+            };
+
+            drop(file_downloads);
+
+            if is_done {
+                let bad_chunks = self.recheck_resumed_chunks(&file_info.file_id);
+                if bad_chunks.is_empty() {
+                    self.complete_file_download(&file_info.file_id, false);
+                    return;
+                }
+                is_done = false;
+
+                // Only the last chunk can be short
+                let total_chunks = total_size_to_total_chunks(file_info.total_size);
+                let mut bad_bytes = bad_chunks.chunks_len() * CHUNK_SIZE as u64;
+                if bad_chunks.has_chunk(total_chunks - 1) {
+                    bad_bytes -= total_chunks * CHUNK_SIZE as u64 - file_info.total_size;
+                }
+                let mut progress = file_download_progress.lock();
+                progress.bytes_downloaded = progress.bytes_downloaded.saturating_sub(bad_bytes);
+                progress.progress =
+                    ((progress.bytes_downloaded * 100) / progress.total_size) as usize;
+                drop(progress);
+
+                if !self.rebalance_download_swarm(&file_info.file_id, &mut download_swarm) {
+                    return;
+                }
+                file_download_progress.lock().seeder_node_ids = download_swarm.serving_node_ids();
+                continue;
+            }
+
+            if let Some(saved_file_download) = saved_file_download {
+                STORE_MANAGER.save_file_download(&self.pool_state.pool_id, saved_file_download);
+            }
         }
     }
 
+    // Returns false if the download has ended
+    fn rebalance_download_swarm(
+        &self,
+        file_id: &String,
+        download_swarm: &mut DownloadSwarm,
+    ) -> bool {
+        let seeders = match self.pool_state.sorted_file_seeders(file_id) {
+            Some(seeders) => seeders,
+            None => {
+                self.complete_file_download(file_id, false);
+                return false;
+            }
+        };
+
+        if !download_swarm.has_valid_seeders(&seeders) {
+            self.complete_file_download(file_id, true);
+            return false;
+        }
+
+        let seeders: Vec<String> = seeders
+            .into_iter()
+            .filter(|seeder| self.pool_state.is_node_active(seeder))
+            .collect();
+
+        let requests = {
+            let mut file_downloads = self.file_downloads.lock();
+            let file_download = match file_downloads.get_mut(file_id) {
+                Some(file_download) => file_download,
+                None => return false,
+            };
+
+            // Note chunks_missing should never be empty or else it would've finished downloading
+            let chunks_missing = file_download
+                .full_chunk_range
+                .diff(&file_download.chunks_downloaded_ranges);
+
+            let requests =
+                download_swarm.rebalance(&seeders, &chunks_missing, &self.download_limiter);
+            file_download.requested_node_ids = download_swarm.node_ids();
+            requests
+        };
+
+        self.send_swarm_requests(file_id, requests);
+
+        true
+    }
+
+    fn send_swarm_requests(&self, file_id: &String, requests: Vec<SwarmRequest>) {
+        for request in requests {
+            self.request_chunks_missing(file_id.clone(), request);
+        }
+    }
+
+    // Used to stream a download that's still in progress, the chunk handler picks them up
+    pub(super) fn prioritize_file_chunks(&self, file_id: &String, chunk_ranges: ChunkRanges) {
+        let mut file_downloads = self.file_downloads.lock();
+        if let Some(file_download) = file_downloads.get_mut(file_id) {
+            let prioritized_chunks = chunk_ranges.diff(&file_download.chunks_downloaded_ranges);
+            if !prioritized_chunks.is_empty() {
+                file_download.prioritized_chunks = Some(prioritized_chunks);
+            }
+        }
+    }
+
+    fn take_prioritized_chunks(&self, file_id: &String) -> Option<ChunkRanges> {
+        let mut file_downloads = self.file_downloads.lock();
+        file_downloads.get_mut(file_id)?.prioritized_chunks.take()
+    }
+
+    pub(super) fn file_download(&self, file_id: &String) -> Option<SavedFileDownload> {
+        let file_downloads = self.file_downloads.lock();
+        file_downloads
+            .get(file_id)
+            .map(|file_download| file_download.saved())
+    }
+
+    // Chunks from before a restart were never verified against the proofs, so once everything
+    // is downloaded the file is checked against the tree the proofs filled in. Returns the
+    // resumed chunks that failed, they're dropped from the download to be requested again
+    fn recheck_resumed_chunks(&self, file_id: &String) -> ChunkRanges {
+        let (path, file_info, merkle_tree) = {
+            let file_downloads = self.file_downloads.lock();
+            match file_downloads.get(file_id) {
+                Some(file_download)
+                    if !file_download.resumed_chunks_ranges.is_empty()
+                        && !file_download.file_info.file_hash.is_empty() =>
+                {
+                    (
+                        file_download.path.clone(),
+                        file_download.file_info.clone(),
+                        file_download.merkle_tree.clone(),
+                    )
+                }
+                _ => return Vec::new(),
+            }
+        };
+
+        // complete_file_download fails it if the file can't be read
+        let file_tree = match MerkleTree::from_file(&path, file_info.total_size) {
+            Some(file_tree) => file_tree,
+            None => return Vec::new(),
+        };
+
+        if file_tree.root_hex().as_ref() == Some(&file_info.file_hash) {
+            *merkle_tree.write() = file_tree;
+            return Vec::new();
+        }
+
+        let mut mismatched_chunks = ChunkRanges::new();
+        for chunk_number in merkle_tree.read().mismatched_chunks(&file_tree) {
+            mismatched_chunks.add_chunk(chunk_number);
+        }
+
+        let mut file_downloads = self.file_downloads.lock();
+        let file_download = match file_downloads.get_mut(file_id) {
+            Some(file_download) => file_download,
+            None => return Vec::new(),
+        };
+
+        let bad_chunks = file_download
+            .resumed_chunks_ranges
+            .intersection(&mismatched_chunks);
+
+        log::warn!(
+            "recheck_resumed_chunks : {} resumed chunks of file {} failed",
+            bad_chunks.chunks_len(),
+            file_id
+        );
+
+        file_download.chunks_downloaded_ranges =
+            file_download.chunks_downloaded_ranges.diff(&bad_chunks);
+        file_download.resumed_chunks_ranges = file_download.resumed_chunks_ranges.diff(&bad_chunks);
+        file_download.chunks_downloaded -= bad_chunks.chunks_len();
+
+        bad_chunks
+    }
+
     pub(super) fn complete_file_download(&self, file_id: &String, fail_override: bool) {
         let mut file_downloads = self.file_downloads.lock();
         let file_download = match file_downloads.remove(file_id) {
@@ -743,9 +1341,32 @@ impl FileManager {
             None => return,
         };
 
-        let success =
+        drop(file_downloads);
+
+        if !file_download.is_temp {
+            STORE_MANAGER.remove_file_download(&self.pool_state.pool_id, file_id);
+        }
+
+        let mut success =
             !fail_override && file_download.chunks_downloaded == file_download.total_chunks;
 
+        // Chunks from before a restart were never verified against the proofs,
+        // so the tree is rebuilt from the whole file instead
+        if success
+            && !file_download.file_info.file_hash.is_empty()
+            && !file_download.merkle_tree.read().is_complete()
+        {
+            match MerkleTree::from_file(&file_download.path, file_download.file_info.total_size) {
+                Some(merkle_tree)
+                    if merkle_tree.root_hex().as_ref()
+                        == Some(&file_download.file_info.file_hash) =>
+                {
+                    *file_download.merkle_tree.write() = merkle_tree;
+                }
+                _ => success = false,
+            }
+        }
+
         info!(
             "Completed file {}, at {:.2}. Success: {}",
             file_id,
@@ -765,13 +1386,15 @@ impl FileManager {
                 });
             }
 
-            self.seed_file(file_download.file_info, file_download.path);
+            self.seed_file(
+                file_download.file_info,
+                file_download.path,
+                Some(file_download.merkle_tree),
+            );
         } else {
             let _ = remove_file(file_download.path);
         }
 
-        drop(file_downloads);
-
         let mut chunk_handlers = self.chunk_handlers.write();
         chunk_handlers.remove(file_id);
         drop(chunk_handlers);
@@ -780,6 +1403,13 @@ impl FileManager {
         complete_pool_file_download_event(&self.pool_state.pool_id, file_id.clone(), success);
     }
 
+    // Temp files are small enough to just download again
+    fn save_file_download(&self, file_download: &FileDownload) {
+        if !file_download.is_temp {
+            STORE_MANAGER.save_file_download(&self.pool_state.pool_id, file_download.saved());
+        }
+    }
+
     fn add_temp_file(&self, temp_file: TempFile) {
         if let Some(mut removed_temp_files) =
             STORE_MANAGER.add_temp_file(&self.pool_state.pool_id, temp_file)
@@ -794,31 +1424,31 @@ impl FileManager {
         self.chunk_handlers.read().contains_key(file_id)
     }
 
-    fn request_chunks_missing(
-        &self,
-        file_id: String,
-        request_node_id: String,
-        requested_chunks: ChunkRanges,
-        request_from_origin: bool,
-    ) {
+    fn request_chunks_missing(&self, file_id: String, request: SwarmRequest) {
         if let Some(pool_net) = self.pool_net_ref.load_full() {
             tokio::spawn(async move {
                 pool_net
                     .send_file_request(
                         file_id,
-                        request_node_id,
-                        requested_chunks,
-                        request_from_origin,
+                        request.node_id,
+                        request.chunk_ranges,
+                        request.request_from_origin,
+                        request.replace,
                     )
                     .await;
             });
         }
     }
 
-    fn seed_file(&self, file_info: PoolFileInfo, path: PathBuf) {
+    fn seed_file(
+        &self,
+        file_info: PoolFileInfo,
+        path: PathBuf,
+        merkle_tree: Option<Arc<RwLock<MerkleTree>>>,
+    ) {
         if let Some(pool_net) = self.pool_net_ref.load_full() {
             tokio::spawn(async move {
-                pool_net.send_file_offer(file_info, path).await;
+                pool_net.send_file_offer(file_info, path, merkle_tree).await;
             });
         }
     }
@@ -832,19 +1462,134 @@ impl FileManager {
     }
 }
 
+impl FileDownload {
+    fn saved(&self) -> SavedFileDownload {
+        SavedFileDownload {
+            file_info: self.file_info.clone(),
+            path: self.path.clone(),
+            chunks_downloaded_ranges: self.chunks_downloaded_ranges.clone(),
+            paused: false,
+        }
+    }
+
+    // Chunks that can be sent on with a proof
+    fn provable_chunks_ranges(&self) -> ChunkRanges {
+        if self.resumed_chunks_ranges.is_empty() || self.file_info.file_hash.is_empty() {
+            return self.chunks_downloaded_ranges.clone();
+        }
+        self.chunks_downloaded_ranges
+            .diff(&self.resumed_chunks_ranges)
+    }
+}
+
+impl FileRequest {
+    fn update_progress(&mut self, bytes: u64) {
+        let bytes_per_sec = self.upload_rate.add(bytes);
+
+        let mut progress = self.progress.lock();
+        if bytes > 0 {
+            progress.chunks_sent += 1;
+        }
+        progress.bytes_per_sec = bytes_per_sec;
+    }
+}
+
+// Removed requests drop out of the upload progress however they were removed
+impl Drop for FileRequest {
+    fn drop(&mut self) {
+        STATE_UPDATER.unregister_upload_progress(&self.file_id, &self.requesting_node_id);
+    }
+}
+
+impl TransferRate {
+    fn new() -> Self {
+        TransferRate {
+            transferred: VecDeque::new(),
+            transferred_bytes: 0,
+            start_instant: Instant::now(),
+        }
+    }
+
+    // Returns bytes per second, adding 0 bytes lets the rate drop off while idle
+    fn add(&mut self, bytes: u64) -> u64 {
+        if bytes > 0 {
+            self.transferred.push_back((Instant::now(), bytes));
+            self.transferred_bytes += bytes;
+        }
+
+        while let Some((transferred_instant, transferred_bytes)) = self.transferred.front() {
+            if transferred_instant.elapsed() <= TRANSFER_RATE_WINDOW {
+                break;
+            }
+            self.transferred_bytes -= transferred_bytes;
+            self.transferred.pop_front();
+        }
+
+        // Transfers that just started would look slow averaged over the whole window
+        let window = self
+            .start_instant
+            .elapsed()
+            .min(TRANSFER_RATE_WINDOW)
+            .as_secs_f64();
+        if window > 0.0 {
+            (self.transferred_bytes as f64 / window) as u64
+        } else {
+            0
+        }
+    }
+}
+
+fn update_download_progress(
+    progress: &Mutex<IPCFileDownloadProgress>,
+    download_rate: &mut TransferRate,
+    bytes: u64,
+) {
+    let bytes_per_sec = download_rate.add(bytes);
+
+    let mut progress = progress.lock();
+    progress.bytes_downloaded += bytes;
+    if progress.total_size > 0 {
+        progress.progress = ((progress.bytes_downloaded * 100) / progress.total_size) as usize;
+    }
+    progress.bytes_per_sec = bytes_per_sec;
+    progress.eta_secs = if bytes_per_sec > 0 {
+        Some(
+            progress
+                .total_size
+                .saturating_sub(progress.bytes_downloaded)
+                / bytes_per_sec,
+        )
+    } else {
+        None
+    };
+}
+
+// After every download of the same or higher priority
+fn queue_position(download_queue: &Vec<QueuedFileDownload>, priority: i32) -> usize {
+    download_queue
+        .iter()
+        .position(|queued_file_download| queued_file_download.priority < priority)
+        .unwrap_or(download_queue.len())
+}
+
 impl ChunkSender {
     pub fn new(
         file_info: PoolFileInfo,
         path: PathBuf,
+        merkle_tree: Option<Arc<RwLock<MerkleTree>>>,
         file_manager_ref: Weak<FileManager>,
         intend_to_broadcast: bool,
     ) -> Arc<Self> {
+        let merkle_tree = merkle_tree
+            .unwrap_or_else(|| Arc::new(RwLock::new(MerkleTree::new(file_info.total_size))));
+
         Arc::new(ChunkSender {
             total_chunks: total_size_to_total_chunks(file_info.total_size),
             full_chunk_range: create_full_chunk_range(file_info.total_size),
             file_info,
             broadcasting: AtomicBool::new(intend_to_broadcast),
             path,
+            merkle_tree,
             file_requests: Mutex::new(VecDeque::new()),
             file_manager_ref,
         })
@@ -856,6 +1601,11 @@ impl ChunkSender {
     }
 
     pub fn add_request(&self, requesting_node_id: String, mut file_request_data: FileRequestData) {
+        let pool_id = match self.file_manager_ref.upgrade() {
+            Some(file_manager) => file_manager.pool_state.pool_id.clone(),
+            None => return,
+        };
+
         let mut file_requests = self.file_requests.lock();
 
         let mut existing_file_request: Option<&mut FileRequest> = None;
@@ -867,6 +1617,21 @@ impl ChunkSender {
         }
 
         if let Some(file_request) = existing_file_request {
+            if file_request_data.replace && !file_request_data.requested_chunks.is_empty() {
+                // Starts over on the new ranges, the progress carries on from what was sent
+                file_request_data.requested_chunks.compact();
+                let mut progress = file_request.progress.lock();
+                progress.chunks_requested =
+                    progress.chunks_sent + file_request_data.requested_chunks.chunks_len();
+                drop(progress);
+                file_request.requested_chunks = file_request_data.requested_chunks;
+                file_request.promised_chunks.clear();
+                file_request.start_chunk_number = 0;
+                file_request.next_chunk_number = 0;
+                file_request.chunk_missing_range_number = 0;
+                file_request.new = true;
+                file_request.wrapped = false;
+            }
             file_request_data
                 .promised_chunks
                 .map_promised(&mut file_request.promised_chunks);
@@ -878,6 +1643,21 @@ impl ChunkSender {
                 .promised_chunks
                 .map_promised(&mut promised_chunks);
 
+            let progress = Arc::new(Mutex::new(IPCFileUploadProgress {
+                pool_id,
+                file_id: file_request_data.file_id.clone(),
+                requesting_node_id: requesting_node_id.clone(),
+                chunks_sent: 0,
+                chunks_requested: file_request_data.requested_chunks.chunks_len(),
+                bytes_per_sec: 0,
+            }));
+
+            STATE_UPDATER.register_upload_progress(
+                file_request_data.file_id.clone(),
+                requesting_node_id.clone(),
+                progress.clone(),
+            );
+
             file_requests.push_back(FileRequest {
                 file_id: file_request_data.file_id,
                 requesting_node_id,
@@ -888,6 +1668,8 @@ impl ChunkSender {
                 chunk_missing_range_number: 0,
                 new: true,
                 wrapped: false,
+                upload_rate: TransferRate::new(),
+                progress,
             });
 
             if file_requests.len() == 1 && !self.broadcasting.load(Ordering::SeqCst) {
@@ -909,14 +1691,18 @@ impl ChunkSender {
         }
     }
 
-    pub fn retract_request(&self, file_id: &String) {
+    // Requests are per file, so only the requesting node tells them apart. Returns true if
+    // there was a request to retract
+    pub fn retract_request(&self, requesting_node_id: &String) -> bool {
         let mut file_requests = self.file_requests.lock();
 
         for i in 0..file_requests.len() {
-            if &file_requests[i].file_id == file_id {
+            if &file_requests[i].requesting_node_id == requesting_node_id {
                 file_requests.remove(i);
-                break;
+                return true;
             }
         }
+
+        false
     }
 }
@@ -0,0 +1,54 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+
+use crate::config::{LOGICAL_CLOCK_COUNTER_BITS, MAX_CLOCK_DRIFT};
+
+// Hybrid logical clock, the upper bits are wall clock millis and the lower
+// LOGICAL_CLOCK_COUNTER_BITS are a counter for events within the same milli.
+// Ticks are always greater than every clock sent or observed so far.
+pub struct LogicalClock {
+    last: Mutex<u64>,
+}
+
+impl LogicalClock {
+    pub fn new() -> Self {
+        LogicalClock {
+            last: Mutex::new(0),
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        let physical = physical_clock();
+
+        let mut last = self.last.lock();
+        *last = physical.max(last.saturating_add(1));
+        *last
+    }
+
+    // Clocks more than MAX_CLOCK_DRIFT ahead of our wall clock are ignored so a single node
+    // with a bad clock can't push every other node's clock into the future
+    pub fn observe(&self, clock: u64) {
+        let max_clock = physical_clock()
+            .saturating_add((MAX_CLOCK_DRIFT.as_millis() as u64) << LOGICAL_CLOCK_COUNTER_BITS);
+        if clock > max_clock {
+            log::warn!(
+                "LogicalClock::observe : ignoring clock {} past max drift",
+                clock
+            );
+            return;
+        }
+
+        let mut last = self.last.lock();
+        if clock > *last {
+            *last = clock;
+        }
+    }
+}
+
+fn physical_clock() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => (now.as_millis() as u64) << LOGICAL_CLOCK_COUNTER_BITS,
+        Err(_) => 0,
+    }
+}
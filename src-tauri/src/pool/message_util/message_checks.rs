@@ -4,6 +4,7 @@ pub trait MessageChecks {
     fn is_valid(&self) -> bool;
     fn is_valid_message(&self) -> bool;
     fn is_valid_direct_message(&self) -> bool;
+    fn is_valid_ephemeral_message(&self) -> bool;
     fn is_valid_chunk(&self) -> bool;
 }
 
@@ -47,6 +48,27 @@ impl MessageChecks for PoolMessagePackage {
         return true;
     }
 
+    fn is_valid_ephemeral_message(&self) -> bool {
+        if !self.is_valid() {
+            return false;
+        }
+
+        let ephemeral_msg = match &self.ephemeral_msg {
+            Some(ephemeral_msg) => ephemeral_msg,
+            None => return false,
+        };
+
+        if ephemeral_msg.ephemeral_id.is_empty()
+            || ephemeral_msg.user_id.is_empty()
+            || ephemeral_msg.r#type < 0
+            || ephemeral_msg.data.is_none()
+        {
+            return false;
+        }
+
+        return true;
+    }
+
     fn is_valid_chunk(&self) -> bool {
         if !self.is_valid() {
             return false;
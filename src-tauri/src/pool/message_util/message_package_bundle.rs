@@ -1,7 +1,7 @@
 use bytes::{Bytes};
 use prost::{Message};
 
-use crate::poolpb::{PoolMessagePackage, PoolMessage, PoolChunkMessage, PoolMessagePackageSourceInfo, PoolDirectMessage};
+use crate::poolpb::{PoolMessagePackage, PoolMessage, PoolChunkMessage, PoolMessagePackageSourceInfo, PoolDirectMessage, PoolEphemeralMessage};
 
 #[derive(Clone)]
 pub struct MessagePackageBundle {
@@ -135,6 +135,10 @@ impl MessagePackageBundle {
         self.msg_pkg.direct_msg.take().unwrap()
     }
 
+    pub fn take_ephemeral_msg(&mut self) -> PoolEphemeralMessage {
+        self.msg_pkg.ephemeral_msg.take().unwrap()
+    }
+
     pub fn take_chunk_msg(&mut self) -> PoolChunkMessage {
         self.msg_pkg.chunk_msg.take().unwrap()
     }
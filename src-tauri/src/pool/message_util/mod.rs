@@ -1,4 +1,5 @@
 
+pub(super) mod logical_clock;
 pub(super) mod message_checks;
 pub(super) mod message_package_bundle;
 pub(super) mod received_message_queue;
\ No newline at end of file
@@ -2,10 +2,13 @@ pub mod pool_net;
 pub mod pool_manager;
 
 pub(self) mod pool_conn;
+pub(self) mod node_connection_stats;
 pub(self) mod sync_server_client;
 
 pub(self) mod cache_manager;
 pub(self) mod file_manager;
+pub(self) mod download_swarm;
+pub(self) mod bandwidth_limiter;
 
 pub(self) mod pool_state;
 pub(self) mod pool_node_position;
@@ -0,0 +1,86 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use parking_lot::Mutex;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+
+use crate::ipc::{IPCConnectionStateChange, IPCDataChannelStats};
+
+#[derive(Default)]
+pub(super) struct DataChannelCounters {
+    bytes_sent: AtomicU64,
+    messages_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_received: AtomicU64,
+}
+
+impl DataChannelCounters {
+    // Takes the result of the data channel's send, only what went out is counted
+    pub fn add_sent<E>(&self, sent: Result<usize, E>) {
+        if let Ok(bytes) = sent {
+            self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+            self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn add_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn ipc(&self) -> IPCDataChannelStats {
+        IPCDataChannelStats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Shared between a node connection and its data channel handlers
+pub(super) struct NodeConnectionStats {
+    pub main: DataChannelCounters,
+    pub chunks: DataChannelCounters,
+
+    rtt_ms: AtomicU64, // u64::MAX until the first pong
+    created_instant: Instant,
+    state_changes: Mutex<Vec<IPCConnectionStateChange>>,
+}
+
+impl NodeConnectionStats {
+    pub fn new() -> Self {
+        NodeConnectionStats {
+            main: DataChannelCounters::default(),
+            chunks: DataChannelCounters::default(),
+            rtt_ms: AtomicU64::new(u64::MAX),
+            created_instant: Instant::now(),
+            state_changes: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn set_rtt_ms(&self, rtt_ms: u64) {
+        self.rtt_ms.store(rtt_ms, Ordering::Relaxed);
+    }
+
+    pub fn rtt_ms(&self) -> Option<u64> {
+        match self.rtt_ms.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            rtt_ms => Some(rtt_ms),
+        }
+    }
+
+    pub fn add_state_change(&self, state: RTCPeerConnectionState) {
+        self.state_changes.lock().push(IPCConnectionStateChange {
+            state: state.to_string(),
+            elapsed_ms: self.created_instant.elapsed().as_millis() as u64,
+        });
+    }
+
+    pub fn state_changes(&self) -> Vec<IPCConnectionStateChange> {
+        self.state_changes.lock().clone()
+    }
+}
@@ -12,7 +12,7 @@ use arc_swap::ArcSwapOption;
 use bytes::Bytes;
 use flume::{Receiver, Sender};
 use log::info;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use prost::Message;
 use tokio::sync::{broadcast::Sender as BroadcastSender, Mutex as AsyncMutex};
 use webrtc::{
@@ -21,26 +21,39 @@ use webrtc::{
         data_channel_init::RTCDataChannelInit, data_channel_state::RTCDataChannelState,
         OnCloseHdlrFn, OnMessageHdlrFn, OnOpenHdlrFn, RTCDataChannel,
     },
-    ice_transport::ice_server::RTCIceServer,
+    ice_transport::{
+        ice_candidate::{RTCIceCandidate, RTCIceCandidateInit},
+        ice_gatherer::OnLocalCandidateHdlrFn,
+        ice_server::RTCIceServer,
+    },
     peer_connection::{
         configuration::RTCConfiguration, peer_connection_state::RTCPeerConnectionState,
-        sdp::session_description::RTCSessionDescription, RTCPeerConnection,
+        sdp::session_description::RTCSessionDescription, OnPeerConnectionStateChangeHdlrFn,
+        RTCPeerConnection,
     },
     sctp::stream::OnBufferedAmountLowFn,
+    stats::StatsReportType,
 };
 
 use crate::{
     config::{
-        BUFFERED_AMOUNT_LOW_THRESHOLD, DC_INIT_BUFFER_MAX_FILL_RATE_TIMEOUT,
-        DC_INIT_BUFFER_MIN_FILL_RATE_TIMEOUT, DC_REFILL_RATE_CHUNK_AMOUNT, DC_REFILL_RATE_SIZE,
+        BUFFERED_AMOUNT_LOW_THRESHOLD, CONNECTION_STATS_INTERVAL,
+        DC_INIT_BUFFER_MAX_FILL_RATE_TIMEOUT, DC_INIT_BUFFER_MIN_FILL_RATE_TIMEOUT,
+        DC_REFILL_RATE_CHUNK_AMOUNT, DC_REFILL_RATE_SIZE, DEFAULT_STUN_SERVER_URL,
         MAX_DC_BUFFER_CHUNK_AMOUNT, MAX_DC_BUFFER_SIZE,
     },
+    ipc::IPCPoolNodeConnectionStats,
     poolpb::PoolMessagePackage,
-    sspb::ss_message,
+    sspb::{
+        ss_message::{self, IceCandidateData},
+        IceServer,
+    },
+    STATE_UPDATER, STORE_MANAGER,
 };
 
 use super::{
     message_util::{message_checks::MessageChecks, message_package_bundle::MessagePackageBundle},
+    node_connection_stats::NodeConnectionStats,
     pool_net::PoolNet,
     pool_node_position::PoolPanelNodeIDs,
     pool_state::PoolState,
@@ -58,11 +71,18 @@ struct ChunksBuffer {
 
     last_max_buffer_time: Arc<AtomicU64>,
 }
+// Remote candidates can come in before the remote description is set, they can't be added until then
+enum RemoteIceCandidates {
+    Pending(Vec<RTCIceCandidateInit>),
+    Described, // added as they come in
+}
+
 struct PoolNodeConnection {
     connection: Arc<RTCPeerConnection>,
     main_data_channel: Arc<RTCDataChannel>,
     chunks_data_channel: Arc<RTCDataChannel>,
     chunks_buffer: Arc<ChunksBuffer>,
+    stats: Arc<NodeConnectionStats>,
 
     _closed_tx: Sender<()>,
     closed_rx: Receiver<()>,
@@ -77,13 +97,21 @@ pub struct PoolConn {
 
     is_fully_connected: AtomicBool,
 
+    sync_server_ice_servers: RwLock<Vec<RTCIceServer>>, // from InitPoolData
+    trickle_ice: AtomicBool,                            // from InitPoolData
+    remote_ice_candidates: Mutex<HashMap<String, RemoteIceCandidates>>, // node_id -> remote_ice_candidates
+
     report_node_chan: Sender<ss_message::ReportNodeData>,
     pub(super) report_node_recv: Receiver<ss_message::ReportNodeData>,
+
+    ice_candidate_chan: Sender<IceCandidateData>,
+    pub(super) ice_candidate_recv: Receiver<IceCandidateData>,
 }
 
 impl PoolConn {
     pub(super) fn init(pool_state: Arc<PoolState>) -> Arc<Self> {
         let (report_node_chan, report_node_recv) = flume::unbounded::<ss_message::ReportNodeData>();
+        let (ice_candidate_chan, ice_candidate_recv) = flume::unbounded::<IceCandidateData>();
 
         let pool_conn: Arc<PoolConn> = Arc::new(PoolConn {
             pool_state,
@@ -91,8 +119,18 @@ impl PoolConn {
             node_connections: RwLock::new(HashMap::with_capacity(12)), // MAX 6 connections, so double just in case
             min_time_to_send_deque_size: Arc::new(AtomicU64::new(0)),
             is_fully_connected: AtomicBool::new(false),
+            sync_server_ice_servers: RwLock::new(Vec::new()),
+            trickle_ice: AtomicBool::new(false),
+            remote_ice_candidates: Mutex::new(HashMap::new()),
             report_node_chan,
             report_node_recv,
+            ice_candidate_chan,
+            ice_candidate_recv,
+        });
+
+        let pool_conn_clone = pool_conn.clone();
+        tokio::spawn(async move {
+            pool_conn_clone.connection_stats_loop().await;
         });
 
         pool_conn
@@ -100,6 +138,12 @@ impl PoolConn {
 
     pub(super) async fn clean(&self) {
         self.pool_net_ref.store(None);
+        self.disconnect_all_nodes().await;
+    }
+
+    // Messages sent until the sync server reconnects are kept as missed messages
+    pub(super) async fn disconnect_all_nodes(&self) {
+        self.is_fully_connected.store(false, Ordering::Relaxed);
         let node_connections: Vec<(String, PoolNodeConnection)> = {
             let mut node_connections = self.node_connections.write();
             node_connections.drain().collect()
@@ -108,6 +152,7 @@ impl PoolConn {
         for (_, node_connection) in node_connections {
             self.close_node_connection(node_connection).await;
         }
+        self.remote_ice_candidates.lock().clear();
     }
 
     pub(super) fn is_fully_connected(&self) -> bool {
@@ -115,6 +160,10 @@ impl PoolConn {
     }
 
     pub(super) fn update_is_fully_connected(&self) {
+        if !self.pool_state.is_sync_server_connected() {
+            self.is_fully_connected.store(false, Ordering::Relaxed);
+            return;
+        }
         let node_connections = self.node_connections.read();
         for (_, c) in node_connections.iter() {
             if c.main_data_channel.ready_state() == RTCDataChannelState::Connecting {
@@ -125,11 +174,132 @@ impl PoolConn {
         self.is_fully_connected.store(true, Ordering::Relaxed);
     }
 
+    pub(super) fn set_sync_server_ice_servers(&self, ice_servers: Vec<IceServer>) {
+        *self.sync_server_ice_servers.write() = ice_servers
+            .into_iter()
+            .map(|ice_server| RTCIceServer {
+                urls: ice_server.urls,
+                username: ice_server.username,
+                credential: ice_server.credential,
+                ..Default::default()
+            })
+            .collect();
+    }
+
+    pub(super) fn set_trickle_ice(&self, trickle_ice: bool) {
+        self.trickle_ice.store(trickle_ice, Ordering::Relaxed);
+    }
+
+    pub(super) async fn add_ice_candidate(&self, node_id: String, candidate: String) {
+        let candidate = match serde_json::from_str::<RTCIceCandidateInit>(&candidate) {
+            Ok(candidate) => candidate,
+            Err(_) => return,
+        };
+
+        {
+            let mut remote_ice_candidates = self.remote_ice_candidates.lock();
+            let remote_ice_candidates = remote_ice_candidates
+                .entry(node_id.clone())
+                .or_insert(RemoteIceCandidates::Pending(Vec::new()));
+            if let RemoteIceCandidates::Pending(candidates) = remote_ice_candidates {
+                candidates.push(candidate);
+                return;
+            }
+        }
+
+        if let Some(connection) = self.node_connection(&node_id) {
+            let _ = connection.add_ice_candidate(candidate).await;
+        }
+    }
+
+    pub(super) async fn node_connection_stats(&self) -> Vec<IPCPoolNodeConnectionStats> {
+        let connections: Vec<(
+            String,
+            Arc<RTCPeerConnection>,
+            Arc<RTCDataChannel>,
+            Arc<NodeConnectionStats>,
+        )> = {
+            let node_connections = self.node_connections.read();
+            node_connections
+                .iter()
+                .map(|(node_id, c)| {
+                    (
+                        node_id.clone(),
+                        c.connection.clone(),
+                        c.chunks_data_channel.clone(),
+                        c.stats.clone(),
+                    )
+                })
+                .collect()
+        };
+
+        let mut connection_stats = Vec::with_capacity(connections.len());
+        for (node_id, connection, chunks_dc, stats) in connections {
+            let (local_candidate_type, remote_candidate_type) =
+                Self::selected_candidate_types(&connection).await;
+            connection_stats.push(IPCPoolNodeConnectionStats {
+                pool_id: self.pool_state.pool_id.clone(),
+                node_id,
+                state: connection.connection_state().to_string(),
+                rtt_ms: stats.rtt_ms(),
+                main_channel: stats.main.ipc(),
+                chunks_channel: stats.chunks.ipc(),
+                chunks_buffered_amount: chunks_dc.buffered_amount().await,
+                local_candidate_type,
+                remote_candidate_type,
+                state_changes: stats.state_changes(),
+            });
+        }
+        connection_stats
+    }
+
+    pub(super) fn set_rtt_ms(&self, node_id: &String, rtt_ms: u64) {
+        let node_connections = self.node_connections.read();
+        if let Some(node_connection) = node_connections.get(node_id) {
+            node_connection.stats.set_rtt_ms(rtt_ms);
+        }
+    }
+
+    // Pings the connected nodes and hands the stats to the state updater until the pool closes
+    async fn connection_stats_loop(&self) {
+        loop {
+            tokio::select! {
+                _ = self.pool_state.close_signal() => {
+                    break;
+                },
+                _ = tokio::time::sleep(CONNECTION_STATS_INTERVAL) => {},
+            }
+
+            let connection_stats = self.node_connection_stats().await;
+
+            if let Some(pool_net) = self.pool_net_ref.load_full() {
+                for stats in connection_stats.iter() {
+                    if stats.state == RTCPeerConnectionState::Connected.to_string() {
+                        pool_net.send_ping(&stats.node_id).await;
+                    }
+                }
+            }
+
+            if connection_stats.is_empty() {
+                STATE_UPDATER.remove_connection_stats(&self.pool_state.pool_id);
+            } else {
+                STATE_UPDATER
+                    .set_connection_stats(self.pool_state.pool_id.clone(), connection_stats);
+            }
+        }
+
+        STATE_UPDATER.remove_connection_stats(&self.pool_state.pool_id);
+    }
+
     pub(super) async fn generate_offer(
         self: &Arc<Self>,
         target_node_id: String,
     ) -> anyhow::Result<String> {
-        let node_connection = self.create_connection(&target_node_id).await?;
+        // Remote candidates only come in after the offer
+        self.remote_ice_candidates.lock().remove(&target_node_id);
+
+        let trickle_ice = self.trickle_ice.load(Ordering::Relaxed);
+        let node_connection = self.create_connection(&target_node_id, trickle_ice).await?;
         let connection = node_connection.connection.clone();
         let closed_rx = node_connection.closed_rx.clone();
 
@@ -139,10 +309,12 @@ impl PoolConn {
         let desc = connection.create_offer(None).await?;
         connection.set_local_description(desc).await?;
 
-        let mut gathering_complete_chan = connection.gathering_complete_promise().await;
-        tokio::select! {
-            _ = closed_rx.recv_async() => { return Err(anyhow::anyhow!("node connection closed")) },
-            _ = gathering_complete_chan.recv() => {}
+        if !trickle_ice {
+            let mut gathering_complete_chan = connection.gathering_complete_promise().await;
+            tokio::select! {
+                _ = closed_rx.recv_async() => { return Err(anyhow::anyhow!("node connection closed")) },
+                _ = gathering_complete_chan.recv() => {}
+            }
         }
 
         let local_desc = connection
@@ -159,23 +331,36 @@ impl PoolConn {
         target_node_id: String,
         sdp: String,
     ) -> anyhow::Result<String> {
-        let node_connection = self.create_connection(&target_node_id).await?;
+        // Left over from an earlier connection, pending ones can already be for this offer
+        {
+            let mut remote_ice_candidates = self.remote_ice_candidates.lock();
+            if let Some(RemoteIceCandidates::Described) = remote_ice_candidates.get(&target_node_id)
+            {
+                remote_ice_candidates.remove(&target_node_id);
+            }
+        }
+
+        let trickle_ice = self.trickle_ice.load(Ordering::Relaxed);
+        let node_connection = self.create_connection(&target_node_id, trickle_ice).await?;
         let connection = node_connection.connection.clone();
         let closed_rx = node_connection.closed_rx.clone();
 
-        self.replace_node_connection(target_node_id, node_connection)
+        self.replace_node_connection(target_node_id.clone(), node_connection)
             .await;
 
         let offer = serde_json::from_str::<RTCSessionDescription>(&sdp)?;
-        connection.set_remote_description(offer).await?;
+        self.set_remote_description(&target_node_id, &connection, offer)
+            .await?;
 
         let desc = connection.create_answer(None).await?;
         connection.set_local_description(desc).await?;
 
-        let mut gathering_complete_chan = connection.gathering_complete_promise().await;
-        tokio::select! {
-            _ = closed_rx.recv_async() => { return Err(anyhow::anyhow!("node connection closed")) },
-            _ = gathering_complete_chan.recv() => {}
+        if !trickle_ice {
+            let mut gathering_complete_chan = connection.gathering_complete_promise().await;
+            tokio::select! {
+                _ = closed_rx.recv_async() => { return Err(anyhow::anyhow!("node connection closed")) },
+                _ = gathering_complete_chan.recv() => {}
+            }
         }
 
         let local_desc = connection
@@ -192,7 +377,7 @@ impl PoolConn {
         target_node_id: String,
         sdp: String,
     ) -> anyhow::Result<()> {
-        let (connection, closed_rx) = {
+        let (connection, closed_rx, stats) = {
             let node_connections = self.node_connections.read();
             let node_connection = node_connections
                 .get(&target_node_id)
@@ -200,21 +385,19 @@ impl PoolConn {
             (
                 node_connection.connection.clone(),
                 node_connection.closed_rx.clone(),
+                node_connection.stats.clone(),
             )
         };
 
         let (open_tx, open_rx) = flume::bounded::<()>(0);
-        connection.on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
-            let open_chan = open_tx.clone();
-            Box::pin(async move {
-                if s == RTCPeerConnectionState::Connected {
-                    let _ = open_chan.send(());
-                }
-            })
-        }));
+        connection.on_peer_connection_state_change(Self::on_peer_connection_state_change(
+            stats,
+            Some(open_tx),
+        ));
 
         let answer = serde_json::from_str::<RTCSessionDescription>(&sdp)?;
-        connection.set_remote_description(answer).await?;
+        self.set_remote_description(&target_node_id, &connection, answer)
+            .await?;
 
         tokio::select! {
             _ = closed_rx.recv_async() => { return Err(anyhow::anyhow!("node connection closed")) },
@@ -224,6 +407,32 @@ impl PoolConn {
         anyhow::Ok(())
     }
 
+    // Adds the remote candidates that came in before it
+    async fn set_remote_description(
+        &self,
+        target_node_id: &String,
+        connection: &RTCPeerConnection,
+        desc: RTCSessionDescription,
+    ) -> anyhow::Result<()> {
+        connection.set_remote_description(desc).await?;
+
+        let pending_candidates = {
+            let mut remote_ice_candidates = self.remote_ice_candidates.lock();
+            match remote_ice_candidates
+                .insert(target_node_id.clone(), RemoteIceCandidates::Described)
+            {
+                Some(RemoteIceCandidates::Pending(candidates)) => candidates,
+                _ => Vec::new(),
+            }
+        };
+
+        for candidate in pending_candidates {
+            let _ = connection.add_ice_candidate(candidate).await;
+        }
+
+        anyhow::Ok(())
+    }
+
     async fn replace_node_connection(
         &self,
         target_node_id: String,
@@ -244,6 +453,7 @@ impl PoolConn {
             let mut node_connections = self.node_connections.write();
             node_connections.remove(&target_node_id)
         };
+        self.remote_ice_candidates.lock().remove(&target_node_id);
 
         if let Some(existing_node_connection) = existing_node_connection {
             self.close_node_connection(existing_node_connection).await;
@@ -487,7 +697,7 @@ impl PoolConn {
             return false;
         }
         if msg_pkg_bundle.is_chunk {
-            let (chunks_dc, chunks_buffer, closed_rx) = {
+            let (chunks_dc, chunks_buffer, closed_rx, stats) = {
                 let node_connections = self.node_connections.read();
                 match node_connections.get(node_id) {
                     Some(nc) => (
@@ -495,6 +705,7 @@ impl PoolConn {
                         nc.chunks_data_channel.clone(),
                         nc.chunks_buffer.clone(),
                         nc.closed_rx.clone(),
+                        nc.stats.clone(),
                     ),
                     None => {
                         log::warn!("send_data_channel : no node connection node_id {}", node_id);
@@ -518,7 +729,9 @@ impl PoolConn {
                     };
                 }
 
-                let _ = chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await;
+                stats
+                    .chunks
+                    .add_sent(chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await);
                 return true;
             } else if chunks_dc.ready_state() == RTCDataChannelState::Connecting {
                 // log::debug!("send_data_channel : connecting {}", node_id);
@@ -528,7 +741,9 @@ impl PoolConn {
                     let init_buffer = match &mut *init_buffer_lock {
                         Some(init_buffer) => init_buffer,
                         None => {
-                            let _ = chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await;
+                            stats
+                                .chunks
+                                .add_sent(chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await);
                             return true;
                         }
                     };
@@ -569,7 +784,9 @@ impl PoolConn {
 
                 tokio::select! {
                     _ = signal_chunks_send_rx.recv() => {
-                        let _ = chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await;
+                        stats
+                            .chunks
+                            .add_sent(chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await);
                         return true;
                     },
                     _ = buffer_rate_limiter.recv_async() => {}
@@ -579,7 +796,9 @@ impl PoolConn {
                 let init_buffer = match &mut *init_buffer_lock {
                     Some(init_buffer) => init_buffer,
                     None => {
-                        let _ = chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await;
+                        stats
+                            .chunks
+                            .add_sent(chunks_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await);
                         return true;
                     }
                 };
@@ -600,25 +819,56 @@ impl PoolConn {
                 let node_connections = self.node_connections.read();
                 match node_connections.get(node_id) {
                     Some(nc) if nc.main_data_channel.ready_state() == RTCDataChannelState::Open => {
-                        Some(nc.main_data_channel.clone())
+                        Some((nc.main_data_channel.clone(), nc.stats.clone()))
                     }
                     _ => None,
                 }
             };
 
-            if let Some(main_dc) = main_dc {
-                let _ = main_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await;
+            if let Some((main_dc, stats)) = main_dc {
+                stats
+                    .main
+                    .add_sent(main_dc.send(&msg_pkg_bundle.encoded_msg_pkg).await);
                 return true;
             }
         }
         return false;
     }
 
+    fn on_ice_candidate(
+        ice_candidate_chan: Sender<IceCandidateData>,
+        node_id: String,
+    ) -> OnLocalCandidateHdlrFn {
+        Box::new(move |candidate: Option<RTCIceCandidate>| {
+            let ice_candidate_chan = ice_candidate_chan.clone();
+            let node_id = node_id.clone();
+            Box::pin(async move {
+                // None is the end of gathering, nothing to send for it
+                let candidate = match candidate.map(|candidate| candidate.to_json()) {
+                    Some(Ok(candidate)) => candidate,
+                    _ => return,
+                };
+
+                if let Ok(candidate) = serde_json::to_string(&candidate) {
+                    let _ = ice_candidate_chan.send(IceCandidateData { node_id, candidate });
+                }
+            })
+        })
+    }
+
     fn main_dc_on_open(self_clone: Weak<Self>, node_id: String) -> OnOpenHdlrFn {
         Box::new(move || {
             Box::pin(async move {
                 if let Some(self_clone) = self_clone.upgrade() {
                     info!("main_dc_in_open : for {} with node_id {}", self_clone.pool_state.node_id, node_id);
+                    if let Some(connection) = self_clone.node_connection(&node_id) {
+                        let (local_candidate_type, remote_candidate_type) =
+                            Self::selected_candidate_types(&connection).await;
+                        info!(
+                            "main_dc_in_open : candidate types {:?} <-> {:?} with node_id {}",
+                            local_candidate_type, remote_candidate_type, node_id
+                        );
+                    }
                     self_clone.update_is_fully_connected();
                     if let Some(pool_net) = &*self_clone.pool_net_ref.load() {
                         if !self_clone.pool_state.is_latest() {
@@ -633,12 +883,18 @@ impl PoolConn {
         })
     }
 
-    fn main_dc_on_message(&self, pool_net: Arc<PoolNet>, node_id: String) -> OnMessageHdlrFn {
+    fn main_dc_on_message(
+        &self,
+        pool_net: Arc<PoolNet>,
+        node_id: String,
+        stats: Arc<NodeConnectionStats>,
+    ) -> OnMessageHdlrFn {
         let self_node_id = self.pool_state.node_id.clone();
         Box::new(move |dc_msg| {
             let self_node_id = self_node_id.clone();
             let node_id = node_id.clone();
             let pool_net = pool_net.clone();
+            stats.main.add_received(dc_msg.data.len());
             Box::pin(async move {
                 if dc_msg.data.len() == 0 || dc_msg.is_string {
                     // invalid message
@@ -677,6 +933,19 @@ impl PoolConn {
                                 is_chunk: false,
                             })
                             .await;
+                    } else if msg_pkg.ephemeral_msg.is_some() {
+                        if !msg_pkg.is_valid_ephemeral_message() {
+                            return;
+                        }
+
+                        pool_net
+                            .handle_ephemeral_message(MessagePackageBundle {
+                                msg_pkg: msg_pkg,
+                                encoded_msg_pkg: dc_msg.data,
+                                from_node_id: node_id,
+                                is_chunk: false,
+                            })
+                            .await;
                     }
                 }
             })
@@ -691,6 +960,9 @@ impl PoolConn {
                 if let Some(self_clone) = self_clone.upgrade() {
                     info!("main_dc_on_close : node_id {}", node_id);
                     self_clone.update_is_fully_connected();
+                    if let Some(pool_net) = &*self_clone.pool_net_ref.load() {
+                        pool_net.merge_history_backfill(&node_id);
+                    }
                     let _ = self_clone
                         .report_node_chan
                         .send_async(ss_message::ReportNodeData {
@@ -707,6 +979,7 @@ impl PoolConn {
         &self,
         chunks_dc: Arc<RTCDataChannel>,
         chunks_buffer: Arc<ChunksBuffer>,
+        stats: Arc<NodeConnectionStats>,
     ) -> OnOpenHdlrFn {
         Box::new(move || {
             Box::pin(async move {
@@ -715,7 +988,7 @@ impl PoolConn {
                     if let Some(mut init_buffer) = init_buffer.take() {
                         loop {
                             if let Some(chunk) = init_buffer.buffer.pop_front() {
-                                let _ = chunks_dc.send(&chunk).await;
+                                stats.chunks.add_sent(chunks_dc.send(&chunk).await);
                                 continue;
                             }
                             break;
@@ -730,12 +1003,18 @@ impl PoolConn {
         })
     }
 
-    fn chunks_dc_on_message(&self, pool_net: Arc<PoolNet>, node_id: String) -> OnMessageHdlrFn {
+    fn chunks_dc_on_message(
+        &self,
+        pool_net: Arc<PoolNet>,
+        node_id: String,
+        stats: Arc<NodeConnectionStats>,
+    ) -> OnMessageHdlrFn {
         let self_node_id = self.pool_state.node_id.clone();
         Box::new(move |dc_msg| {
             let self_node_id = self_node_id.clone();
             let node_id = node_id.clone();
             let pool_net = pool_net.clone();
+            stats.chunks.add_received(dc_msg.data.len());
             Box::pin(async move {
                 if dc_msg.data.len() == 0 || dc_msg.is_string {
                     // invalid message
@@ -765,6 +1044,24 @@ impl PoolConn {
         })
     }
 
+    // Records the state changes, open_tx is signalled once connected
+    fn on_peer_connection_state_change(
+        stats: Arc<NodeConnectionStats>,
+        open_tx: Option<Sender<()>>,
+    ) -> OnPeerConnectionStateChangeHdlrFn {
+        Box::new(move |s: RTCPeerConnectionState| {
+            stats.add_state_change(s);
+            let open_chan = open_tx.clone();
+            Box::pin(async move {
+                if let Some(open_chan) = open_chan {
+                    if s == RTCPeerConnectionState::Connected {
+                        let _ = open_chan.send(());
+                    }
+                }
+            })
+        })
+    }
+
     fn chunks_dc_on_buffered_amount_low(
         &self,
         signal_chunks_send_tx: BroadcastSender<()>,
@@ -810,13 +1107,21 @@ impl PoolConn {
     async fn create_connection(
         self: &Arc<Self>,
         node_id: &String,
+        trickle_ice: bool,
     ) -> anyhow::Result<PoolNodeConnection> {
         let pool_net = match self.pool_net_ref.load_full() {
             Some(pool_net) => pool_net,
             None => return Err(anyhow!("pool_net doesn't exist")),
         };
 
-        let pc = Arc::new(Self::create_peer_connection().await?);
+        let pc = Arc::new(self.create_peer_connection().await?);
+
+        if trickle_ice {
+            pc.on_ice_candidate(Self::on_ice_candidate(
+                self.ice_candidate_chan.clone(),
+                node_id.clone(),
+            ));
+        }
 
         let main_dc_options = Some(RTCDataChannelInit {
             ordered: Some(false),
@@ -853,12 +1158,19 @@ impl PoolConn {
             last_max_buffer_time: last_max_buffer_time.clone(),
         });
 
+        let stats = Arc::new(NodeConnectionStats::new());
+        pc.on_peer_connection_state_change(Self::on_peer_connection_state_change(
+            stats.clone(),
+            None,
+        ));
+
         let node_connection = PoolNodeConnection {
             // position: position,
             connection: pc,
             main_data_channel: main_dc,
             chunks_data_channel: chunks_dc,
             chunks_buffer: chunks_buffer,
+            stats,
             _closed_tx,
             closed_rx,
         };
@@ -875,6 +1187,7 @@ impl PoolConn {
                 self,
                 pool_net.clone(),
                 node_id.clone(),
+                node_connection.stats.clone(),
             ));
 
         node_connection
@@ -892,6 +1205,7 @@ impl PoolConn {
                 self,
                 node_connection.chunks_data_channel.clone(),
                 node_connection.chunks_buffer.clone(),
+                node_connection.stats.clone(),
             ));
 
         node_connection
@@ -900,6 +1214,7 @@ impl PoolConn {
                 self,
                 pool_net.clone(),
                 node_id.clone(),
+                node_connection.stats.clone(),
             ));
 
         node_connection
@@ -920,13 +1235,28 @@ impl PoolConn {
         anyhow::Ok(node_connection)
     }
 
-    async fn create_peer_connection() -> Result<RTCPeerConnection, webrtc::Error> {
+    async fn create_peer_connection(&self) -> Result<RTCPeerConnection, webrtc::Error> {
         let api = APIBuilder::new().build();
 
-        let ice_servers = vec![RTCIceServer {
-            urls: vec!["stun:stun.l.google.com:19302".to_string()],
-            ..Default::default()
-        }];
+        let mut ice_servers: Vec<RTCIceServer> = STORE_MANAGER
+            .ice_servers()
+            .into_iter()
+            .map(|ice_server| RTCIceServer {
+                urls: ice_server.urls,
+                username: ice_server.username,
+                credential: ice_server.credential,
+                ..Default::default()
+            })
+            .collect();
+
+        if ice_servers.is_empty() {
+            ice_servers.push(RTCIceServer {
+                urls: vec![DEFAULT_STUN_SERVER_URL.to_string()],
+                ..Default::default()
+            });
+        }
+
+        ice_servers.extend(self.sync_server_ice_servers.read().iter().cloned());
 
         let config = RTCConfiguration {
             ice_servers,
@@ -936,6 +1266,44 @@ impl PoolConn {
         api.new_peer_connection(config).await
     }
 
+    fn node_connection(&self, node_id: &String) -> Option<Arc<RTCPeerConnection>> {
+        let node_connections = self.node_connections.read();
+        node_connections
+            .get(node_id)
+            .map(|node_connection| node_connection.connection.clone())
+    }
+
+    // The pair is looked up in the stats, the selected pair itself doesn't expose its candidates
+    async fn selected_candidate_types(
+        connection: &RTCPeerConnection,
+    ) -> (Option<String>, Option<String>) {
+        let stats = connection.get_stats().await;
+
+        let candidate_pair = stats.reports.values().find_map(|report| match report {
+            StatsReportType::CandidatePair(candidate_pair) if candidate_pair.nominated => {
+                Some(candidate_pair)
+            }
+            _ => None,
+        });
+        let candidate_pair = match candidate_pair {
+            Some(candidate_pair) => candidate_pair,
+            None => return (None, None),
+        };
+
+        let candidate_type = |candidate_id: &String| match stats.reports.get(candidate_id) {
+            Some(StatsReportType::LocalCandidate(candidate))
+            | Some(StatsReportType::RemoteCandidate(candidate)) => {
+                Some(candidate.candidate_type.to_string())
+            }
+            _ => None,
+        };
+
+        (
+            candidate_type(&candidate_pair.local_candidate_id),
+            candidate_type(&candidate_pair.remote_candidate_id),
+        )
+    }
+
     fn direction_of_message(&self, my_path: &Vec<u32>, src_path: &Vec<u32>) -> (bool, bool) {
         let mut send_to_parent = false;
         let mut send_to_child = true;
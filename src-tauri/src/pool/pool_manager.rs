@@ -1,16 +1,23 @@
 use std::{collections::HashMap, fs::remove_dir_all, path::PathBuf, sync::Arc};
 
 use log::info;
-use tokio::sync::RwLock as AsyncRwLock;
+use parking_lot::RwLock;
+use tokio::sync::{RwLock as AsyncRwLock, TryLockError};
 
 use crate::{
     events::{complete_pool_file_download_event},
-    poolpb::PoolFileInfo,
+    ipc::IPCPoolNodeConnectionStats,
+    poolpb::{PoolChunkRange, PoolFileInfo, PoolFolderInfo},
+    store::{
+        file_store::SavedFileDownload,
+        setting_store::{BandwidthLimits, SyncServerSettings},
+    },
+    STORE_MANAGER,
 };
 
 use super::{
-    cache_manager::CacheManager, pool_conn::PoolConn, pool_net::PoolNet, pool_state::PoolState,
-    sync_server_client::SyncServerClient,
+    bandwidth_limiter::BandwidthLimiter, cache_manager::CacheManager, pool_conn::PoolConn,
+    pool_net::PoolNet, pool_state::PoolState, sync_server_client::SyncServerClient,
 };
 
 struct Pool {
@@ -21,10 +28,19 @@ struct Pool {
 }
 
 impl Pool {
-    pub(self) fn init(pool_id: String) -> Self {
+    pub(self) fn init(
+        pool_id: String,
+        upload_limiter: Arc<BandwidthLimiter>,
+        download_limiter: Arc<BandwidthLimiter>,
+    ) -> Self {
         let pool_state = Arc::new(PoolState::init(pool_id));
         let pool_conn = PoolConn::init(pool_state.clone());
-        let pool_net = PoolNet::init(pool_state.clone(), pool_conn.clone());
+        let pool_net = PoolNet::init(
+            pool_state.clone(),
+            pool_conn.clone(),
+            upload_limiter,
+            download_limiter,
+        );
         let sync_server_client = SyncServerClient::init(pool_state.clone(), pool_conn.clone());
 
         pool_conn.pool_net_ref.store(Some(pool_net.clone()));
@@ -47,14 +63,32 @@ impl Pool {
 
 pub struct PoolManager {
     active_pools: AsyncRwLock<HashMap<String, Pool>>,
+    upload_limiter: Arc<BandwidthLimiter>,
+    download_limiter: Arc<BandwidthLimiter>,
 }
 
 impl PoolManager {
     pub fn init() -> Self {
         info!("Initializing Pool Manager...");
 
+        let bandwidth_limits = STORE_MANAGER.bandwidth_limits();
+
         PoolManager {
             active_pools: AsyncRwLock::new(HashMap::new()),
+            upload_limiter: Arc::new(BandwidthLimiter::new(bandwidth_limits.upload_limit)),
+            download_limiter: Arc::new(BandwidthLimiter::new(bandwidth_limits.download_limit)),
+        }
+    }
+
+    pub async fn set_bandwidth_limits(&self, bandwidth_limits: BandwidthLimits) {
+        self.upload_limiter.set_rate(bandwidth_limits.upload_limit);
+        self.download_limiter
+            .set_rate(bandwidth_limits.download_limit);
+
+        let active_pools = self.active_pools.read().await;
+        for pool in active_pools.values() {
+            pool.pool_net
+                .set_pool_upload_limit(bandwidth_limits.pool_upload_limit);
         }
     }
 
@@ -71,7 +105,11 @@ impl PoolManager {
     }
 
     pub async fn connect_to_pool(&self, pool_id: String) {
-        let pool: Pool = Pool::init(pool_id.clone());
+        let pool: Pool = Pool::init(
+            pool_id.clone(),
+            self.upload_limiter.clone(),
+            self.download_limiter.clone(),
+        );
 
         let mut active_pools = self.active_pools.write().await;
         if let Some(existing_pool) = active_pools.insert(pool_id, pool) {
@@ -88,10 +126,63 @@ impl PoolManager {
         }
     }
 
-    pub async fn send_text_message(&self, pool_id: &String, text: String) {
+    pub async fn send_text_message(
+        &self,
+        pool_id: &String,
+        text: String,
+        reply_msg_id: Option<String>,
+    ) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            pool.pool_net.send_text_message(text, reply_msg_id).await;
+        }
+    }
+
+    pub async fn edit_message(&self, pool_id: &String, msg_id: String, text: String) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            pool.pool_net.send_edit_message(msg_id, text).await;
+        }
+    }
+
+    pub async fn delete_message(&self, pool_id: &String, msg_id: String) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            pool.pool_net.send_delete_message(msg_id).await;
+        }
+    }
+
+    pub async fn send_reaction(
+        &self,
+        pool_id: &String,
+        msg_id: String,
+        emoji: String,
+        remove: bool,
+    ) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            pool.pool_net.send_reaction(msg_id, emoji, remove).await;
+        }
+    }
+
+    pub async fn send_read_receipt(&self, pool_id: &String, msg_id: String) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            pool.pool_net.send_read_receipt(msg_id);
+        }
+    }
+
+    pub async fn send_typing_indicator(&self, pool_id: &String, is_typing: bool) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            pool.pool_net.send_typing_indicator(is_typing).await;
+        }
+    }
+
+    pub async fn send_presence(&self, pool_id: &String, is_idle: bool) {
         let active_pools = self.active_pools.read().await;
         if let Some(pool) = active_pools.get(pool_id) {
-            pool.pool_net.send_text_message(text).await;
+            pool.pool_net.send_presence(is_idle).await;
         }
     }
 
@@ -100,10 +191,21 @@ impl PoolManager {
         if let Some(pool) = active_pools.get(pool_id) {
             let file_path = PathBuf::from(file_path);
 
-            if let Some(file_offer) =
-                PoolNet::generate_file_offer(file_path.clone(), pool.pool_state.node_id.clone())
-            {
-                pool.pool_net.send_file_offer(file_offer, file_path).await;
+            let file_path_clone = file_path.clone();
+            let node_id = pool.pool_state.node_id.clone();
+            let file_offer = tokio::task::spawn_blocking(move || {
+                PoolNet::generate_file_offer(file_path_clone, node_id)
+            })
+            .await;
+
+            if let Ok(Some((file_offer, merkle_tree))) = file_offer {
+                pool.pool_net
+                    .send_file_offer(
+                        file_offer,
+                        file_path,
+                        Some(Arc::new(RwLock::new(merkle_tree))),
+                    )
+                    .await;
             }
         }
     }
@@ -113,10 +215,65 @@ impl PoolManager {
         if let Some(pool) = active_pools.get(pool_id) {
             let path = PathBuf::from(file_path);
 
-            if let Some(file_offer) =
-                PoolNet::generate_file_offer(path.clone(), pool.pool_state.node_id.clone())
-            {
-                pool.pool_net.send_image_offer(file_offer, path).await;
+            let path_clone = path.clone();
+            let node_id = pool.pool_state.node_id.clone();
+            let file_offer = tokio::task::spawn_blocking(move || {
+                PoolNet::generate_file_offer(path_clone, node_id)
+            })
+            .await;
+
+            if let Ok(Some((file_offer, merkle_tree))) = file_offer {
+                pool.pool_net
+                    .send_image_offer(file_offer, path, Some(Arc::new(RwLock::new(merkle_tree))))
+                    .await;
+            }
+        }
+    }
+
+    pub async fn add_folder_offer(&self, pool_id: &String, folder_path: String) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            let folder_path = PathBuf::from(folder_path);
+
+            let folder_path_clone = folder_path.clone();
+            let pool_id = pool_id.clone();
+            let node_id = pool.pool_state.node_id.clone();
+            let folder_offer = tokio::task::spawn_blocking(move || {
+                PoolNet::generate_folder_offer(folder_path_clone, &pool_id, node_id)
+            })
+            .await;
+
+            if let Ok(Some((folder_offer, files))) = folder_offer {
+                pool.pool_net
+                    .send_folder_offer(folder_offer, folder_path, files)
+                    .await;
+            }
+        }
+    }
+
+    pub async fn retract_folder_offer(&self, pool_id: &String, folder_id: String) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            pool.pool_net.send_retract_folder_offer(folder_id).await;
+        }
+    }
+
+    pub async fn download_folder(
+        &self,
+        pool_id: &String,
+        folder_info: PoolFolderInfo,
+        dir_path: String,
+        file_ids: Vec<String>,
+    ) {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            let failed_file_ids = pool
+                .pool_net
+                .download_folder(folder_info, PathBuf::from(dir_path), file_ids)
+                .await;
+
+            for file_id in failed_file_ids {
+                complete_pool_file_download_event(pool_id, file_id, false);
             }
         }
     }
@@ -151,4 +308,92 @@ impl PoolManager {
             pool.pool_net.send_retract_file_request(file_id).await;
         }
     }
+
+    pub async fn pause_file_download(&self, pool_id: &String, file_id: String) -> bool {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            return pool.pool_net.pause_file_download(file_id).await;
+        }
+        false
+    }
+
+    pub async fn resume_file_download(&self, pool_id: &String, file_id: String) -> bool {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            return pool.pool_net.resume_file_download(file_id).await;
+        }
+        false
+    }
+
+    pub async fn set_file_download_priority(
+        &self,
+        pool_id: &String,
+        file_id: String,
+        priority: i32,
+    ) -> bool {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            return pool.pool_net.set_file_download_priority(file_id, priority);
+        }
+        false
+    }
+
+    pub async fn stop_file_upload(
+        &self,
+        pool_id: &String,
+        file_id: String,
+        requesting_node_id: String,
+    ) -> bool {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            return pool.pool_net.stop_file_upload(file_id, requesting_node_id);
+        }
+        false
+    }
+
+    pub async fn pool_connection_stats(&self, pool_id: &String) -> Vec<IPCPoolNodeConnectionStats> {
+        let active_pools = self.active_pools.read().await;
+        if let Some(pool) = active_pools.get(pool_id) {
+            return pool.pool_conn.node_connection_stats().await;
+        }
+        Vec::new()
+    }
+
+    pub async fn sync_server_version(&self, sync_server: &SyncServerSettings) -> Option<String> {
+        SyncServerClient::check_version(sync_server).await
+    }
+
+    // Sync for the media protocol handler, Err if the pools are being changed
+    pub fn file_download(
+        &self,
+        pool_id: &String,
+        file_id: &String,
+    ) -> Result<Option<SavedFileDownload>, TryLockError> {
+        let active_pools = self.active_pools.try_read()?;
+        Ok(active_pools
+            .get(pool_id)
+            .and_then(|pool| pool.pool_net.file_download(file_id)))
+    }
+
+    pub fn prioritize_file_chunks(
+        &self,
+        pool_id: &String,
+        file_id: &String,
+        chunk_range: PoolChunkRange,
+    ) {
+        if let Ok(active_pools) = self.active_pools.try_read() {
+            if let Some(pool) = active_pools.get(pool_id) {
+                pool.pool_net
+                    .prioritize_file_chunks(file_id, vec![chunk_range]);
+            }
+        }
+    }
+
+    // For when the max concurrent downloads has been raised
+    pub async fn start_queued_file_downloads(&self) {
+        let active_pools = self.active_pools.read().await;
+        for pool in active_pools.values() {
+            pool.pool_net.start_queued_file_downloads();
+        }
+    }
 }
@@ -1,52 +1,75 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
+    fs::{create_dir_all, read_dir},
     io::Cursor,
     mem,
     path::PathBuf,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use base64::Engine;
 use bytes::Bytes;
-use flume::Receiver;
+use flume::{Receiver, Sender};
 
 use image::GenericImageView;
 use nanoid::nanoid;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 
 use crate::{
     config::{
-        FILE_ID_LENGTH, LATEST_MESSAGES_SIZE, MAX_SEND_CHUNK_BUFFER_LENGTH, MAX_TEMP_FILE_SIZE,
-        MESSAGE_ID_LENGTH, PREVIEW_IMAGE_DIMENSION,
+        EPHEMERAL_ID_LENGTH, EPHEMERAL_RECEIVE_INTERVAL, EPHEMERAL_SEND_INTERVAL, FILE_ID_LENGTH,
+        HISTORY_PAGE_SIZE, LATEST_MESSAGES_SIZE, MAX_FOLDER_OFFER_FILES,
+        MAX_HISTORY_BACKFILL_MESSAGES, MAX_SEND_CHUNK_BUFFER_LENGTH, MAX_TEMP_FILE_SIZE,
+        MESSAGE_ID_LENGTH, PREVIEW_IMAGE_DIMENSION, READ_RECEIPT_INTERVAL,
     },
-    events::{append_pool_message_event, latest_pool_messages_event},
+    db::messages_db::{
+        amendment_target_msg_id, apply_amendment, message_order, AmendMessageResult,
+    },
+    events::{
+        append_pool_message_event, insert_pool_message_event, latest_pool_messages_event,
+        pool_ephemeral_message_event, remove_pool_folder_offer_event, update_pool_message_event,
+        update_pool_message_reactions_event, update_pool_read_receipt_event,
+    },
+    ipc::IPCReadReceipt,
     poolpb::{
         pool_direct_message::{
-            Data as PoolDirectMessageData, DirectType as PoolDirectMessageType, LatestReplyData,
+            Data as PoolDirectMessageData, DirectType as PoolDirectMessageType, HistoryReplyData,
+            HistoryRequestData, LatestReplyData, PingData,
+        },
+        pool_ephemeral_message::{
+            Data as PoolEphemeralMessageData, PresenceData, Type as PoolEphemeralMessageType,
+            TypingData,
         },
         pool_message::{
-            media_offer_data::MediaData, Data as PoolMessageData, FileRequestData, MediaOfferData,
-            NodeInfoData, RetractFileOfferData, RetractFileRequestData, TextData,
+            media_offer_data::MediaData, Data as PoolMessageData, DeleteData, EditData,
+            FileRequestData, MediaOfferData, NodeInfoData, ReactionData, ReadReceiptData,
+            RetractFileOfferData, RetractFileRequestData, RetractFolderOfferData, TextData,
             Type as PoolMessageType,
         },
-        PoolChunkMessage, PoolDirectMessage, PoolFileInfo, PoolImageData, PoolMediaType,
-        PoolMessage, PoolMessagePackage, PoolMessagePackageDestinationInfo,
-        PoolMessagePackageSourceInfo,
+        PoolChunkMessage, PoolDirectMessage, PoolEphemeralMessage, PoolFileInfo,
+        PoolFolderFileInfo, PoolFolderInfo, PoolImageData, PoolMediaType, PoolMessage,
+        PoolMessagePackage, PoolMessagePackageDestinationInfo, PoolMessagePackageSourceInfo,
+    },
+    store::{
+        file_store::{FilePathError, FileStore, SavedFileDownload},
+        user_store::ReadReceipt,
     },
-    store::file_store::FilePathError,
     MESSAGES_DB, STORE_MANAGER,
 };
 
 use super::{
+    bandwidth_limiter::BandwidthLimiter,
     cache_manager::CacheManager,
     chunk::{
-        chunk_ranges::{create_full_chunk_range, ChunkRanges, ChunkRangesUtil},
+        chunk_ranges::{ChunkRanges, ChunkRangesUtil},
         chunk_util::chunk_number_to_partner_int_path,
+        merkle_tree::MerkleTree,
     },
     file_manager::FileManager,
     message_util::{
-        message_package_bundle::MessagePackageBundle, received_message_queue::ReceivedMessageQueue,
+        logical_clock::LogicalClock, message_package_bundle::MessagePackageBundle,
+        received_message_queue::ReceivedMessageQueue,
     },
     pool_conn::PoolConn,
     pool_state::PoolState,
@@ -63,6 +86,7 @@ impl SendChunkInfo {
         file_id: String,
         chunk_number: u64,
         chunk: Vec<u8>,
+        proof: Vec<Bytes>,
         dest_node_ids: Option<Vec<String>>,
         send_to_self: bool,
     ) -> Self {
@@ -71,11 +95,19 @@ impl SendChunkInfo {
                 file_id,
                 chunk_number,
                 chunk: Bytes::from(chunk),
+                proof,
             },
             dest_node_ids,
             send_to_self,
         }
     }
+
+    fn is_local(&self) -> bool {
+        match &self.dest_node_ids {
+            Some(dest_node_ids) => dest_node_ids.is_empty(),
+            None => false,
+        }
+    }
 }
 
 pub(super) struct PoolNet {
@@ -88,16 +120,36 @@ pub(super) struct PoolNet {
     missed_messages: Mutex<Vec<MessagePackageBundle>>,
     received_messages: Mutex<ReceivedMessageQueue>,
     latest_messages: Mutex<VecDeque<PoolMessage>>,
+    history_requests: Mutex<HashMap<String, HistoryRequestData>>, // node_id -> page waiting on a reply
+    history_backfills: Mutex<HashMap<String, Vec<PoolMessage>>>,  // node_id -> pages not merged yet
+    logical_clock: LogicalClock,
+
+    received_ephemeral_messages: Mutex<ReceivedMessageQueue>,
+    ephemeral_received: Mutex<HashMap<(String, i32), (Instant, Option<PoolEphemeralMessageData>)>>, // (user_id, type) -> last received
+    ephemeral_sent: Mutex<HashMap<i32, (Instant, Option<PoolEphemeralMessageData>)>>, // type -> last sent
+
+    read_receipt_tx: Sender<ReadReceiptData>,
+
+    upload_limiter: Arc<BandwidthLimiter>, // shared by all pools
+    pool_upload_limiter: BandwidthLimiter,
 }
 
 impl PoolNet {
-    pub(super) fn init(pool_state: Arc<PoolState>, pool_conn: Arc<PoolConn>) -> Arc<Self> {
+    pub(super) fn init(
+        pool_state: Arc<PoolState>,
+        pool_conn: Arc<PoolConn>,
+        upload_limiter: Arc<BandwidthLimiter>,
+        download_limiter: Arc<BandwidthLimiter>,
+    ) -> Arc<Self> {
         let (send_chunk_tx, send_chunk_rx) =
             flume::bounded::<SendChunkInfo>(MAX_SEND_CHUNK_BUFFER_LENGTH);
 
-        let file_manager = FileManager::init(pool_state.clone(), send_chunk_tx.clone());
+        let file_manager =
+            FileManager::init(pool_state.clone(), send_chunk_tx.clone(), download_limiter);
         let cache_manager = CacheManager::init(pool_state.clone(), send_chunk_tx.clone());
 
+        let (read_receipt_tx, read_receipt_rx) = flume::unbounded::<ReadReceiptData>();
+
         let pool_net: Arc<PoolNet> = Arc::new(PoolNet {
             pool_state,
             pool_conn,
@@ -106,6 +158,17 @@ impl PoolNet {
             missed_messages: Mutex::new(Vec::new()),
             received_messages: Mutex::new(ReceivedMessageQueue::new()),
             latest_messages: Mutex::new(VecDeque::new()),
+            history_requests: Mutex::new(HashMap::new()),
+            history_backfills: Mutex::new(HashMap::new()),
+            logical_clock: LogicalClock::new(),
+            received_ephemeral_messages: Mutex::new(ReceivedMessageQueue::new()),
+            ephemeral_received: Mutex::new(HashMap::new()),
+            ephemeral_sent: Mutex::new(HashMap::new()),
+            read_receipt_tx,
+            upload_limiter,
+            pool_upload_limiter: BandwidthLimiter::new(
+                STORE_MANAGER.bandwidth_limits().pool_upload_limit,
+            ),
         });
 
         let pool_net_clone = pool_net.clone();
@@ -113,6 +176,11 @@ impl PoolNet {
             pool_net_clone.send_chunk_loop(send_chunk_rx).await;
         });
 
+        let pool_net_clone = pool_net.clone();
+        tokio::spawn(async move {
+            pool_net_clone.read_receipt_loop(read_receipt_rx).await;
+        });
+
         pool_net
             .file_manager
             .pool_net_ref
@@ -121,7 +189,16 @@ impl PoolNet {
         pool_net
     }
 
+    pub(super) fn set_pool_upload_limit(&self, pool_upload_limit: u64) {
+        self.pool_upload_limiter.set_rate(pool_upload_limit);
+    }
+
     pub(super) async fn clean(&self) {
+        let node_ids: Vec<String> = self.history_backfills.lock().keys().cloned().collect();
+        for node_id in node_ids {
+            self.merge_history_backfill(&node_id);
+        }
+
         self.file_manager.clean();
         if let Some(cache_manager) = &self.cache_manager {
             cache_manager.clean();
@@ -134,12 +211,17 @@ impl PoolNet {
     }
 
     pub(super) async fn send_latest_reply(&self, target_node_id: &String) {
-        let latest_reply_data = {
+        let latest_messages: Vec<PoolMessage> = {
             let latest_messages = self.latest_messages.lock();
-            LatestReplyData {
-                latest_messages: latest_messages.iter().cloned().collect(),
-                file_seeders: self.pool_state.collect_file_seeders(),
-            }
+            latest_messages.iter().cloned().collect()
+        };
+
+        let latest_reply_data = LatestReplyData {
+            reactions: MESSAGES_DB.reaction_messages(&self.pool_state.pool_id, &latest_messages),
+            latest_messages,
+            file_seeders: self.pool_state.collect_file_seeders(),
+            amendments: MESSAGES_DB
+                .latest_amendments(&self.pool_state.pool_id, LATEST_MESSAGES_SIZE),
         };
 
         self.send_direct_message(
@@ -150,6 +232,64 @@ impl PoolNet {
         .await;
     }
 
+    pub(super) async fn send_ping(&self, target_node_id: &String) {
+        let sent = self.pool_state.instant_seed.elapsed().as_millis() as u64;
+        self.send_direct_message(
+            PoolDirectMessageType::Ping,
+            Some(PoolDirectMessageData::PingData(PingData { sent })),
+            target_node_id,
+        )
+        .await;
+    }
+
+    pub(super) async fn send_history_request(
+        &self,
+        history_request_data: HistoryRequestData,
+        target_node_id: &String,
+    ) {
+        self.history_requests
+            .lock()
+            .insert(target_node_id.clone(), history_request_data.clone());
+
+        self.send_direct_message(
+            PoolDirectMessageType::HistoryRequest,
+            Some(PoolDirectMessageData::HistoryRequestData(
+                history_request_data,
+            )),
+            target_node_id,
+        )
+        .await;
+    }
+
+    pub(super) async fn send_history_reply(
+        &self,
+        history_request_data: HistoryRequestData,
+        target_node_id: &String,
+    ) {
+        let (messages, has_more) =
+            MESSAGES_DB.history_page(&self.pool_state.pool_id, &history_request_data);
+
+        let amendments = MESSAGES_DB.history_amendments(
+            &self.pool_state.pool_id,
+            &history_request_data,
+            &messages,
+        );
+
+        let history_reply_data = HistoryReplyData {
+            request: Some(history_request_data),
+            messages,
+            has_more,
+            amendments,
+        };
+
+        self.send_direct_message(
+            PoolDirectMessageType::HistoryReply,
+            Some(PoolDirectMessageData::HistoryReplyData(history_reply_data)),
+            target_node_id,
+        )
+        .await;
+    }
+
     pub(super) async fn send_missed_messages(&self) {
         let missed_messages = {
             let mut missed_messages = self.missed_messages.lock();
@@ -186,8 +326,22 @@ impl PoolNet {
         .await;
     }
 
-    pub(super) async fn send_text_message(&self, text: String) {
-        let text_data = TextData { text };
+    pub(super) async fn send_text_message(&self, text: String, reply_msg_id: Option<String>) {
+        let (reply_msg_id, reply_snippet) = match reply_msg_id {
+            Some(reply_msg_id) => {
+                let reply_snippet = MESSAGES_DB
+                    .reply_snippet(&self.pool_state.pool_id, &reply_msg_id)
+                    .unwrap_or_default();
+                (reply_msg_id, reply_snippet)
+            }
+            None => (String::new(), String::new()),
+        };
+
+        let text_data = TextData {
+            text,
+            reply_msg_id,
+            reply_snippet,
+        };
 
         self.send_message(
             PoolMessageType::Text,
@@ -198,14 +352,94 @@ impl PoolNet {
         .await
     }
 
-    pub(super) async fn send_file_offer(&self, file_offer: PoolFileInfo, path: PathBuf) {
+    pub(super) async fn send_edit_message(&self, msg_id: String, text: String) {
+        if !self.is_own_message(&msg_id) {
+            return;
+        }
+
+        self.send_message(
+            PoolMessageType::Edit,
+            Some(PoolMessageData::EditData(EditData { msg_id, text })),
+            None,
+            None,
+        )
+        .await
+    }
+
+    pub(super) async fn send_delete_message(&self, msg_id: String) {
+        if !self.is_own_message(&msg_id) {
+            return;
+        }
+
+        self.send_message(
+            PoolMessageType::Delete,
+            Some(PoolMessageData::DeleteData(DeleteData { msg_id })),
+            None,
+            None,
+        )
+        .await
+    }
+
+    pub(super) async fn send_reaction(&self, msg_id: String, emoji: String, remove: bool) {
+        if emoji.is_empty() {
+            return;
+        }
+
+        self.send_message(
+            PoolMessageType::Reaction,
+            Some(PoolMessageData::ReactionData(ReactionData {
+                msg_id,
+                emoji,
+                remove,
+            })),
+            None,
+            None,
+        )
+        .await
+    }
+
+    pub(super) fn send_read_receipt(&self, msg_id: String) {
+        let msg = match MESSAGES_DB.message(&self.pool_state.pool_id, &msg_id) {
+            Some(msg) => msg,
+            None => return,
+        };
+
+        let _ = self.read_receipt_tx.send(ReadReceiptData {
+            msg_id,
+            msg_order: message_order(&msg),
+            device_id: STORE_MANAGER.device_id(),
+        });
+    }
+
+    pub(super) async fn send_typing_indicator(&self, is_typing: bool) {
+        self.send_ephemeral_message(
+            PoolEphemeralMessageType::Typing,
+            PoolEphemeralMessageData::TypingData(TypingData { is_typing }),
+        )
+        .await;
+    }
+
+    pub(super) async fn send_presence(&self, is_idle: bool) {
+        self.send_ephemeral_message(
+            PoolEphemeralMessageType::Presence,
+            PoolEphemeralMessageData::PresenceData(PresenceData { is_idle }),
+        )
+        .await;
+    }
+
+    pub(super) async fn send_file_offer(
+        &self,
+        file_offer: PoolFileInfo,
+        path: PathBuf,
+        merkle_tree: Option<Arc<RwLock<MerkleTree>>>,
+    ) {
         if !STORE_MANAGER.add_file_offer(&self.pool_state.pool_id, file_offer.clone(), path.clone())
         {
             return;
         }
 
         self.file_manager
-            .add_chunk_sender(file_offer.clone(), path, false);
+            .add_chunk_sender(file_offer.clone(), path, merkle_tree, false);
 
         self.send_message(
             PoolMessageType::FileOffer,
@@ -216,9 +450,14 @@ impl PoolNet {
         .await;
     }
 
-    pub(super) async fn send_image_offer(&self, file_offer: PoolFileInfo, path: PathBuf) {
+    pub(super) async fn send_image_offer(
+        &self,
+        file_offer: PoolFileInfo,
+        path: PathBuf,
+        merkle_tree: Option<Arc<RwLock<MerkleTree>>>,
+    ) {
         if file_offer.total_size > MAX_TEMP_FILE_SIZE {
-            self.send_file_offer(file_offer, path).await;
+            self.send_file_offer(file_offer, path, merkle_tree).await;
             return;
         }
 
@@ -239,8 +478,8 @@ impl PoolNet {
         }
 
         self.file_manager
-            .add_chunk_sender(file_offer.clone(), path, false);
-        //     .add_chunk_sender(file_offer.clone(), path, true);
+            .add_chunk_sender(file_offer.clone(), path, merkle_tree, false);
+        //     .add_chunk_sender(file_offer.clone(), path, merkle_tree, true);
 
         let media_offer_data = MediaOfferData {
             file_info: Some(file_offer),
@@ -259,6 +498,120 @@ impl PoolNet {
         // self.file_manager.broadcast_file(file_id);
     }
 
+    pub(super) async fn send_folder_offer(
+        &self,
+        folder_offer: PoolFolderInfo,
+        path: PathBuf,
+        files: Vec<(PathBuf, PoolFileInfo, Option<MerkleTree>)>,
+    ) {
+        if !STORE_MANAGER.add_folder_offer(&self.pool_state.pool_id, folder_offer.clone(), path) {
+            return;
+        }
+
+        // Each file is seeded like a file offer, only the folder offer is sent
+        let mut file_ids = Vec::new();
+        for (path, file_info, merkle_tree) in files {
+            if STORE_MANAGER.add_file_offer(
+                &self.pool_state.pool_id,
+                file_info.clone(),
+                path.clone(),
+            ) {
+                file_ids.push(file_info.file_id.clone());
+                self.file_manager.add_chunk_sender(
+                    file_info,
+                    path,
+                    merkle_tree.map(|merkle_tree| Arc::new(RwLock::new(merkle_tree))),
+                    false,
+                );
+            }
+        }
+        STORE_MANAGER.set_folder_file_ids(&folder_offer.folder_id, file_ids);
+
+        self.send_message(
+            PoolMessageType::FolderOffer,
+            Some(PoolMessageData::FolderOfferData(folder_offer)),
+            None,
+            None,
+        )
+        .await;
+    }
+
+    pub(super) async fn send_retract_folder_offer(&self, folder_id: String) {
+        let file_ids = match STORE_MANAGER.remove_folder_offer(&self.pool_state.pool_id, &folder_id)
+        {
+            Some((_, file_ids)) => file_ids,
+            None => return,
+        };
+
+        for file_id in file_ids {
+            self.send_retract_file_offer(file_id).await;
+        }
+
+        self.send_message(
+            PoolMessageType::RetractFolderOffer,
+            Some(PoolMessageData::RetractFolderOfferData(
+                RetractFolderOfferData { folder_id },
+            )),
+            None,
+            None,
+        )
+        .await;
+    }
+
+    // Downloads the selected files, or all of them if none are selected, into a new folder
+    // under dir_path. Returns the file ids that couldn't be downloaded
+    pub(super) async fn download_folder(
+        &self,
+        folder_info: PoolFolderInfo,
+        dir_path: PathBuf,
+        file_ids: Vec<String>,
+    ) -> Vec<String> {
+        let mut failed_file_ids = Vec::new();
+
+        let mut folder_path = dir_path;
+        if FileStore::is_valid_path_segment(&folder_info.folder_name) {
+            FileStore::create_valid_file_path(&mut folder_path, &folder_info.folder_name);
+        } else {
+            folder_path.clear();
+        }
+
+        for folder_file_info in folder_info.file_offers {
+            let file_info = match folder_file_info.file_info {
+                Some(file_info) => file_info,
+                None => continue,
+            };
+
+            if !file_ids.is_empty() && !file_ids.contains(&file_info.file_id) {
+                continue;
+            }
+
+            let dir_path = if folder_path.as_os_str().is_empty() {
+                None
+            } else {
+                FileStore::folder_file_dir_path(
+                    &folder_path,
+                    &folder_file_info.relative_path,
+                    &file_info.file_name,
+                )
+            };
+
+            let dir_path = match dir_path {
+                Some(dir_path) if create_dir_all(&dir_path).is_ok() => dir_path,
+                _ => {
+                    failed_file_ids.push(file_info.file_id);
+                    continue;
+                }
+            };
+
+            let file_id = file_info.file_id.clone();
+            if !self.download_file(file_info, Some(dir_path)).await {
+                failed_file_ids.push(file_id);
+            }
+        }
+
+        failed_file_ids
+    }
+
     // Returns true if downloading
     pub(super) async fn download_file(
         &self,
@@ -289,14 +642,16 @@ impl PoolNet {
             return false;
         }
 
-        let file_id = file_info.file_id.clone();
-        let full_chunk_range = create_full_chunk_range(file_info.total_size);
-
-        if let Some(request_node_id) = self.file_manager.init_file_download(file_info, dir_path) {
-            self.send_file_request(file_id, request_node_id, full_chunk_range, false)
-                .await;
+        // Picks up where a paused or interrupted download left off
+        if self
+            .file_manager
+            .has_saved_file_download(&file_info.file_id)
+        {
+            return self.resume_file_download(file_info.file_id).await;
         }
 
+        self.file_manager.init_file_download(file_info, dir_path);
+
         true
     }
 
@@ -306,12 +661,14 @@ impl PoolNet {
         request_node_id: String,
         requested_chunks: ChunkRanges,
         request_from_origin: bool,
+        replace: bool,
     ) {
         let file_request_data = FileRequestData {
             file_id,
             requested_chunks,
             promised_chunks: Vec::new(),
             request_from_origin,
+            replace,
         };
 
         for partner_int_path in 0..3 {
@@ -344,19 +701,79 @@ impl PoolNet {
     }
 
     pub(super) async fn send_retract_file_request(&self, file_id: String) {
-        let requested_node_id = match self.file_manager.download_requested_node_id(&file_id) {
-            Some(requested_node_id) => requested_node_id,
-            None => return,
+        let requested_node_ids = match self.file_manager.download_requested_node_ids(&file_id) {
+            Some(requested_node_ids) => requested_node_ids,
+            None => {
+                // Queued and paused downloads have no request to retract
+                self.file_manager.remove_inactive_file_download(&file_id);
+                return;
+            }
         };
 
         self.file_manager.complete_file_download(&file_id, false);
 
+        self.send_retract_file_request_message(file_id, requested_node_ids)
+            .await;
+    }
+
+    // Returns true if paused
+    pub(super) async fn pause_file_download(&self, file_id: String) -> bool {
+        let requested_node_ids = match self.file_manager.pause_file_download(&file_id) {
+            Some(requested_node_ids) => requested_node_ids,
+            None => return false,
+        };
+
+        self.send_retract_file_request_message(file_id, requested_node_ids)
+            .await;
+
+        true
+    }
+
+    // Returns true if downloading or queued
+    pub(super) async fn resume_file_download(&self, file_id: String) -> bool {
+        self.file_manager.resume_file_download(&file_id)
+    }
+
+    // Returns true if the download is queued
+    pub(super) fn set_file_download_priority(&self, file_id: String, priority: i32) -> bool {
+        self.file_manager
+            .set_file_download_priority(&file_id, priority)
+    }
+
+    // Returns true if the request was being served, the node can still request again
+    pub(super) fn stop_file_upload(&self, file_id: String, requesting_node_id: String) -> bool {
+        self.file_manager
+            .retract_file_request(&requesting_node_id, &file_id)
+    }
+
+    pub(super) fn start_queued_file_downloads(&self) {
+        self.file_manager.start_queued_file_downloads();
+    }
+
+    pub(super) fn file_download(&self, file_id: &String) -> Option<SavedFileDownload> {
+        self.file_manager.file_download(file_id)
+    }
+
+    pub(super) fn prioritize_file_chunks(&self, file_id: &String, chunk_ranges: ChunkRanges) {
+        self.file_manager
+            .prioritize_file_chunks(file_id, chunk_ranges);
+    }
+
+    async fn send_retract_file_request_message(
+        &self,
+        file_id: String,
+        requested_node_ids: Vec<String>,
+    ) {
+        if requested_node_ids.is_empty() {
+            return;
+        }
+
         self.send_message(
             PoolMessageType::RetractFileRequest,
             Some(PoolMessageData::RetractFileRequestData(
                 RetractFileRequestData { file_id },
             )),
-            Some(vec![requested_node_id]),
+            Some(requested_node_ids),
             None,
         )
         .await;
@@ -401,6 +818,9 @@ impl PoolNet {
             user_id: self.pool_state.user.user_id.clone(),
             created: created.as_millis() as u64,
             data: msg_data,
+            clock: self.logical_clock.tick(),
+            edited: 0,
+            deleted: false,
         });
 
         self.handle_message(MessagePackageBundle::create(
@@ -410,10 +830,143 @@ impl PoolNet {
         .await;
     }
 
+    // Unchanged state is only resent every EPHEMERAL_SEND_INTERVAL
+    async fn send_ephemeral_message(
+        &self,
+        ephemeral_type: PoolEphemeralMessageType,
+        ephemeral_data: PoolEphemeralMessageData,
+    ) {
+        {
+            let mut ephemeral_sent = self.ephemeral_sent.lock();
+            if let Some((sent_at, sent_data)) = ephemeral_sent.get(&(ephemeral_type as i32)) {
+                if sent_data.as_ref() == Some(&ephemeral_data)
+                    && sent_at.elapsed() < EPHEMERAL_SEND_INTERVAL
+                {
+                    return;
+                }
+            }
+            ephemeral_sent.insert(
+                ephemeral_type as i32,
+                (Instant::now(), Some(ephemeral_data.clone())),
+            );
+        }
+
+        let created = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(created) => created,
+            Err(_) => return,
+        };
+
+        let ephemeral_msg = PoolEphemeralMessage {
+            ephemeral_id: nanoid!(EPHEMERAL_ID_LENGTH),
+            r#type: ephemeral_type.into(),
+            user_id: self.pool_state.user.user_id.clone(),
+            created: created.as_millis() as u64,
+            data: Some(ephemeral_data),
+        };
+
+        self.received_ephemeral_messages
+            .lock()
+            .append_message(&ephemeral_msg.ephemeral_id);
+
+        let mut msg_pkg = self.create_message_package(None, None);
+        msg_pkg.ephemeral_msg = Some(ephemeral_msg);
+
+        self.pool_conn
+            .distribute_message(MessagePackageBundle::create(
+                msg_pkg,
+                self.pool_state.node_id.clone(),
+            ))
+            .await;
+    }
+
     fn add_message(&self, msg: PoolMessage) {
+        let (is_newest, msg) = match MESSAGES_DB.add_message(&self.pool_state.pool_id, msg) {
+            Some(added) => added,
+            None => return,
+        };
+
         self.add_latest_message(msg.clone());
-        MESSAGES_DB.append_message(&self.pool_state.pool_id, msg.clone());
-        append_pool_message_event(&self.pool_state.pool_id, msg);
+        if is_newest {
+            append_pool_message_event(&self.pool_state.pool_id, msg);
+        } else {
+            insert_pool_message_event(&self.pool_state.pool_id, msg);
+        }
+    }
+
+    fn add_reaction(&self, reaction: PoolMessage) {
+        if let Some((msg_id, reactions)) =
+            MESSAGES_DB.add_reaction(&self.pool_state.pool_id, reaction)
+        {
+            update_pool_message_reactions_event(&self.pool_state.pool_id, msg_id, reactions);
+        }
+    }
+
+    // Returns false if the user's device already has a newer receipt
+    fn add_read_receipt(&self, user_id: &String, read_receipt_data: &ReadReceiptData) -> bool {
+        let device_id = &read_receipt_data.device_id;
+        let read_receipt = ReadReceipt {
+            msg_id: read_receipt_data.msg_id.clone(),
+            msg_order: read_receipt_data.msg_order,
+        };
+
+        if !STORE_MANAGER.update_read_receipt(
+            &self.pool_state.pool_id,
+            user_id,
+            device_id,
+            read_receipt,
+        ) {
+            return false;
+        }
+
+        let unread_count =
+            MESSAGES_DB.unread_count(&self.pool_state.pool_id, &self.pool_state.user.user_id);
+        update_pool_read_receipt_event(
+            &self.pool_state.pool_id,
+            IPCReadReceipt {
+                user_id: user_id.clone(),
+                device_id: device_id.clone(),
+                msg_id: read_receipt_data.msg_id.clone(),
+            },
+            unread_count,
+        );
+
+        true
+    }
+
+    fn is_own_message(&self, msg_id: &String) -> bool {
+        match MESSAGES_DB.message(&self.pool_state.pool_id, msg_id) {
+            Some(msg) => msg.user_id == self.pool_state.user.user_id && !msg.deleted,
+            None => false,
+        }
+    }
+
+    // Amendments are logged in the messages db, nodes that sync later get them with the
+    // latest and history replies
+    fn apply_amendment(&self, amendment: PoolMessage) {
+        match MESSAGES_DB.amend_message(&self.pool_state.pool_id, &amendment) {
+            AmendMessageResult::Amended(target) => {
+                self.amend_latest_messages(&amendment);
+                update_pool_message_event(&self.pool_state.pool_id, target);
+            }
+            // Applied once the target is received
+            AmendMessageResult::NotFound => {}
+            AmendMessageResult::Rejected => {}
+        }
+    }
+
+    fn amend_latest_messages(&self, amendment: &PoolMessage) {
+        let target_msg_id = match amendment_target_msg_id(amendment) {
+            Some(target_msg_id) => target_msg_id,
+            None => return,
+        };
+
+        let mut latest_messages = self.latest_messages.lock();
+        if let Some(target) = latest_messages
+            .iter_mut()
+            .find(|msg| &msg.msg_id == target_msg_id)
+        {
+            apply_amendment(target, amendment);
+        }
     }
 
     fn add_missed_message(&self, msg_pkg_bundle: &MessagePackageBundle) {
@@ -425,16 +978,39 @@ impl PoolNet {
 
     fn add_latest_message(&self, msg: PoolMessage) {
         let mut latest_messages = self.latest_messages.lock();
-        latest_messages.push_back(msg);
+        let position = latest_messages
+            .iter()
+            .rposition(|latest_msg| message_order(latest_msg) <= message_order(&msg))
+            .map_or(0, |i| i + 1);
+        latest_messages.insert(position, msg);
 
         if latest_messages.len() > LATEST_MESSAGES_SIZE {
             latest_messages.pop_front();
         }
     }
 
-    fn set_latest_messages(&self, msgs: &Vec<PoolMessage>) {
+    // Messages received before the latest reply are kept
+    fn merge_latest_messages(&self, msgs: &Vec<PoolMessage>) {
         let mut latest_messages = self.latest_messages.lock();
-        *latest_messages = msgs.iter().cloned().collect();
+        let mut merged: Vec<PoolMessage> = latest_messages.drain(..).collect();
+        for msg in msgs {
+            if !merged
+                .iter()
+                .any(|latest_msg| latest_msg.msg_id == msg.msg_id)
+            {
+                merged.push(msg.clone());
+            }
+        }
+        merged.sort_by_key(|msg| message_order(msg));
+
+        let skip = merged.len().saturating_sub(LATEST_MESSAGES_SIZE);
+        *latest_messages = merged.into_iter().skip(skip).collect();
+    }
+
+    fn observe_clocks(&self, msgs: &Vec<PoolMessage>) {
+        for msg in msgs {
+            self.logical_clock.observe(msg.clock);
+        }
     }
 
     fn validate_received_messages(&self, msg_pkg_bundle: &MessagePackageBundle) -> bool {
@@ -449,25 +1025,137 @@ impl PoolNet {
         }
     }
 
-    fn update_latest(&self, latest_reply_data: LatestReplyData) {
+    // Returns a history request if there is a gap between the stored and latest messages
+    fn update_latest(&self, latest_reply_data: LatestReplyData) -> Option<HistoryRequestData> {
         if self.pool_state.is_latest() {
             // Maybe use diff algorithm to get any extra data?
-            return;
+            return None;
         }
 
         // log::debug!("update_latest {:?}", latest_reply_data);
 
+        let history_request_data = self.history_gap(&latest_reply_data.latest_messages);
+
+        self.observe_clocks(&latest_reply_data.latest_messages);
         self.add_received_messages(&latest_reply_data.latest_messages);
-        self.set_latest_messages(&latest_reply_data.latest_messages);
+        self.merge_latest_messages(&latest_reply_data.latest_messages);
+
+        let (amendments, latest_messages): (Vec<PoolMessage>, Vec<PoolMessage>) = latest_reply_data
+            .latest_messages
+            .into_iter()
+            .partition(|msg| amendment_target_msg_id(msg).is_some());
+
+        MESSAGES_DB.add_latest_messages(&self.pool_state.pool_id, latest_messages);
+
+        // Older nodes still send amendments with the latest messages
+        self.observe_clocks(&latest_reply_data.amendments);
+        for amendment in amendments.into_iter().chain(latest_reply_data.amendments) {
+            self.apply_amendment(amendment);
+        }
 
-        MESSAGES_DB
-            .add_latest_messages(&self.pool_state.pool_id, latest_reply_data.latest_messages);
+        self.observe_clocks(&latest_reply_data.reactions);
+        for reaction in latest_reply_data.reactions {
+            let _ = MESSAGES_DB.add_reaction(&self.pool_state.pool_id, reaction);
+        }
 
         self.pool_state.set_latest(); // Helps preserve message order
         self.pool_state
             .init_file_seeders(latest_reply_data.file_seeders);
 
         latest_pool_messages_event(&self.pool_state.pool_id);
+
+        history_request_data
+    }
+
+    // Latest messages only go back LATEST_MESSAGES_SIZE, so anything between the newest
+    // stored message and the oldest latest message has to be backfilled
+    fn history_gap(&self, latest_messages: &Vec<PoolMessage>) -> Option<HistoryRequestData> {
+        // Amendments are never stored as messages themselves
+        let oldest_latest_msg = latest_messages
+            .iter()
+            .filter(|msg| amendment_target_msg_id(msg).is_none())
+            .min_by_key(|msg| msg.created)?;
+
+        if MESSAGES_DB.has_message(&self.pool_state.pool_id, &oldest_latest_msg.msg_id) {
+            return None;
+        }
+
+        Some(HistoryRequestData {
+            after_created: MESSAGES_DB
+                .newest_created(&self.pool_state.pool_id)
+                .unwrap_or(0),
+            before_created: oldest_latest_msg.created,
+            after_msg_id: String::new(),
+            before_msg_id: oldest_latest_msg.msg_id.clone(),
+            limit: HISTORY_PAGE_SIZE as u32,
+        })
+    }
+
+    // Returns the next page's request if there are more messages. Pages land before the
+    // latest messages, so they are collected and merged at the end of the backfill to
+    // rewrite the messages db once instead of once per page
+    fn update_history(
+        &self,
+        src_node_id: &String,
+        history_reply_data: HistoryReplyData,
+    ) -> Option<HistoryRequestData> {
+        let mut history_request_data = match history_reply_data.request {
+            Some(history_request_data) => history_request_data,
+            None => {
+                self.merge_history_backfill(src_node_id);
+                return None;
+            }
+        };
+
+        self.observe_clocks(&history_reply_data.amendments);
+        for amendment in history_reply_data.amendments {
+            self.apply_amendment(amendment);
+        }
+
+        let last_msg_id = history_reply_data
+            .messages
+            .last()
+            .map(|msg| msg.msg_id.clone());
+
+        self.observe_clocks(&history_reply_data.messages);
+
+        let backfill_len = {
+            let mut history_backfills = self.history_backfills.lock();
+            let backfill = history_backfills.entry(src_node_id.clone()).or_default();
+            backfill.extend(history_reply_data.messages);
+            backfill.len()
+        };
+
+        let last_msg_id = match last_msg_id {
+            Some(last_msg_id) if history_reply_data.has_more => last_msg_id,
+            _ => {
+                self.merge_history_backfill(src_node_id);
+                return None;
+            }
+        };
+
+        if backfill_len >= MAX_HISTORY_BACKFILL_MESSAGES {
+            self.merge_history_backfill(src_node_id);
+        }
+
+        history_request_data.after_msg_id = last_msg_id;
+        Some(history_request_data)
+    }
+
+    // Also called when the node disconnects, so the pages received so far aren't lost
+    pub(super) fn merge_history_backfill(&self, node_id: &String) {
+        let backfill = match self.history_backfills.lock().remove(node_id) {
+            Some(backfill) => backfill,
+            None => return,
+        };
+
+        let inserted = MESSAGES_DB.merge_messages(&self.pool_state.pool_id, backfill);
+
+        log::debug!("merge_history_backfill : inserted {} messages", inserted);
+
+        if inserted > 0 {
+            latest_pool_messages_event(&self.pool_state.pool_id);
+        }
     }
 
     fn update_node_info(&self, target_node_id: &String, node_info_data: NodeInfoData) {
@@ -506,16 +1194,74 @@ impl PoolNet {
         }
     }
 
+    // Waiting on the limiters backs up send_chunk_tx which in turn holds back the chunk senders
     async fn send_chunk_loop(&self, send_chunk_rx: Receiver<SendChunkInfo>) {
         loop {
+            let send_chunk_info = tokio::select! {
+                _ = self.pool_state.close_signal() => {
+                    return;
+                },
+                Ok(send_chunk_info) = send_chunk_rx.recv_async() => send_chunk_info,
+            };
+
+            if !send_chunk_info.is_local() {
+                let bytes = send_chunk_info.chunk_msg.chunk.len() as u64;
+                tokio::select! {
+                    _ = self.pool_state.close_signal() => {
+                        return;
+                    },
+                    _ = async {
+                        self.pool_upload_limiter.acquire(bytes).await;
+                        self.upload_limiter.acquire(bytes).await;
+                    } => {}
+                }
+            }
+
+            self.send_chunk(send_chunk_info).await;
+        }
+    }
+
+    // Only the newest receipt of each READ_RECEIPT_INTERVAL is sent so scrolling
+    // through messages doesn't flood the mesh
+    async fn read_receipt_loop(&self, read_receipt_rx: Receiver<ReadReceiptData>) {
+        loop {
+            let mut read_receipt_data = tokio::select! {
+                _ = self.pool_state.close_signal() => {
+                    return;
+                },
+                Ok(read_receipt_data) = read_receipt_rx.recv_async() => read_receipt_data,
+            };
+
             tokio::select! {
                 _ = self.pool_state.close_signal() => {
                     return;
                 },
-                Ok(send_chunk_info) = send_chunk_rx.recv_async() => {
-                    self.send_chunk(send_chunk_info).await;
+                _ = tokio::time::sleep(READ_RECEIPT_INTERVAL) => {},
+            }
+
+            for newer_read_receipt_data in read_receipt_rx.drain() {
+                if newer_read_receipt_data.msg_order > read_receipt_data.msg_order {
+                    read_receipt_data = newer_read_receipt_data;
+                }
+            }
+
+            if let Some(read_receipt) = STORE_MANAGER.read_receipt(
+                &self.pool_state.pool_id,
+                &self.pool_state.user.user_id,
+                &read_receipt_data.device_id,
+            ) {
+                if read_receipt.msg_order >= read_receipt_data.msg_order {
+                    continue;
                 }
             }
+
+            self.send_message(
+                PoolMessageType::ReadReceipt,
+                Some(PoolMessageData::ReadReceiptData(read_receipt_data)),
+                None,
+                None,
+            )
+            .await;
         }
     }
 
@@ -595,19 +1341,134 @@ impl PoolNet {
                     _ => return,
                 };
 
-                self.update_latest(latest_reply_data);
+                if let Some(history_request_data) = self.update_latest(latest_reply_data) {
+                    self.send_history_request(history_request_data, &src_node_id)
+                        .await;
+                }
+
+                // Seeders are only known once the latest reply is in
+                self.file_manager.resume_file_downloads();
+
+                // Messages sent while reconnecting to the sync server
+                self.send_missed_messages().await;
+            }
+            PoolDirectMessageType::HistoryRequest => {
+                let history_request_data = match direct_msg.data {
+                    Some(PoolDirectMessageData::HistoryRequestData(history_request_data)) => {
+                        history_request_data
+                    }
+                    _ => return,
+                };
+
+                self.send_history_reply(history_request_data, &src_node_id)
+                    .await;
+            }
+            PoolDirectMessageType::HistoryReply => {
+                let history_reply_data = match direct_msg.data {
+                    Some(PoolDirectMessageData::HistoryReplyData(history_reply_data)) => {
+                        history_reply_data
+                    }
+                    _ => return,
+                };
+
+                // Only replies to the page last requested from the node are merged
+                let is_requested = {
+                    let mut history_requests = self.history_requests.lock();
+                    match history_requests.get(&src_node_id) {
+                        Some(history_request_data)
+                            if Some(history_request_data)
+                                == history_reply_data.request.as_ref() =>
+                        {
+                            history_requests.remove(&src_node_id);
+                            true
+                        }
+                        _ => false,
+                    }
+                };
+                if !is_requested {
+                    log::warn!(
+                        "handle_direct_message : unsolicited history reply from {}",
+                        src_node_id
+                    );
+                    return;
+                }
+
+                if let Some(history_request_data) =
+                    self.update_history(&src_node_id, history_reply_data)
+                {
+                    self.send_history_request(history_request_data, &src_node_id)
+                        .await;
+                }
+            }
+            PoolDirectMessageType::Ping => {
+                let ping_data = match direct_msg.data {
+                    Some(PoolDirectMessageData::PingData(ping_data)) => ping_data,
+                    _ => return,
+                };
+
+                self.send_direct_message(
+                    PoolDirectMessageType::Pong,
+                    Some(PoolDirectMessageData::PingData(ping_data)),
+                    &src_node_id,
+                )
+                .await;
+            }
+            PoolDirectMessageType::Pong => {
+                let ping_data = match direct_msg.data {
+                    Some(PoolDirectMessageData::PingData(ping_data)) => ping_data,
+                    _ => return,
+                };
+
+                let now = self.pool_state.instant_seed.elapsed().as_millis() as u64;
+                self.pool_conn
+                    .set_rtt_ms(&src_node_id, now.saturating_sub(ping_data.sent));
             }
         }
     }
 
-    pub(super) async fn handle_message(&self, mut msg_pkg_bundle: MessagePackageBundle) {
-        // log::debug!("UNPROCESSED handle_message {:?}", msg_pkg_bundle.msg_pkg);
+    // Ephemeral messages skip the messages db and missed messages, so nodes
+    // that connect later never see them
+    pub(super) async fn handle_ephemeral_message(&self, mut msg_pkg_bundle: MessagePackageBundle) {
+        let ephemeral_msg = msg_pkg_bundle.take_ephemeral_msg(); // should never panic or else logic error
+        let src_node_id = msg_pkg_bundle.src_node_id(); // should never panic or else logic error
 
-        // To keep order
-        if !self.pool_state.is_latest() {
-            return;
+        {
+            let mut received_ephemeral_messages = self.received_ephemeral_messages.lock();
+            if !received_ephemeral_messages.append_message(&ephemeral_msg.ephemeral_id) {
+                return;
+            }
         }
 
+        // Only repeats of the same state are rate limited, changes always go through
+        {
+            let mut ephemeral_received = self.ephemeral_received.lock();
+            let key = (ephemeral_msg.user_id.clone(), ephemeral_msg.r#type);
+            if let Some((received_at, received_data)) = ephemeral_received.get(&key) {
+                if received_data == &ephemeral_msg.data
+                    && received_at.elapsed() < EPHEMERAL_RECEIVE_INTERVAL
+                {
+                    return;
+                }
+            }
+            ephemeral_received.insert(key, (Instant::now(), ephemeral_msg.data.clone()));
+        }
+
+        if ephemeral_msg.user_id != self.pool_state.user.user_id {
+            pool_ephemeral_message_event(
+                &self.pool_state.pool_id,
+                src_node_id,
+                ephemeral_msg.clone(),
+            );
+        }
+
+        msg_pkg_bundle.msg_pkg.ephemeral_msg = Some(ephemeral_msg);
+        self.pool_conn.distribute_message(msg_pkg_bundle).await;
+    }
+
+    pub(super) async fn handle_message(&self, mut msg_pkg_bundle: MessagePackageBundle) {
+        // log::debug!("UNPROCESSED handle_message {:?}", msg_pkg_bundle.msg_pkg);
+
+        // Messages that arrive before the latest reply are inserted by their clock
         if !self.validate_received_messages(&msg_pkg_bundle) {
             return;
         }
@@ -618,6 +1479,8 @@ impl PoolNet {
         let mut msg = msg_pkg_bundle.take_msg(); // should never panic or else logic error
         let src_node_id = msg_pkg_bundle.src_node_id(); // should never panic or else logic error
 
+        self.logical_clock.observe(msg.clock);
+
         log::debug!("handle_message {:?} {:?}", msg_pkg_bundle.msg_pkg, msg);
 
         if has_dest {
@@ -643,7 +1506,7 @@ impl PoolNet {
                         };
 
                         self.file_manager
-                            .retract_file_request(&retract_file_request_data.file_id);
+                            .retract_file_request(&src_node_id, &retract_file_request_data.file_id);
                     }
                     _ => return,
                 }
@@ -719,6 +1582,27 @@ impl PoolNet {
                         self.add_message(msg);
                     }
                 }
+                PoolMessageType::FolderOffer => {
+                    let folder_info = match &msg.data {
+                        Some(PoolMessageData::FolderOfferData(folder_offer_data)) => {
+                            folder_offer_data
+                        }
+                        _ => return,
+                    };
+
+                    let is_original = src_node_id == folder_info.origin_node_id;
+
+                    let file_offers = folder_info
+                        .file_offers
+                        .iter()
+                        .filter_map(|folder_file_info| folder_file_info.file_info.clone())
+                        .collect();
+                    self.pool_state.add_file_offers(&src_node_id, file_offers);
+
+                    if is_original {
+                        self.add_message(msg);
+                    }
+                }
                 PoolMessageType::MediaOffer => {
                     let media_offer_data = match &msg.data {
                         Some(PoolMessageData::MediaOfferData(media_offer_data)) => media_offer_data,
@@ -753,6 +1637,34 @@ impl PoolNet {
                         }
                     }
                 }
+                PoolMessageType::Reaction => {
+                    let _ = match &msg.data {
+                        Some(PoolMessageData::ReactionData(reaction_data)) => reaction_data,
+                        _ => return,
+                    };
+
+                    self.add_reaction(msg.clone());
+                }
+                PoolMessageType::ReadReceipt => {
+                    let read_receipt_data = match &msg.data {
+                        Some(PoolMessageData::ReadReceiptData(read_receipt_data)) => {
+                            read_receipt_data
+                        }
+                        _ => return,
+                    };
+
+                    // Stale receipts aren't forwarded
+                    if !self.add_read_receipt(&msg.user_id, read_receipt_data) {
+                        return;
+                    }
+                }
+                PoolMessageType::Edit | PoolMessageType::Delete => {
+                    if amendment_target_msg_id(&msg).is_none() {
+                        return;
+                    }
+
+                    self.apply_amendment(msg.clone());
+                }
                 PoolMessageType::RetractFileOffer => {
                     let retract_file_offer_data = match &msg.data {
                         Some(PoolMessageData::RetractFileOfferData(retract_file_offer_data)) => {
@@ -764,6 +1676,20 @@ impl PoolNet {
                     self.pool_state
                         .remove_file_offer(&src_node_id, &retract_file_offer_data.file_id);
                 }
+                PoolMessageType::RetractFolderOffer => {
+                    let retract_folder_offer_data = match &msg.data {
+                        Some(PoolMessageData::RetractFolderOfferData(
+                            retract_folder_offer_data,
+                        )) => retract_folder_offer_data,
+                        _ => return,
+                    };
+
+                    remove_pool_folder_offer_event(
+                        &self.pool_state.pool_id,
+                        src_node_id.clone(),
+                        retract_folder_offer_data.folder_id.clone(),
+                    );
+                }
                 _ => return,
             }
         }
@@ -800,10 +1726,15 @@ impl PoolNet {
             msg: None,
             chunk_msg: None,
             direct_msg: None,
+            ephemeral_msg: None,
         }
     }
 
-    pub(super) fn generate_file_offer(path: PathBuf, node_id: String) -> Option<PoolFileInfo> {
+    // Hashes the whole file, so it shouldn't be called on the async runtime
+    pub(super) fn generate_file_offer(
+        path: PathBuf,
+        node_id: String,
+    ) -> Option<(PoolFileInfo, MerkleTree)> {
         if let Ok(metadata) = path.metadata() {
             if !metadata.is_file() {
                 return None;
@@ -820,16 +1751,126 @@ impl PoolNet {
                 None => return None,
             };
 
-            return Some(PoolFileInfo {
-                file_id: nanoid::nanoid!(FILE_ID_LENGTH),
-                file_name,
-                total_size: metadata.len(),
-                origin_node_id: node_id,
-            });
+            let merkle_tree = MerkleTree::from_file(&path, metadata.len())?;
+
+            return Some((
+                PoolFileInfo {
+                    file_id: nanoid::nanoid!(FILE_ID_LENGTH),
+                    file_name,
+                    total_size: metadata.len(),
+                    origin_node_id: node_id,
+                    file_hash: merkle_tree.root_hex()?,
+                },
+                merkle_tree,
+            ));
         }
         return None;
     }
 
+    // Hashes every file in the folder, so it shouldn't be called on the async runtime
+    pub(super) fn generate_folder_offer(
+        path: PathBuf,
+        pool_id: &String,
+        node_id: String,
+    ) -> Option<(
+        PoolFolderInfo,
+        Vec<(PathBuf, PoolFileInfo, Option<MerkleTree>)>,
+    )> {
+        if !path.is_dir() {
+            return None;
+        }
+
+        let folder_name = path.file_name()?.to_str()?.to_string();
+
+        let mut folder_files: Vec<(String, PathBuf)> = Vec::new();
+        if !Self::walk_folder(&path, "", &mut folder_files) || folder_files.is_empty() {
+            return None;
+        }
+        folder_files.sort();
+
+        let mut file_offers = Vec::with_capacity(folder_files.len());
+        let mut files = Vec::with_capacity(folder_files.len());
+        for (relative_path, file_path) in folder_files {
+            // Files that are already offered keep their file id
+            let (file_info, merkle_tree) =
+                match STORE_MANAGER.file_offer_by_path(pool_id, &file_path) {
+                    Some(file_info) => (file_info, None),
+                    None => {
+                        let (file_info, merkle_tree) =
+                            Self::generate_file_offer(file_path.clone(), node_id.clone())?;
+                        (file_info, Some(merkle_tree))
+                    }
+                };
+
+            file_offers.push(PoolFolderFileInfo {
+                relative_path,
+                file_info: Some(file_info.clone()),
+            });
+            files.push((file_path, file_info, merkle_tree));
+        }
+
+        Some((
+            PoolFolderInfo {
+                folder_id: nanoid!(FILE_ID_LENGTH),
+                folder_name,
+                file_offers,
+                origin_node_id: node_id,
+            },
+            files,
+        ))
+    }
+
+    // Returns false if the folder can't be offered as a whole
+    fn walk_folder(
+        path: &PathBuf,
+        relative_path: &str,
+        folder_files: &mut Vec<(String, PathBuf)>,
+    ) -> bool {
+        let entries = match read_dir(path) {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => return false,
+            };
+
+            // Symlinks are skipped so a link back up the tree can't loop forever
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => return false,
+            };
+
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+
+            let entry_relative_path = if relative_path.is_empty() {
+                name
+            } else {
+                format!("{}/{}", relative_path, name)
+            };
+
+            if file_type.is_dir() {
+                if !Self::walk_folder(&entry.path(), &entry_relative_path, folder_files) {
+                    return false;
+                }
+            } else if file_type.is_file() {
+                folder_files.push((entry_relative_path, entry.path()));
+
+                // The whole manifest goes out as one message
+                if folder_files.len() > MAX_FOLDER_OFFER_FILES {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
     fn generate_image_data(path: PathBuf) -> anyhow::Result<PoolImageData> {
         let image = image::open(path)?;
 
@@ -48,6 +48,7 @@ pub(super) struct PoolState {
 
     latest: AtomicBool,
     _is_only_node: AtomicBool,
+    sync_server_connected: AtomicBool, // false while reconnecting, until a node position is in
 
     pub(super) active_nodes: RwLock<HashMap<String, Vec<u32>>>,
     available_files: Mutex<AvailableFiles>,
@@ -71,6 +72,7 @@ impl PoolState {
             close_chan_rx,
             latest: AtomicBool::new(false),
             _is_only_node: AtomicBool::new(false),
+            sync_server_connected: AtomicBool::new(false),
             active_nodes: RwLock::new(HashMap::new()),
             available_files: Mutex::new(AvailableFiles::new()),
         };
@@ -124,6 +126,18 @@ impl PoolState {
         self.close_chan_rx.clone()
     }
 
+    // The sync server places this node again once reconnected,
+    // so nodes are forgotten and the latest messages are requested again
+    pub(super) fn set_sync_server_disconnected(&self) {
+        self.sync_server_connected.store(false, Ordering::SeqCst);
+        self.latest.store(false, Ordering::SeqCst);
+        self.active_nodes.write().clear();
+    }
+
+    pub(super) fn is_sync_server_connected(&self) -> bool {
+        self.sync_server_connected.load(Ordering::SeqCst)
+    }
+
     pub(super) fn _is_neighbouring_node(&self, node_id: &String) -> bool {
         let node_position = self.node_position.load();
         for i in 0..3 {
@@ -159,6 +173,7 @@ impl PoolState {
         }
 
         self.node_position.store(Arc::new(node_position));
+        self.sync_server_connected.store(true, Ordering::SeqCst);
         only_node
     }
 
@@ -206,16 +221,34 @@ impl PoolState {
         }
 
         let mut available_files = self.available_files.lock();
-        if let Some(file_offers) = available_files.file_offers.remove(node_id) {
-            for file_id in file_offers {
-                if let Some(file_seeders) = available_files.file_seeders.get_mut(&file_id) {
-                    file_seeders.seeders.remove(node_id);
+        available_files.remove_node(node_id);
+    }
 
-                    if file_seeders.seeders.is_empty() {
-                        available_files.file_seeders.remove(&file_id);
-                    }
+    // Nodes that left while the sync server was disconnected never got a remove node,
+    // their file offers are dropped once the init nodes are in
+    pub(super) fn remove_inactive_file_seeders(&self) {
+        let active_node_ids: HashSet<String> = self.active_nodes.read().keys().cloned().collect();
+
+        let removed_file_offers = {
+            let mut available_files = self.available_files.lock();
+            let inactive_node_ids: Vec<String> = available_files
+                .file_offers
+                .keys()
+                .filter(|node_id| **node_id != self.node_id && !active_node_ids.contains(*node_id))
+                .cloned()
+                .collect();
+
+            let mut removed_file_offers = Vec::new();
+            for node_id in inactive_node_ids {
+                for file_id in available_files.remove_node(&node_id) {
+                    removed_file_offers.push((node_id.clone(), file_id));
                 }
             }
+            removed_file_offers
+        };
+
+        for (node_id, file_id) in removed_file_offers {
+            remove_pool_file_offer_event(&self.pool_id, node_id, file_id);
         }
     }
 
@@ -373,6 +406,21 @@ impl AvailableFiles {
             .collect()
     }
 
+    // Returns the file_ids the node was offering
+    fn remove_node(&mut self, node_id: &String) -> HashSet<String> {
+        let file_offers = self.file_offers.remove(node_id).unwrap_or_default();
+        for file_id in file_offers.iter() {
+            if let Some(file_seeders) = self.file_seeders.get_mut(file_id) {
+                file_seeders.seeders.remove(node_id);
+
+                if file_seeders.seeders.is_empty() {
+                    self.file_seeders.remove(file_id);
+                }
+            }
+        }
+        file_offers
+    }
+
     fn add_seeder(&mut self, seeder_node_id: &String, file_info: &PoolFileInfo) -> bool {
         if let Some(file_seeders) = self.file_seeders.get_mut(&file_info.file_id) {
             if file_seeders.file_info.file_name != file_info.file_name
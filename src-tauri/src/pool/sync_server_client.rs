@@ -1,17 +1,23 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
 use log::info;
+use rand::Rng;
+use sha2::{Digest, Sha256};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex as AsyncMutex;
-use tokio_tungstenite::tungstenite::{self, Message as WSMessage};
-use tokio_tungstenite::{connect_async as connect_ws_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::Message as WSMessage;
+use tokio_tungstenite::{
+    connect_async_tls_with_config as connect_ws_async, Connector, MaybeTlsStream, WebSocketStream,
+};
 
 use crate::config::{
-    sync_server_connect_endpoint, HEARTBEAT_INTERVAL_SECONDS, HEARTBEAT_TIMEOUT_SECONDS,
+    sync_server_api_get_version_endpoint, sync_server_connect_endpoint, HEARTBEAT_INTERVAL_SECONDS,
+    HEARTBEAT_TIMEOUT_SECONDS, SYNC_SERVER_RECONNECT_MAX_DELAY, SYNC_SERVER_RECONNECT_MIN_DELAY,
+    SYNC_SERVER_VERSION, SYNC_SERVER_VERSION_TIMEOUT,
 };
 use crate::events::{
     add_pool_node_event, add_pool_user_event, init_pool_event, refresh_auth_token_event,
@@ -23,6 +29,7 @@ use crate::sspb::ss_message::{
     RemoveNodeData, RemoveUserData, SdpResponseData, SuccessResponseData,
 };
 use crate::sspb::SsMessage as SSMessage;
+use crate::store::setting_store::SyncServerSettings;
 use crate::STORE_MANAGER;
 use prost::Message as ProstMessage;
 
@@ -36,6 +43,7 @@ pub struct SyncServerClient {
 
     ws_write: AsyncMutex<Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, WSMessage>>>,
     heartbeat_timeout: AtomicBool,
+    reconnect_attempts: AtomicU32, // since the last successful auth
 }
 
 impl SyncServerClient {
@@ -45,6 +53,7 @@ impl SyncServerClient {
             pool_conn,
             ws_write: AsyncMutex::new(None),
             heartbeat_timeout: AtomicBool::new(true),
+            reconnect_attempts: AtomicU32::new(0),
         });
 
         sync_server_client.start_sync_server_client();
@@ -79,6 +88,9 @@ impl SyncServerClient {
         log::info!("userID: {}", self.pool_state.user.user_id);
 
         STORE_MANAGER.update_pool(pool_info.clone());
+        self.pool_conn
+            .set_sync_server_ice_servers(init_pool_data.ice_servers);
+        self.pool_conn.set_trickle_ice(init_pool_data.trickle_ice);
 
         let init_nodes = {
             let mut init_nodes = Vec::with_capacity(init_pool_data.init_nodes.len());
@@ -98,6 +110,8 @@ impl SyncServerClient {
             init_nodes
         };
 
+        self.pool_state.remove_inactive_file_seeders();
+
         init_pool_event(IPCInitPool {
             node_id: self.pool_state.node_id.clone(),
             pool_info,
@@ -150,9 +164,127 @@ impl SyncServerClient {
         remove_pool_user_event(&self.pool_state.pool_id, remove_user_data.user_id);
     }
 
-    async fn handle_ws_http_error(self: &Arc<SyncServerClient>) {
-        info!("WS ERROR");
-        self.close().await;
+    // The pool is kept, nodes are disconnected until the sync server places this node again
+    async fn handle_ws_disconnect(&self) {
+        info!("WS DISCONNECTED");
+        {
+            let mut ws = self.ws_write.lock().await;
+            if let Some(ws) = &mut *ws {
+                let _ = ws.close().await;
+            }
+            *ws = None;
+        }
+        self.pool_state.set_sync_server_disconnected();
+        self.pool_conn.disconnect_all_nodes().await;
+    }
+
+    // Exponential backoff with jitter, so nodes dropped together don't all reconnect together
+    fn reconnect_delay(&self) -> Duration {
+        let attempt = self.reconnect_attempts.fetch_add(1, Ordering::SeqCst);
+        let delay = SYNC_SERVER_RECONNECT_MIN_DELAY
+            .saturating_mul(1 << attempt.min(16))
+            .min(SYNC_SERVER_RECONNECT_MAX_DELAY);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    // The certificate is trusted on top of the system roots
+    fn tls_connector(sync_server: &SyncServerSettings) -> Option<native_tls::TlsConnector> {
+        let mut builder = native_tls::TlsConnector::builder();
+        if !sync_server.certificate_path.is_empty() {
+            builder.add_root_certificate(Self::certificate(sync_server)?);
+        }
+        builder.build().ok()
+    }
+
+    fn certificate(sync_server: &SyncServerSettings) -> Option<native_tls::Certificate> {
+        let certificate = std::fs::read(&sync_server.certificate_path)
+            .ok()
+            .and_then(|pem| native_tls::Certificate::from_pem(&pem).ok());
+        if certificate.is_none() {
+            log::warn!(
+                "certificate : invalid certificate {}",
+                sync_server.certificate_path
+            );
+        }
+        certificate
+    }
+
+    // native-tls can't pin, so once the handshake is done the certificate the sync server
+    // presented has to have the same SHA-256 fingerprint as the configured one
+    fn verify_pinned_certificate(
+        sync_server: &SyncServerSettings,
+        peer_certificate: Option<&[u8]>,
+    ) -> bool {
+        if !sync_server.pin_certificate || sync_server.certificate_path.is_empty() {
+            return true;
+        }
+
+        let pinned_certificate = match Self::certificate(sync_server)
+            .and_then(|certificate| certificate.to_der().ok())
+        {
+            Some(pinned_certificate) => pinned_certificate,
+            None => return false,
+        };
+
+        let pinned = match peer_certificate {
+            Some(peer_certificate) => {
+                Sha256::digest(peer_certificate) == Sha256::digest(&pinned_certificate)
+            }
+            None => false,
+        };
+        if !pinned {
+            log::warn!("verify_pinned_certificate : certificate doesn't match the pinned one");
+        }
+        pinned
+    }
+
+    // Goes through the same TLS settings as the websocket, None unless the sync server
+    // answers with the version this client speaks
+    pub(super) async fn check_version(sync_server: &SyncServerSettings) -> Option<String> {
+        let (domain, is_secure) = sync_server.domain_and_secure();
+        let url = sync_server_api_get_version_endpoint(domain, is_secure);
+
+        let mut client_builder = reqwest::Client::builder()
+            .timeout(SYNC_SERVER_VERSION_TIMEOUT)
+            .tls_info(true);
+        if url.starts_with("https") {
+            client_builder =
+                client_builder.use_preconfigured_tls(Self::tls_connector(sync_server)?);
+        }
+        let client = client_builder.build().ok()?;
+
+        let response = match client.get(url).send().await {
+            Ok(response) => response,
+            Err(err) => {
+                log::warn!("check_version : {}", err);
+                return None;
+            }
+        };
+        if !response.status().is_success() {
+            log::warn!("check_version : {}", response.status());
+            return None;
+        }
+
+        if response.url().scheme() == "https" {
+            let peer_certificate = response
+                .extensions()
+                .get::<reqwest::tls::TlsInfo>()
+                .and_then(|tls_info| tls_info.peer_certificate());
+            if !Self::verify_pinned_certificate(sync_server, peer_certificate) {
+                return None;
+            }
+        }
+
+        let version = response.text().await.ok()?.trim().to_string();
+        if version != SYNC_SERVER_VERSION {
+            log::warn!(
+                "check_version : version {} isn't {}",
+                version,
+                SYNC_SERVER_VERSION
+            );
+            return None;
+        }
+        Some(version)
     }
 
     pub(super) fn start_sync_server_client(self: &Arc<SyncServerClient>) {
@@ -163,21 +295,78 @@ impl SyncServerClient {
     }
 
     async fn sync_server_loop(self: Arc<Self>) {
+        while self.clone().connect_sync_server().await {
+            self.handle_ws_disconnect().await;
+
+            let delay = self.reconnect_delay();
+            info!("WS RECONNECT in {:?}", delay);
+            tokio::select! {
+                _ = self.pool_state.close_signal() => {
+                    return;
+                },
+                _ = tokio::time::sleep(delay) => {},
+            }
+        }
+    }
+
+    // Returns whether to reconnect, auth errors and closes from the sync server close the pool
+    async fn connect_sync_server(self: Arc<Self>) -> bool {
+        let sync_server = STORE_MANAGER.sync_server_settings();
         let device_id = STORE_MANAGER.device_id();
-        let url = url::Url::parse(
-            sync_server_connect_endpoint(self.pool_state.pool_id.as_str(), device_id.clone())
-                .as_str(),
-        )
-        .unwrap();
+        let (domain, is_secure) = sync_server.domain_and_secure();
+        let url = match url::Url::parse(
+            sync_server_connect_endpoint(
+                domain,
+                is_secure,
+                self.pool_state.pool_id.as_str(),
+                device_id.clone(),
+            )
+            .as_str(),
+        ) {
+            Ok(url) => url,
+            Err(_) => {
+                info!("WS ERROR");
+                return !self.pool_state.is_closed();
+            }
+        };
+
+        match SyncServerClient::check_version(&sync_server).await {
+            Some(version) => info!("sync_server_loop : version {}", version),
+            None => {
+                log::warn!("sync_server_loop : VERSION_ERR for {}", url.as_str());
+                return !self.pool_state.is_closed();
+            }
+        }
 
-        let ws_conn = match connect_ws_async(url).await {
+        let connector = match url.scheme() {
+            "wss" => match SyncServerClient::tls_connector(&sync_server) {
+                Some(tls_connector) => Connector::NativeTls(tls_connector),
+                None => return !self.pool_state.is_closed(),
+            },
+            _ => Connector::Plain,
+        };
+
+        let ws_conn = match connect_ws_async(url, None, Some(connector)).await {
             Ok((ws_conn, _)) => ws_conn,
             Err(_) => {
-                self.handle_ws_http_error().await;
-                return;
+                info!("WS ERROR");
+                return !self.pool_state.is_closed();
             }
         };
 
+        // Checked before the auth token is sent
+        if let MaybeTlsStream::NativeTls(tls_stream) = ws_conn.get_ref() {
+            let peer_certificate = tls_stream
+                .get_ref()
+                .peer_certificate()
+                .ok()
+                .flatten()
+                .and_then(|certificate| certificate.to_der().ok());
+            if !Self::verify_pinned_certificate(&sync_server, peer_certificate.as_deref()) {
+                return !self.pool_state.is_closed();
+            }
+        }
+
         info!("WS OPEN");
         let (ws_write, mut ws_read) = ws_conn.split();
         {
@@ -200,20 +389,32 @@ impl SyncServerClient {
                 );
                 self.pool_state.set_auth_error();
                 self.close().await;
-                return;
+                return false;
             }
         }
+        self.reconnect_attempts.store(0, Ordering::SeqCst);
 
-        self.clone().start_heartbeat_interval();
+        let heartbeat = self.heartbeat_loop();
+        tokio::pin!(heartbeat);
 
-        use tungstenite::Error;
         loop {
-            match ws_read.next().await {
+            let ws_msg = tokio::select! {
+                _ = self.pool_state.close_signal() => {
+                    return false;
+                },
+                _ = &mut heartbeat => {
+                    info!("WS HEARTBEAT TIMEOUT");
+                    break;
+                },
+                ws_msg = ws_read.next() => ws_msg,
+            };
+
+            match ws_msg {
                 Some(Ok(WSMessage::Binary(buf))) => {
                     if let Ok(ss_msg) = SSMessage::decode(&*buf) {
                         if ss_msg.op() == SSMessageOp::Close {
                             self.close().await;
-                            continue;
+                            return false;
                         }
 
                         if ss_msg.op() == SSMessageOp::Heartbeat {
@@ -229,16 +430,17 @@ impl SyncServerClient {
                         });
                     }
                 }
-                Some(Err(Error::Http(_) | Error::HttpFormat(_) | Error::Url(_))) => {
-                    self.handle_ws_http_error().await;
+                Some(Err(_)) => {
+                    info!("WS ERROR");
                     break;
                 }
                 _ => {
-                    self.close().await;
                     break;
                 }
             };
         }
+
+        !self.pool_state.is_closed()
     }
 
     async fn handle_ss_message(&self, ss_msg: SSMessage) {
@@ -366,47 +568,49 @@ impl SyncServerClient {
                         self.remove_user(remove_user_data);
                     }
                 }
+                SSMessageOp::IceCandidate => {
+                    if let Some(SSMessageData::IceCandidateData(ice_candidate_data)) = ss_msg.data {
+                        self.pool_conn
+                            .add_ice_candidate(
+                                ice_candidate_data.node_id,
+                                ice_candidate_data.candidate,
+                            )
+                            .await;
+                    }
+                    return; // no response, candidates are sent as they come
+                }
             }
             self.send_ws_message(res_ss_msg).await;
         }
     }
 
-    fn start_heartbeat_interval(self: Arc<Self>) {
+    // Returns once the sync server stops answering heartbeats
+    async fn heartbeat_loop(&self) {
         let heartbeat_msg: SSMessage = SSMessage {
             op: SSMessageOp::Heartbeat.into(),
             key: String::from(""),
             data: None,
         };
         let heartbeat_buf: Vec<u8> = SyncServerClient::encode_ss_message(heartbeat_msg);
-        tokio::spawn(async move {
-            loop {
-                self.heartbeat_timeout.store(true, Ordering::SeqCst);
-
-                // debug!("SEND WS HEARTBEAT");
-                if !self.send_ws_conn(heartbeat_buf.clone()).await {
-                    break;
-                }
+        loop {
+            self.heartbeat_timeout.store(true, Ordering::SeqCst);
 
-                tokio::select! {
-                    _ = self.pool_state.close_signal() => {
-                        break;
-                    },
-                    _ = tokio::time::sleep(Duration::from_secs(HEARTBEAT_TIMEOUT_SECONDS)) => {},
-                }
+            // debug!("SEND WS HEARTBEAT");
+            if !self.send_ws_conn(heartbeat_buf.clone()).await {
+                return;
+            }
 
-                if self.heartbeat_timeout.load(Ordering::SeqCst) {
-                    self.close().await;
-                    break;
-                }
+            tokio::time::sleep(Duration::from_secs(HEARTBEAT_TIMEOUT_SECONDS)).await;
 
-                tokio::select! {
-                    _ = self.pool_state.close_signal() => {
-                        break;
-                    },
-                    _ = tokio::time::sleep(Duration::from_secs(HEARTBEAT_INTERVAL_SECONDS - HEARTBEAT_TIMEOUT_SECONDS,)) => {},
-                }
+            if self.heartbeat_timeout.load(Ordering::SeqCst) {
+                return;
             }
-        });
+
+            tokio::time::sleep(Duration::from_secs(
+                HEARTBEAT_INTERVAL_SECONDS - HEARTBEAT_TIMEOUT_SECONDS,
+            ))
+            .await;
+        }
     }
 
     async fn report_node_loop(&self) {
@@ -422,6 +626,13 @@ impl SyncServerClient {
                         data: Some(SSMessageData::ReportNodeData(report_node_data))
                     }).await;
                 },
+                Ok(ice_candidate_data) = self.pool_conn.ice_candidate_recv.recv_async() => {
+                    self.send_ws_message(SSMessage {
+                        op: SSMessageOp::IceCandidate.into(),
+                        key: String::from(""),
+                        data: Some(SSMessageData::IceCandidateData(ice_candidate_data))
+                    }).await;
+                },
             }
         }
     }
@@ -1,36 +1,37 @@
-use std::{
-    collections::HashMap,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
-    time::Duration,
-};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use flume::{Receiver, Sender};
 use parking_lot::Mutex;
 
 use crate::{
     events::state_update_event,
-    ipc::{IPCFileDownloadProgress, IPCStateUpdate},
+    ipc::{
+        IPCFileDownloadProgress, IPCFileUploadProgress, IPCPoolNodeConnectionStats, IPCStateUpdate,
+    },
     STATE_UPDATER,
 };
 
 const STATE_UPDATER_INTERVAL: Duration = Duration::from_millis(500);
 
 struct State {
-    download_progress: HashMap<String, Arc<AtomicUsize>>, // file_id -> progress
+    download_progress: HashMap<String, Arc<Mutex<IPCFileDownloadProgress>>>, // file_id -> progress
+    upload_progress: HashMap<(String, String), Arc<Mutex<IPCFileUploadProgress>>>, // (file_id, requesting_node_id) -> progress
+    connection_stats: HashMap<String, Vec<IPCPoolNodeConnectionStats>>, // pool_id -> connection_stats
 }
 
 impl State {
     fn new() -> Self {
         State {
             download_progress: HashMap::new(),
+            upload_progress: HashMap::new(),
+            connection_stats: HashMap::new(),
         }
     }
 
     fn check_inactive(&self) -> bool {
         self.download_progress.is_empty()
+            && self.upload_progress.is_empty()
+            && self.connection_stats.is_empty()
     }
 }
 
@@ -81,23 +82,34 @@ impl StateUpdater {
 
         let mut file_downloads_progress = Vec::with_capacity(state.download_progress.len());
 
-        for (file_id, progress) in state.download_progress.iter() {
-            file_downloads_progress.push(IPCFileDownloadProgress {
-                file_id: file_id.clone(),
-                progress: progress.load(Ordering::Relaxed),
-            });
+        for progress in state.download_progress.values() {
+            file_downloads_progress.push(progress.lock().clone());
+        }
+
+        let mut file_uploads_progress = Vec::with_capacity(state.upload_progress.len());
+
+        for progress in state.upload_progress.values() {
+            file_uploads_progress.push(progress.lock().clone());
         }
 
+        let connection_stats = state.connection_stats.values().flatten().cloned().collect();
+
         // log::debug!("trigger_update_state {:?}", file_downloads_progress.len());
 
         state_update_event(IPCStateUpdate {
             file_downloads_progress,
+            file_uploads_progress,
+            connection_stats,
         });
 
         true
     }
 
-    pub fn register_download_progress(&self, file_id: String, progress: Arc<AtomicUsize>) {
+    pub fn register_download_progress(
+        &self,
+        file_id: String,
+        progress: Arc<Mutex<IPCFileDownloadProgress>>,
+    ) {
         let mut state = self.state.lock();
         state.download_progress.insert(file_id, progress);
         self.wake_updater();
@@ -107,4 +119,40 @@ impl StateUpdater {
         let mut state = self.state.lock();
         state.download_progress.remove(file_id);
     }
+
+    pub fn register_upload_progress(
+        &self,
+        file_id: String,
+        requesting_node_id: String,
+        progress: Arc<Mutex<IPCFileUploadProgress>>,
+    ) {
+        let mut state = self.state.lock();
+        state
+            .upload_progress
+            .insert((file_id, requesting_node_id), progress);
+        self.wake_updater();
+    }
+
+    pub fn unregister_upload_progress(&self, file_id: &String, requesting_node_id: &String) {
+        let mut state = self.state.lock();
+        state
+            .upload_progress
+            .remove(&(file_id.clone(), requesting_node_id.clone()));
+    }
+
+    // Replaced by the pool's connection stats loop every CONNECTION_STATS_INTERVAL
+    pub fn set_connection_stats(
+        &self,
+        pool_id: String,
+        connection_stats: Vec<IPCPoolNodeConnectionStats>,
+    ) {
+        let mut state = self.state.lock();
+        state.connection_stats.insert(pool_id, connection_stats);
+        self.wake_updater();
+    }
+
+    pub fn remove_connection_stats(&self, pool_id: &String) {
+        let mut state = self.state.lock();
+        state.connection_stats.remove(pool_id);
+    }
 }
@@ -1,8 +1,10 @@
 use std::{
+    cmp::min,
     collections::{HashMap, VecDeque},
     fs::{create_dir, read_dir, remove_file},
-    path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH}, io::Read,
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use serde::{Deserialize, Serialize};
@@ -13,8 +15,11 @@ use tauri::http::{
 };
 
 use crate::{
-    config::{FILE_ID_LENGTH, MAX_TEMP_FILES_SIZE_PER_POOL, MAX_TEMP_FILE_SIZE},
-    poolpb::PoolFileInfo, STORE_MANAGER, POOL_MANAGER,
+    config::{
+        CHUNK_SIZE, FILE_ID_LENGTH, MAX_MEDIA_RANGE_SIZE, MAX_TEMP_FILES_SIZE_PER_POOL,
+        MAX_TEMP_FILE_SIZE,
+    },
+    poolpb::{PoolChunkRange, PoolFileInfo, PoolFolderInfo}, STORE_MANAGER, POOL_MANAGER,
 };
 
 use super::store_manager::StoreManager;
@@ -47,10 +52,26 @@ struct FilePathInfo {
     normalized_path: String,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedFileDownload {
+    pub file_info: PoolFileInfo,
+    pub path: PathBuf,
+    pub chunks_downloaded_ranges: Vec<PoolChunkRange>,
+    #[serde(default)]
+    pub paused: bool,
+}
+
 #[derive(Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileStore {
     file_offers: HashMap<String, HashMap<String, PoolFileInfo>>, // pool_id -> normalized_path.to_str() -> file_info
+    #[serde(default)]
+    folder_offers: HashMap<String, HashMap<String, PoolFolderInfo>>, // pool_id -> normalized_path.to_str() -> folder_info
+    #[serde(default)]
+    folder_file_ids: HashMap<String, Vec<String>>, // folder_id -> file ids first offered by the folder offer
+    #[serde(default)]
+    file_downloads: HashMap<String, HashMap<String, SavedFileDownload>>, // pool_id -> file_id -> saved_file_download
 
     #[serde(skip)]
     file_paths: HashMap<String, FilePathInfo>, // file_id -> file_path_info
@@ -126,6 +147,110 @@ impl StoreManager {
         false
     }
 
+    pub fn file_offer_by_path(&self, pool_id: &String, path: &PathBuf) -> Option<PoolFileInfo> {
+        let normalized_path = FileStore::normalize_path(path)?;
+        let file_store = self.file_store.lock();
+        file_store
+            .file_offers
+            .get(pool_id)?
+            .get(normalized_path.to_str()?)
+            .cloned()
+    }
+
+    pub fn add_folder_offer(
+        &self,
+        pool_id: &String,
+        folder_info: PoolFolderInfo,
+        path: PathBuf,
+    ) -> bool {
+        if let Some(normalized_path) = FileStore::normalize_path(path) {
+            if let Some(normalized_path) = normalized_path.to_str() {
+                let mut file_store = self.file_store.lock();
+
+                let pool_folder_offers =
+                    file_store.folder_offers.entry(pool_id.clone()).or_default();
+                if pool_folder_offers.contains_key(normalized_path) {
+                    return false;
+                }
+                pool_folder_offers.insert(normalized_path.to_string(), folder_info);
+
+                file_store.update();
+                return true;
+            }
+        }
+        false
+    }
+
+    // Files that were already offered on their own aren't the folder offer's to retract
+    pub fn set_folder_file_ids(&self, folder_id: &String, file_ids: Vec<String>) {
+        let mut file_store = self.file_store.lock();
+        file_store
+            .folder_file_ids
+            .insert(folder_id.clone(), file_ids);
+        file_store.update();
+    }
+
+    // Returns the folder info and the file ids first offered by the folder offer
+    pub fn remove_folder_offer(
+        &self,
+        pool_id: &String,
+        folder_id: &String,
+    ) -> Option<(PoolFolderInfo, Vec<String>)> {
+        let mut file_store = self.file_store.lock();
+        let pool_folder_offers = file_store.folder_offers.get_mut(pool_id)?;
+        let normalized_path = pool_folder_offers
+            .iter()
+            .find(|(_, folder_info)| &folder_info.folder_id == folder_id)
+            .map(|(normalized_path, _)| normalized_path.clone())?;
+
+        let folder_info = pool_folder_offers.remove(&normalized_path)?;
+        let file_ids = file_store
+            .folder_file_ids
+            .remove(folder_id)
+            .unwrap_or_default();
+        file_store.update();
+        Some((folder_info, file_ids))
+    }
+
+    pub fn file_downloads(&self, pool_id: &String) -> Vec<SavedFileDownload> {
+        let file_store = self.file_store.lock();
+        if let Some(pool_downloads) = file_store.file_downloads.get(pool_id) {
+            return pool_downloads.values().cloned().collect();
+        }
+        Vec::new()
+    }
+
+    pub fn file_download(&self, pool_id: &String, file_id: &String) -> Option<SavedFileDownload> {
+        let file_store = self.file_store.lock();
+        file_store
+            .file_downloads
+            .get(pool_id)?
+            .get(file_id)
+            .cloned()
+    }
+
+    pub fn save_file_download(&self, pool_id: &String, file_download: SavedFileDownload) {
+        let mut file_store = self.file_store.lock();
+        file_store
+            .file_downloads
+            .entry(pool_id.clone())
+            .or_default()
+            .insert(file_download.file_info.file_id.clone(), file_download);
+        file_store.update();
+    }
+
+    pub fn remove_file_download(&self, pool_id: &String, file_id: &String) {
+        let mut file_store = self.file_store.lock();
+        if let Some(pool_downloads) = file_store.file_downloads.get_mut(pool_id) {
+            if pool_downloads.remove(file_id).is_some() {
+                if pool_downloads.is_empty() {
+                    file_store.file_downloads.remove(pool_id);
+                }
+                file_store.update();
+            }
+        }
+    }
+
     pub fn file_path(&self, file_id: &String) -> Result<(PathBuf, bool), FilePathError> {
         let file_store = self.file_store.lock();
         if let Some(file_path) = file_store.file_paths.get(file_id) {
@@ -189,6 +314,8 @@ impl StoreManager {
 impl FileStore {
     pub(super) fn init(&mut self) {
         self.validate_file_offers();
+        self.validate_folder_offers();
+        self.validate_file_downloads();
         let _ = self.init_temp_file_queues();
     }
 
@@ -290,6 +417,31 @@ impl FileStore {
         self.file_paths = file_paths;
     }
 
+    // Files inside are validated with the rest of the file offers
+    fn validate_folder_offers(&mut self) {
+        for pool_folder_offers in self.folder_offers.values_mut() {
+            pool_folder_offers.retain(|normalized_path, _| PathBuf::from(normalized_path).is_dir());
+        }
+
+        let folder_offers = &self.folder_offers;
+        self.folder_file_ids.retain(|folder_id, _| {
+            folder_offers.values().any(|pool_folder_offers| {
+                pool_folder_offers
+                    .values()
+                    .any(|folder_info| &folder_info.folder_id == folder_id)
+            })
+        });
+    }
+
+    // Partial files that were moved or deleted can't be resumed
+    fn validate_file_downloads(&mut self) {
+        for pool_downloads in self.file_downloads.values_mut() {
+            pool_downloads.retain(|_, file_download| file_download.path.is_file());
+        }
+        self.file_downloads
+            .retain(|_, pool_downloads| !pool_downloads.is_empty());
+    }
+
     pub fn register_media_protocol(_app: &AppHandle, req: &HttpRequest) -> Result<HttpResponse, Box<dyn std::error::Error>> {
         let response = ResponseBuilder::new();
         let path = req.uri().strip_prefix("media://localhost/").unwrap();
@@ -297,36 +449,133 @@ impl FileStore {
         let pool_id = path[0].to_string();
         let file_id = path[1].to_string();
 
-        let file_path = match STORE_MANAGER.file_path(&file_id) {
-            Ok((file_path, _)) => file_path,
-            Err(err) => {
-                if err == FilePathError::NotExist {
-                    let pool_id = pool_id.clone();
-                    let file_id = file_id.clone();
-                    tokio::spawn(async move {
-                        POOL_MANAGER.retract_file_offer(&pool_id, file_id).await;
-                    });
-                }
+        // Downloads still in progress are streamed from what's been downloaded so far.
+        // If the pools are busy it isn't known whether it's one, so the player retries
+        let file_download = match POOL_MANAGER.file_download(&pool_id, &file_id) {
+            Ok(file_download) => file_download,
+            Err(_) => {
+                return response
+                    .mimetype("text/plain")
+                    .status(503)
+                    .header("Retry-After", "1")
+                    .body(Vec::new())
+            }
+        };
 
-                match Self::temp_file_path(pool_id, file_id) {
-                    Some(path) => path,
-                    _ => return response.mimetype("text/plain").status(404).body(Vec::new()),
-                }
+        let file_path = match &file_download {
+            Some(file_download) => file_download.path.clone(),
+            None => match STORE_MANAGER.file_path(&file_id) {
+                Ok((file_path, _)) => file_path,
+                Err(err) => {
+                    if err == FilePathError::NotExist {
+                        let pool_id = pool_id.clone();
+                        let file_id = file_id.clone();
+                        tokio::spawn(async move {
+                            POOL_MANAGER.retract_file_offer(&pool_id, file_id).await;
+                        });
+                    }
+
+                    match Self::temp_file_path(pool_id.clone(), file_id.clone()) {
+                        Some(path) => path,
+                        _ => return response.mimetype("text/plain").status(404).body(Vec::new()),
+                    }
+                },
             },
         };
 
-        let mut file = match std::fs::File::open(file_path) {
+        let mut file = match std::fs::File::open(&file_path) {
             Ok(file) => file,
             Err(_) => return response.mimetype("text/plain").status(404).body(Vec::new()),
         };
 
+        let total_size = match &file_download {
+            Some(file_download) => file_download.file_info.total_size,
+            None => match file.metadata() {
+                Ok(metadata) => metadata.len(),
+                Err(_) => return response.mimetype("text/plain").status(404).body(Vec::new()),
+            },
+        };
+
+        let mimetype = media_mimetype(&mut file, &file_path);
+        let response = response
+            .mimetype(&mimetype)
+            .header("Accept-Ranges", "bytes");
+
+        let range = match req
+            .headers()
+            .get("range")
+            .and_then(|range| range.to_str().ok())
+        {
+            Some(range) => match parse_media_range(range, total_size) {
+                Some(range) => range,
+                None => {
+                    return response
+                        .status(416)
+                        .header("Content-Range", format!("bytes */{}", total_size))
+                        .body(Vec::new())
+                }
+            },
+            None if file_download.is_some() && total_size > 0 => (0, total_size - 1),
+            None => {
+                let mut buf = Vec::new();
+                match file.read_to_end(&mut buf) {
+                    Ok(_) => {},
+                    _ => return response.mimetype("text/plain").status(404).body(Vec::new()),
+                }
+                return response.body(buf);
+            },
+        };
+
+        // Responses are capped, players request the rest
+        let (start, mut end) = range;
+        end = min(end, start + MAX_MEDIA_RANGE_SIZE - 1);
+
+        if let Some(file_download) = file_download {
+            let chunk_size = CHUNK_SIZE as u64;
+            POOL_MANAGER.prioritize_file_chunks(
+                &pool_id,
+                &file_id,
+                PoolChunkRange {
+                    start: start / chunk_size,
+                    end: end / chunk_size,
+                },
+            );
+
+            // Not downloaded yet, the player retries after the prioritized chunks had time to arrive
+            match downloaded_media_range_end(
+                &file_download.chunks_downloaded_ranges,
+                start / chunk_size,
+            ) {
+                Some(downloaded_end) => end = min(end, downloaded_end),
+                None => {
+                    return response
+                        .status(503)
+                        .header("Retry-After", "1")
+                        .body(Vec::new())
+                }
+            }
+        }
+
         let mut buf = Vec::new();
-        match file.read_to_end(&mut buf) {
-            Ok(_) => {},
-            _ => return response.mimetype("text/plain").status(404).body(Vec::new()),
+        let read_ok = match file.seek(SeekFrom::Start(start)) {
+            Ok(_) => file.take(end - start + 1).read_to_end(&mut buf).is_ok(),
+            Err(_) => false,
+        };
+        if !read_ok || buf.is_empty() {
+            return response.mimetype("text/plain").status(404).body(Vec::new());
         }
 
-        response.body(buf)
+        // The file can be shorter than expected if its download was cancelled
+        let end = start + buf.len() as u64 - 1;
+
+        response
+            .status(206)
+            .header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, total_size),
+            )
+            .header("Content-Length", buf.len().to_string())
+            .body(buf)
     }
 
     pub fn normalize_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
@@ -377,6 +626,36 @@ impl FileStore {
         }
     }
 
+    // Directory of a folder file inside the download's folder, None if the relative path
+    // from the manifest would end up outside of it
+    pub fn folder_file_dir_path(
+        folder_path: &PathBuf,
+        relative_path: &String,
+        file_name: &String,
+    ) -> Option<PathBuf> {
+        let mut segments: Vec<&str> = relative_path.split('/').collect();
+        if segments.pop()? != file_name || !Self::is_valid_path_segment(file_name) {
+            return None;
+        }
+
+        let mut path = folder_path.clone();
+        for segment in segments {
+            if !Self::is_valid_path_segment(segment) {
+                return None;
+            }
+            path.push(segment);
+        }
+        Some(path)
+    }
+
+    pub fn is_valid_path_segment(segment: &str) -> bool {
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(component)), None) => component == segment,
+            _ => false,
+        }
+    }
+
     pub fn create_valid_file_path(path: &mut PathBuf, file_name: &String) {
         path.push(file_name.clone());
 
@@ -390,3 +669,117 @@ impl FileStore {
         }
     }
 }
+
+// Only the first range is served, players don't ask for more than one
+fn parse_media_range(range: &str, total_size: u64) -> Option<(u64, u64)> {
+    let range = range.strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (start, end) = range.split_once('-')?;
+    let last = total_size.checked_sub(1)?;
+
+    let (start, end) = if start.is_empty() {
+        let suffix_len: u64 = end.parse().ok()?;
+        if suffix_len == 0 {
+            return None;
+        }
+        (total_size.saturating_sub(suffix_len), last)
+    } else if end.is_empty() {
+        (start.parse().ok()?, last)
+    } else {
+        (start.parse().ok()?, min(end.parse().ok()?, last))
+    };
+
+    if start > end {
+        return None;
+    }
+    Some((start, end))
+}
+
+// Last byte downloaded contiguously from start_chunk, None if start_chunk isn't downloaded yet
+fn downloaded_media_range_end(
+    chunks_downloaded_ranges: &Vec<PoolChunkRange>,
+    start_chunk: u64,
+) -> Option<u64> {
+    chunks_downloaded_ranges
+        .iter()
+        .find(|chunk_range| chunk_range.start <= start_chunk && start_chunk <= chunk_range.end)
+        .map(|chunk_range| (chunk_range.end + 1) * CHUNK_SIZE as u64 - 1)
+}
+
+// Sniffed from the content first, the extension is a fallback for files still downloading
+fn media_mimetype(file: &mut std::fs::File, file_path: &PathBuf) -> String {
+    let mut head = Vec::new();
+    let _ = file.by_ref().take(8 * 1024).read_to_end(&mut head);
+    let _ = file.seek(SeekFrom::Start(0));
+
+    if let Some(kind) = infer::get(&head) {
+        return kind.mime_type().to_string();
+    }
+
+    let extension = file_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "ogv" => "video/ogg",
+        "ogg" => "audio/ogg",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "m4a" => "audio/mp4",
+        "flac" => "audio/flac",
+        "aac" => "audio/aac",
+        "opus" => "audio/opus",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_media_range;
+
+    #[test]
+    fn parse_media_range_suffix() {
+        assert_eq!(parse_media_range("bytes=-500", 1000), Some((500, 999)));
+        assert_eq!(parse_media_range("bytes=-5000", 1000), Some((0, 999)));
+        assert_eq!(parse_media_range("bytes=-0", 1000), None);
+    }
+
+    #[test]
+    fn parse_media_range_open_ended() {
+        assert_eq!(parse_media_range("bytes=100-", 1000), Some((100, 999)));
+        assert_eq!(parse_media_range("bytes=0-", 1000), Some((0, 999)));
+        assert_eq!(parse_media_range("bytes=999-", 1000), Some((999, 999)));
+    }
+
+    #[test]
+    fn parse_media_range_bounded() {
+        assert_eq!(parse_media_range("bytes=100-199", 1000), Some((100, 199)));
+        assert_eq!(
+            parse_media_range("bytes=100-199, 300-399", 1000),
+            Some((100, 199))
+        );
+        assert_eq!(parse_media_range("bytes=900-5000", 1000), Some((900, 999)));
+    }
+
+    #[test]
+    fn parse_media_range_out_of_range() {
+        assert_eq!(parse_media_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_media_range("bytes=2000-3000", 1000), None);
+        assert_eq!(parse_media_range("bytes=500-100", 1000), None);
+        assert_eq!(parse_media_range("bytes=0-", 0), None);
+        assert_eq!(parse_media_range("items=0-100", 1000), None);
+        assert_eq!(parse_media_range("bytes=a-b", 1000), None);
+    }
+}
@@ -1,9 +1,55 @@
 use serde::{Deserialize, Serialize};
 
-use crate::config::{LATEST_MESSAGES_SIZE, MESSAGE_VIEWPORT_SIZE, MIN_MESSAGE_HIEGHT};
+use crate::config::{
+    DEFAULT_MAX_CONCURRENT_DOWNLOADS, LATEST_MESSAGES_SIZE, MESSAGE_VIEWPORT_SIZE,
+    MIN_MESSAGE_HIEGHT, SYNC_SERVER_DOMAIN, SYNC_SERVER_IS_SECURE,
+};
 
 use super::store_manager::StoreManager;
 
+// Bytes per second, 0 is unlimited
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthLimits {
+    pub upload_limit: u64,
+    pub pool_upload_limit: u64,
+    pub download_limit: u64, // soft, chunks already requested still come in at full speed
+}
+
+// Same as the sync server's, username and credential are for TURN
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub credential: String,
+}
+
+// An empty domain is the built in sync server
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncServerSettings {
+    pub domain: String, // host[:port]
+    pub secure: bool,   // wss and https
+    #[serde(default)]
+    pub certificate_path: String, // PEM, a custom CA or the server's own certificate
+    #[serde(default)]
+    pub pin_certificate: bool, // the sync server has to present this exact certificate
+}
+
+impl SyncServerSettings {
+    // The compiled in domain is used until one is set
+    pub fn domain_and_secure(&self) -> (&str, bool) {
+        if self.domain.is_empty() {
+            (SYNC_SERVER_DOMAIN, SYNC_SERVER_IS_SECURE)
+        } else {
+            (self.domain.as_str(), self.secure)
+        }
+    }
+}
+
 #[derive(Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SettingStore {
@@ -11,6 +57,14 @@ pub struct SettingStore {
     monitor_height: u32,
     #[serde(skip)]
     max_messages_render: usize,
+    #[serde(default)]
+    bandwidth_limits: BandwidthLimits,
+    #[serde(default)]
+    max_concurrent_downloads: usize, // 0 is the default
+    #[serde(default)]
+    ice_servers: Vec<IceServer>, // empty is the default STUN server
+    #[serde(default)]
+    sync_server: SyncServerSettings,
 }
 
 impl StoreManager {
@@ -32,4 +86,51 @@ impl StoreManager {
         let setting_store = self.setting_store.lock();
         setting_store.max_messages_render
     }
+
+    pub fn bandwidth_limits(&self) -> BandwidthLimits {
+        let setting_store = self.setting_store.lock();
+        setting_store.bandwidth_limits
+    }
+
+    pub fn set_bandwidth_limits(&self, bandwidth_limits: BandwidthLimits) {
+        let mut setting_store = self.setting_store.lock();
+        setting_store.bandwidth_limits = bandwidth_limits;
+        setting_store.update();
+    }
+
+    pub fn max_concurrent_downloads(&self) -> usize {
+        let setting_store = self.setting_store.lock();
+        match setting_store.max_concurrent_downloads {
+            0 => DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+            max_concurrent_downloads => max_concurrent_downloads,
+        }
+    }
+
+    pub fn set_max_concurrent_downloads(&self, max_concurrent_downloads: usize) {
+        let mut setting_store = self.setting_store.lock();
+        setting_store.max_concurrent_downloads = max_concurrent_downloads;
+        setting_store.update();
+    }
+
+    pub fn ice_servers(&self) -> Vec<IceServer> {
+        let setting_store = self.setting_store.lock();
+        setting_store.ice_servers.clone()
+    }
+
+    pub fn set_ice_servers(&self, ice_servers: Vec<IceServer>) {
+        let mut setting_store = self.setting_store.lock();
+        setting_store.ice_servers = ice_servers;
+        setting_store.update();
+    }
+
+    pub fn sync_server_settings(&self) -> SyncServerSettings {
+        let setting_store = self.setting_store.lock();
+        setting_store.sync_server.clone()
+    }
+
+    pub fn set_sync_server_settings(&self, sync_server_settings: SyncServerSettings) {
+        let mut setting_store = self.setting_store.lock();
+        setting_store.sync_server = sync_server_settings;
+        setting_store.update();
+    }
 }
@@ -10,9 +10,18 @@ use super::store_manager::StoreManager;
 pub struct PoolData {
     pub pool_info: PoolInfo,
     pub last_modified: u64,
+    // user_id -> device_id -> last read receipt
+    #[serde(default)]
+    pub device_read_receipts: HashMap<String, HashMap<String, ReadReceipt>>,
     // Additional data such as file offers, etc.
 }
 
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ReadReceipt {
+    pub msg_id: String,
+    pub msg_order: u64,
+}
+
 pub struct BasicUserInfo {
     pub user_id: String,
     pub display_name: String,
@@ -52,9 +61,14 @@ impl StoreManager {
 
     pub fn update_pool(&self, pool_info: PoolInfo) {
         let mut user_store = self.user_store.lock();
+        let device_read_receipts = match user_store.pools.get(&pool_info.pool_id) {
+            Some(pool) => pool.device_read_receipts.clone(),
+            None => HashMap::new(),
+        };
         user_store.pools.insert(pool_info.pool_id.clone(), PoolData {
             pool_info,
             last_modified: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            device_read_receipts,
         });
         user_store.update();
     }
@@ -114,6 +128,68 @@ impl StoreManager {
         }
     }
 
+    // Returns false if the user's device already has a receipt for the same or a later message
+    pub fn update_read_receipt(
+        &self,
+        pool_id: &String,
+        user_id: &String,
+        device_id: &String,
+        read_receipt: ReadReceipt,
+    ) -> bool {
+        let mut user_store = self.user_store.lock();
+        if let Some(pool) = user_store.pools.get_mut(pool_id) {
+            let read_receipts = pool.device_read_receipts.entry(user_id.clone()).or_default();
+            if let Some(last_read_receipt) = read_receipts.get(device_id) {
+                if last_read_receipt.msg_order >= read_receipt.msg_order {
+                    return false;
+                }
+            }
+
+            read_receipts.insert(device_id.clone(), read_receipt);
+            user_store.update();
+            return true;
+        }
+        false
+    }
+
+    pub fn read_receipt(
+        &self,
+        pool_id: &String,
+        user_id: &String,
+        device_id: &String,
+    ) -> Option<ReadReceipt> {
+        let user_store = self.user_store.lock();
+        user_store
+            .pools
+            .get(pool_id)?
+            .device_read_receipts
+            .get(user_id)?
+            .get(device_id)
+            .cloned()
+    }
+
+    // Order of the furthest read message on any of the user's devices
+    pub fn last_read_order(&self, pool_id: &String, user_id: &String) -> Option<u64> {
+        let user_store = self.user_store.lock();
+        user_store
+            .pools
+            .get(pool_id)?
+            .device_read_receipts
+            .get(user_id)?
+            .values()
+            .map(|read_receipt| read_receipt.msg_order)
+            .max()
+    }
+
+    // user_id -> device_id -> last read receipt
+    pub fn read_receipts(&self, pool_id: &String) -> HashMap<String, HashMap<String, ReadReceipt>> {
+        let user_store = self.user_store.lock();
+        match user_store.pools.get(pool_id) {
+            Some(pool) => pool.device_read_receipts.clone(),
+            None => HashMap::new(),
+        }
+    }
+
     pub fn basic_user_info(&self) -> BasicUserInfo {
         let user_store = self.user_store.lock();
         BasicUserInfo {
@@ -138,7 +214,7 @@ impl StoreManager {
         user_store.user_info.display_name.clone()
     }
 
-    pub fn _user_id(&self) -> String {
+    pub fn user_id(&self) -> String {
         let user_store = self.user_store.lock();
         user_store.user_info.user_id.clone()
     }